@@ -11,6 +11,8 @@ pub enum ArgsResult {
     Exit,
     Proceed,
     ProceedWithScriptPath(PathBuf),
+    ProceedWithSession(PathBuf),
+    Server(Option<String>),
     ProceedWithDefaultConfig,
 }
 
@@ -22,6 +24,8 @@ pub fn handle_args(args: &[String], options: &mut Options) -> ArgsResult {
         version: {}\n
         config file is in {}\n
         irust {{path_to_rust_file}} will start IRust with the file loaded in the repl
+        --session {{path_to_session_file}} => restore a session saved with `:save`
+        --server [address] => start a JSON-RPC server on stdin/stdout (or on the tcp address) instead of the repl
         --help => shows this message
         --reset-config => reset IRust configuration to default
         --default-config => uses the default configuration for this run (it will not be saved)",
@@ -43,6 +47,15 @@ pub fn handle_args(args: &[String], options: &mut Options) -> ArgsResult {
             ArgsResult::Proceed
         }
         "--default-config" => ArgsResult::ProceedWithDefaultConfig,
+        "--session" => {
+            if let Some(path) = args.get(1) {
+                ArgsResult::ProceedWithSession(PathBuf::from(path))
+            } else {
+                eprintln!("Missing session file path");
+                ArgsResult::Proceed
+            }
+        }
+        "--server" => ArgsResult::Server(args.get(1).cloned()),
         maybe_path => {
             let path = Path::new(&maybe_path);
             if path.exists() {
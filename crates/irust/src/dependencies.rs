@@ -39,17 +39,14 @@ pub fn check_required_deps() -> bool {
 
 pub fn warn_about_opt_deps(options: &mut Options) {
     //TODO: add rust-analyzer
-    let opt_deps: [Dep; 3] = [
+    let opt_deps: [Dep; 2] = [
         Dep::new("rustfmt", "rustfmt", "beautifying repl code", &|| {
             if !dep_installed("rustup") {
                 println!(
                     "{}",
                     "rustup is not installed.\nrustup is required to install rustfmt".red()
                 );
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "rustup is not installed",
-                ));
+                return Err(io::Error::other("rustup is not installed"));
             }
             let cmd = ["rustup", "component", "add", "rustfmt"];
             println!("{}", format!("Running: {cmd:?}").magenta());
@@ -58,19 +55,6 @@ pub fn warn_about_opt_deps(options: &mut Options) {
                 .args(&cmd[1..])
                 .status()?])
         }),
-        Dep::new(
-            "cargo-show-asm",
-            "cargo-asm",
-            "viewing functions assembly",
-            &|| {
-                let cmd = ["cargo", "install", "cargo-show-asm"];
-                println!("{}", format!("Running: {cmd:?}").magenta());
-
-                Ok(vec![process::Command::new(cmd[0])
-                    .args(&cmd[1..])
-                    .status()?])
-            },
-        ),
         Dep::new(
             "cargo-expand",
             "cargo-expand",
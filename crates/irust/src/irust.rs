@@ -1,30 +1,35 @@
 mod engine;
-use std::io::Read;
-use std::net::{SocketAddrV4, TcpListener};
-use std::sync::mpsc;
-use std::time::Duration;
-
 use engine::Engine;
 mod art;
+mod bench;
+mod emit;
 mod format;
 mod help;
 pub mod highlight;
 mod history;
+mod inspect;
 pub mod options;
 mod parser;
 mod ra;
 mod script;
+pub mod server;
+mod session;
+mod streaming;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
 use highlight::theme::Theme;
 use history::History;
 use irust_api::{Command, GlobalVariables};
-use irust_repl::Repl;
+use irust_repl::{diagnostics::Diagnostic, jobs::Job, CompileMode, Repl, ToolChain};
 use options::Options;
 use printer::{buffer::Buffer, printer::Printer};
 use ra::Completer;
 use script::Script;
+use std::collections::{BTreeMap, HashMap};
+
+pub use format::format_build_err;
+/// How long the input should stay unchanged before showing its diagnostics
+const LIVE_DIAGNOSTICS_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
 
-pub use format::format_err;
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub struct IRust {
@@ -39,20 +44,39 @@ pub struct IRust {
     history: History,
     completer: Option<Completer>,
     script_mg: Option<Box<dyn Script>>,
+    /// The last input that failed to compile with its diagnostics, used by `:fix`
+    last_failure: Option<(String, Vec<Diagnostic>)>,
+    /// The last `:bench` estimate of each expression
+    bench_history: HashMap<String, bench::Estimate>,
+    /// Background evaluations (`:bg`) by id
+    jobs: BTreeMap<usize, Job>,
+    next_job_id: usize,
 }
 
 impl IRust {
-    pub fn new(options: Options) -> Self {
+    pub fn new(mut options: Options) -> Self {
         // Make sure to call Repl::new at the start so it can set `irust-repl` dir, which might be used by others (ScriptManager)
-        let repl = Repl::new(
-            options.toolchain,
-            options.executor,
-            options.main_result,
-            options.edition,
-            // prelude dir
-            (|| Some(crate::utils::irust_dirs::data_dir()?.join("irust")))(),
-        )
-        .expect("Could not create repl");
+        let toolchain = Self::session_toolchain(&options).unwrap_or_else(|e| {
+            eprintln!("{e}\nUsing the default toolchain instead");
+            ToolChain::Default
+        });
+        let mut repl = Self::new_repl(&options, toolchain).expect("Could not create repl");
+        if let Err(e) = repl.set_persistent(options.persistent) {
+            eprintln!("Could not activate persistent mode: {e}");
+            options.persistent = false;
+        }
+        if options.persistent && options.streaming {
+            eprintln!("The streaming mode can't be used in persistent mode, disabling it");
+            options.streaming = false;
+        }
+        if let Err(e) = repl.set_sandbox(options.sandbox) {
+            eprintln!("Could not activate the sandbox: {e}");
+            options.sandbox = false;
+        }
+        if let Err(e) = options.compile_mode.check_available(&repl.toolchain()) {
+            eprintln!("{e}\nUsing debug mode instead");
+            options.compile_mode = CompileMode::Debug;
+        }
 
         let mut global_variables = GlobalVariables::new();
 
@@ -100,9 +124,48 @@ impl IRust {
             history,
             completer,
             script_mg,
+            last_failure: None,
+            bench_history: HashMap::new(),
+            jobs: BTreeMap::new(),
+            next_job_id: 1,
         }
     }
 
+    /// `toolchain` is the [`Self::session_toolchain`] of the options
+    fn new_repl(options: &Options, toolchain: ToolChain) -> Result<Repl> {
+        let mut repl = Repl::new(
+            toolchain,
+            options.executor,
+            options.main_result,
+            options.edition,
+            // prelude dir
+            (|| Some(crate::utils::irust_dirs::data_dir()?.join("irust")))(),
+        )?;
+        repl.set_profile(options.profile.clone())?;
+        repl.set_limits(options.limits.clone())?;
+        Ok(repl)
+    }
+
+    /// The configured toolchain, or with the default one the toolchain of the `rust-toolchain.toml` of the current
+    /// directory (if `use_toolchain_file` is set)
+    ///
+    /// Errors if the toolchain of the file is not installed, the caller should fall back to the default toolchain
+    fn session_toolchain(options: &Options) -> Result<ToolChain> {
+        if options.toolchain != ToolChain::Default || !options.use_toolchain_file {
+            return Ok(options.toolchain.clone());
+        }
+        let Some((toolchain, path)) = std::env::current_dir()
+            .ok()
+            .and_then(|dir| ToolChain::from_toolchain_file(&dir))
+        else {
+            return Ok(ToolChain::Default);
+        };
+        toolchain
+            .check_installed()
+            .map_err(|e| format!("{} (from {})", e, path.display()))?;
+        Ok(toolchain)
+    }
+
     fn prepare(&mut self) -> Result<()> {
         // title is optional
         let title = if let Some(title) = self.trigger_set_title_hook() {
@@ -131,34 +194,29 @@ impl IRust {
     pub fn run(&mut self) -> Result<()> {
         self.prepare()?;
 
-        let mut server = if self.options.local_server {
-            // Only one rust instance gets to act as a server
-            start_server(self.options.local_server_adress).ok()
-        } else {
-            None
-        };
-
         loop {
             // flush queued output after each key
             // some events that have an inner input loop like ctrl-r/ ctrl-d require flushing inside their respective handler function
             std::io::Write::flush(&mut self.printer.writer.raw)?;
 
-            let evs = if let Some(ref mut server) = server {
-                read_from_net_and_stdin(server)
-            } else {
-                vec![crossterm::event::read()]
-            };
+            // Show the input diagnostics once the user stops typing
+            if self.completer.is_some()
+                && self.options.ra_live_diagnostics
+                && !crossterm::event::poll(LIVE_DIAGNOSTICS_DELAY)?
+            {
+                // Diagnostics are only a hint, ignore rust-analyzer errors
+                let _ = self.show_live_diagnostics();
+                std::io::Write::flush(&mut self.printer.writer.raw)?;
+            }
 
-            for ev in evs {
-                match ev {
-                    Ok(ev) => {
-                        self.handle_input_event(ev)?;
-                        if self.exit_flag {
-                            return Ok(());
-                        }
+            match crossterm::event::read() {
+                Ok(ev) => {
+                    self.handle_input_event(ev)?;
+                    if self.exit_flag {
+                        return Ok(());
                     }
-                    Err(e) => return Err(format!("failed to read input. error: {e}").into()),
                 }
+                Err(e) => return Err(format!("failed to read input. error: {e}").into()),
             }
         }
     }
@@ -271,6 +329,16 @@ impl IRust {
                     modifiers: KeyModifiers::CONTROL,
                     ..
                 } => self.execute(Command::HandleCtrlR)?,
+                KeyEvent {
+                    code: KeyCode::Char('f'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => self.execute(Command::ApplyFix)?,
+                KeyEvent {
+                    code: KeyCode::Char('k'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                } => self.execute(Command::Hover)?,
                 KeyEvent {
                     code: KeyCode::Char('o'),
                     modifiers: KeyModifiers::CONTROL,
@@ -350,50 +418,6 @@ impl IRust {
     }
 }
 
-fn start_server(adress: SocketAddrV4) -> Result<mpsc::Receiver<String>> {
-    let server = TcpListener::bind(adress)?;
-    let (tx, rx) = mpsc::channel();
-    std::thread::spawn(move || {
-        let mut buf = String::new();
-        loop {
-            (|| {
-                let mut c = server.accept().ok()?.0;
-                c.read_to_string(&mut buf).ok()?;
-                tx.send(buf.clone()).ok()?;
-                buf.clear();
-                Some(())
-            })();
-        }
-    });
-    Ok(rx)
-}
-fn read_from_net_and_stdin(server: &mut mpsc::Receiver<String>) -> Vec<std::io::Result<Event>> {
-    loop {
-        if let Ok(e) = server.try_recv() {
-            return e
-                .chars()
-                .map(|c| {
-                    Ok(Event::Key(KeyEvent {
-                        code: KeyCode::Char(c),
-                        modifiers: KeyModifiers::NONE,
-                        kind: KeyEventKind::Press,
-                        state: KeyEventState::NONE,
-                    }))
-                })
-                .chain(std::iter::once(Ok(Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
-                    modifiers: KeyModifiers::NONE,
-                    kind: KeyEventKind::Press,
-                    state: KeyEventState::NONE,
-                }))))
-                .collect();
-        }
-        if let Ok(true) = crossterm::event::poll(Duration::from_millis(100)) {
-            return vec![crossterm::event::read()];
-        }
-    }
-}
-
 impl Drop for IRust {
     fn drop(&mut self) {
         // ignore errors on drop with let _
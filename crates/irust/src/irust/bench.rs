@@ -0,0 +1,204 @@
+//! Statistical benchmarking of an expression (`:bench <expr>`)
+//!
+//! The generated code warms up, then measures `SAMPLES` batches of iterations and prints the time per
+//! iteration of each batch, the statistics are computed here
+use std::fmt::Write;
+
+const SAMPLES: usize = 100;
+const WARM_UP_MS: u64 = 500;
+const MEASUREMENT_MS: u64 = 2000;
+const SAMPLE_MARKER: &str = "__irust_bench_sample ";
+/// Changes with a smaller welch t-statistic are considered noise
+const SIGNIFICANCE: f64 = 2.0;
+
+/// Evaluator that benchmarks the expression, it prints each sample prefixed with `SAMPLE_MARKER`
+///
+/// The expression is on its own lines so the diagnostics point to it
+pub fn evaluator() -> [String; 2] {
+    [
+        "let mut __irust_bench = || {\n".to_string(),
+        format!(
+            "\n}};
+        let __irust_start = std::time::Instant::now();
+        let mut __irust_iterations: u64 = 0;
+        while __irust_start.elapsed() < std::time::Duration::from_millis({WARM_UP_MS}) {{
+            std::hint::black_box(__irust_bench());
+            __irust_iterations += 1;
+        }}
+        let __irust_per_iteration = __irust_start.elapsed().as_nanos() / __irust_iterations as u128;
+        let __irust_iterations = ({MEASUREMENT_MS}_000_000 / {SAMPLES} / __irust_per_iteration.max(1)).max(1);
+        for _ in 0..{SAMPLES} {{
+            let __irust_start = std::time::Instant::now();
+            for _ in 0..__irust_iterations {{
+                std::hint::black_box(__irust_bench());
+            }}
+            println!(
+                \"{SAMPLE_MARKER}{{}} {{}}\",
+                __irust_iterations,
+                __irust_start.elapsed().as_nanos() as f64 / __irust_iterations as f64
+            );
+        }}
+        "
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// Nanoseconds per iteration
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub samples: usize,
+    pub iterations: u64,
+    /// Tukey fences: (low severe, low mild, high mild, high severe)
+    pub outliers: (usize, usize, usize, usize),
+}
+
+impl Estimate {
+    /// Parse the harness output, returns `None` if there are no samples
+    pub fn from_output(output: &str) -> Option<Self> {
+        let mut iterations = 0;
+        let samples: Vec<f64> = output
+            .lines()
+            .filter_map(|line| {
+                let (count, time) = line.strip_prefix(SAMPLE_MARKER)?.split_once(' ')?;
+                iterations = count.parse().ok()?;
+                time.parse().ok()
+            })
+            .collect();
+        Self::from_samples(samples, iterations)
+    }
+
+    fn from_samples(mut samples: Vec<f64>, iterations: u64) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(f64::total_cmp);
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let std_dev = if samples.len() > 1 {
+            (samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.)).sqrt()
+        } else {
+            0.
+        };
+
+        let (q1, q3) = (percentile(&samples, 25.), percentile(&samples, 75.));
+        let iqr = q3 - q1;
+        let mut outliers = (0, 0, 0, 0);
+        for &sample in &samples {
+            if sample < q1 - 3. * iqr {
+                outliers.0 += 1;
+            } else if sample < q1 - 1.5 * iqr {
+                outliers.1 += 1;
+            } else if sample > q3 + 3. * iqr {
+                outliers.3 += 1;
+            } else if sample > q3 + 1.5 * iqr {
+                outliers.2 += 1;
+            }
+        }
+
+        Some(Self {
+            mean,
+            median: percentile(&samples, 50.),
+            std_dev,
+            samples: samples.len(),
+            iterations,
+            outliers,
+        })
+    }
+
+    /// Report the estimate, with the change from the `previous` estimate of the same expression
+    pub fn report(&self, previous: Option<&Estimate>) -> String {
+        let mut report = format!(
+            "mean:   {}\nmedian: {}\nstddev: {}\n{} samples of {} iterations",
+            format_time(self.mean),
+            format_time(self.median),
+            format_time(self.std_dev),
+            self.samples,
+            self.iterations
+        );
+
+        let (low_severe, low_mild, high_mild, high_severe) = self.outliers;
+        let total = low_severe + low_mild + high_mild + high_severe;
+        if total > 0 {
+            let kinds: Vec<String> = [
+                (low_severe, "low severe"),
+                (low_mild, "low mild"),
+                (high_mild, "high mild"),
+                (high_severe, "high severe"),
+            ]
+            .into_iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{count} {kind}"))
+            .collect();
+            let _ = write!(
+                report,
+                "\nfound {total} outliers among {} samples ({})",
+                self.samples,
+                kinds.join(", ")
+            );
+        }
+
+        if let Some(previous) = previous {
+            let change = (self.mean - previous.mean) / previous.mean * 100.;
+            // Welch's t-test
+            let error = (self.std_dev.powi(2) / self.samples as f64
+                + previous.std_dev.powi(2) / previous.samples as f64)
+                .sqrt();
+            let t = (self.mean - previous.mean) / error;
+            let verdict = if t.is_nan() || t.abs() <= SIGNIFICANCE {
+                "no significant change"
+            } else if change < 0. {
+                "faster"
+            } else {
+                "slower"
+            };
+            let _ = write!(
+                report,
+                "\nchange: {change:+.2}% ({verdict}), previous mean: {}",
+                format_time(previous.mean)
+            );
+        }
+        report
+    }
+}
+
+/// Percentile of sorted samples, with linear interpolation
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = percent / 100. * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+fn format_time(ns: f64) -> String {
+    if ns < 1e3 {
+        format!("{ns:.2} ns")
+    } else if ns < 1e6 {
+        format!("{:.2} µs", ns / 1e3)
+    } else if ns < 1e9 {
+        format!("{:.2} ms", ns / 1e6)
+    } else {
+        format!("{:.2} s", ns / 1e9)
+    }
+}
+
+#[test]
+fn estimate_test() {
+    let mut samples: Vec<f64> = (0..100).map(|i| 100. + (i % 10) as f64).collect();
+    samples[0] = 1000.;
+    let estimate = Estimate::from_samples(samples, 50).unwrap();
+    assert_eq!(estimate.median, 105.);
+    assert_eq!(estimate.outliers, (0, 0, 0, 1));
+    assert!((estimate.mean - 113.5).abs() < 1e-9);
+
+    let output =
+        format!("{SAMPLE_MARKER}10 1500\nprinted by the expression\n{SAMPLE_MARKER}10 2500\n");
+    let previous = Estimate::from_output(&output).unwrap();
+    assert_eq!(previous.iterations, 10);
+    assert_eq!(previous.mean, 2000.);
+    let report = estimate.report(Some(&previous));
+    assert!(report.contains("median: 105.00 ns"));
+    assert!(report.contains("found 1 outliers among 100 samples (1 high severe)"));
+    assert!(report.contains("change: -94.33% (faster), previous mean: 2.00 µs"));
+}
@@ -0,0 +1,220 @@
+//! `:asm`, `:llvm_ir` and `:mir`: the code emitted by rustc for a repl function
+//!
+//! `--diff` shows the debug and release code side by side, `--diff +<toolchain>` compares the current toolchain
+//! with another one
+use crate::irust::{IRust, Result};
+use crossterm::style::Color;
+use irust_repl::emit::EmitKind;
+use irust_repl::ToolChain;
+use printer::printer::{PrintQueue, PrinterItem};
+use similar::{Algorithm, ChangeTag};
+use std::str::FromStr;
+
+const SEPARATOR: &str = " │ ";
+
+struct EmitArgs<'a> {
+    function: &'a str,
+    release: bool,
+    diff: Option<Diff>,
+}
+
+enum Diff {
+    Release,
+    ToolChain(ToolChain),
+}
+
+/// `[--release|--debug] [--diff [+toolchain]] <function>`
+fn parse_args(args: &str, release: bool) -> Result<EmitArgs<'_>> {
+    let mut function = None;
+    let mut release = release;
+    let mut diff = None;
+    let mut args = args.split_whitespace().peekable();
+    while let Some(arg) = args.next() {
+        match arg {
+            "--release" => release = true,
+            "--debug" => release = false,
+            "--diff" => {
+                diff = Some(match args.next_if(|toolchain| toolchain.starts_with('+')) {
+                    Some(toolchain) => Diff::ToolChain(ToolChain::from_str(toolchain)?),
+                    None => Diff::Release,
+                })
+            }
+            arg if arg.starts_with("--") => return Err(format!("Unknown flag `{arg}`").into()),
+            arg if function.is_none() => function = Some(arg),
+            _ => return Err("Only one function can be specified".into()),
+        }
+    }
+    Ok(EmitArgs {
+        function: function.ok_or("No function specified")?,
+        release,
+        diff,
+    })
+}
+
+impl IRust {
+    pub fn emit(&mut self, buffer: String, kind: EmitKind) -> Result<PrintQueue> {
+        let args = buffer.split_once(' ').map_or("", |(_, args)| args);
+        let args = parse_args(args, self.options.compile_mode.is_release())?;
+        let toolchain = self.repl.toolchain();
+
+        self.before_compiling_hook();
+        let emitted = match &args.diff {
+            None => self
+                .repl
+                .emit(kind, args.function, args.release, toolchain)
+                .map(|code| colorize(&code, kind)),
+            Some(Diff::Release) => self
+                .repl
+                .emit(kind, args.function, false, toolchain.clone())
+                .and_then(|debug| {
+                    let release = self.repl.emit(kind, args.function, true, toolchain)?;
+                    Ok(side_by_side(
+                        ("debug", &debug),
+                        ("release", &release),
+                        self.printer.cursor.width(),
+                    ))
+                }),
+            Some(Diff::ToolChain(other)) => self
+                .repl
+                .emit(kind, args.function, args.release, toolchain.clone())
+                .and_then(|current| {
+                    let other_code =
+                        self.repl
+                            .emit(kind, args.function, args.release, other.clone())?;
+                    Ok(side_by_side(
+                        (&format!("+{toolchain}"), &current),
+                        (&format!("+{other}"), &other_code),
+                        self.printer.cursor.width(),
+                    ))
+                }),
+        };
+        self.after_compiling_hook();
+
+        self.printer.print_output_paged(emitted?)?;
+        Ok(PrintQueue::default())
+    }
+}
+
+/// Split a line in its code and its comment
+fn split_comment(line: &str, kind: EmitKind) -> (&str, &str) {
+    let start = match kind {
+        EmitKind::Asm => ["#", ";", "//"]
+            .into_iter()
+            .filter_map(|comment| line.find(comment))
+            .min(),
+        EmitKind::LlvmIr => line.find(';'),
+        EmitKind::Mir => line.find("//"),
+    };
+    line.split_at(start.unwrap_or(line.len()))
+}
+
+/// Comments are grey, labels yellow and instructions blue
+fn colorize_line(line: &str, kind: EmitKind) -> Vec<(String, Color)> {
+    let (code, comment) = split_comment(line, kind);
+    let mut parts = vec![];
+    let trimmed = code.trim();
+    let is_label = match kind {
+        EmitKind::Asm | EmitKind::LlvmIr => {
+            trimmed.ends_with(':') || trimmed.starts_with("define ") || trimmed == "}"
+        }
+        EmitKind::Mir => {
+            trimmed.starts_with("fn ") || trimmed.starts_with("bb") && trimmed.ends_with('{')
+        }
+    };
+    if is_label || trimmed.is_empty() {
+        parts.push((code.to_string(), Color::Yellow));
+    } else {
+        let indent = &code[..code.len() - code.trim_start().len()];
+        // `%x = load ..` the instruction is after the assignment
+        let (assignment, instruction) = match kind {
+            EmitKind::LlvmIr => trimmed
+                .split_once(" = ")
+                .map_or(("", trimmed), |(var, rest)| (var, rest)),
+            _ => ("", trimmed),
+        };
+        let (op, operands) = instruction
+            .split_once(char::is_whitespace)
+            .unwrap_or((instruction, ""));
+        parts.push((indent.to_string(), Color::White));
+        if !assignment.is_empty() {
+            parts.push((format!("{assignment} = "), Color::White));
+        }
+        let op_color = match kind {
+            // MIR statements are mostly assignments, only the keywords are highlighted
+            EmitKind::Mir if !matches!(op, "let" | "debug" | "scope" | "return;") => Color::White,
+            _ => Color::Blue,
+        };
+        parts.push((op.to_string(), op_color));
+        if !operands.is_empty() {
+            parts.push((format!(" {operands}"), Color::White));
+        }
+        // Keep the spaces between the code and the comment
+        parts.push((
+            code[indent.len() + trimmed.len()..].to_string(),
+            Color::White,
+        ));
+    }
+    if !comment.is_empty() {
+        parts.push((comment.to_string(), Color::DarkGrey));
+    }
+    parts
+}
+
+fn colorize(code: &str, kind: EmitKind) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    for line in code.lines() {
+        for (part, color) in colorize_line(line, kind) {
+            queue.push(PrinterItem::String(part, color));
+        }
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+/// Pad or truncate the line to `width` chars
+fn fit(line: &str, width: usize) -> String {
+    let line = line.replace('\t', "    ");
+    let len = line.chars().count();
+    if len > width {
+        let mut line: String = line.chars().take(width.saturating_sub(1)).collect();
+        line.push('…');
+        line
+    } else {
+        line + &" ".repeat(width - len)
+    }
+}
+
+fn side_by_side(
+    (left_title, left): (&str, &str),
+    (right_title, right): (&str, &str),
+    width: usize,
+) -> PrintQueue {
+    let column = width.saturating_sub(SEPARATOR.chars().count() + 1) / 2;
+    let mut queue = PrintQueue::default();
+    let row = |queue: &mut PrintQueue, (left, left_color), (right, right_color)| {
+        queue.push(PrinterItem::String(fit(left, column), left_color));
+        queue.push(PrinterItem::Str(SEPARATOR, Color::DarkGrey));
+        queue.push(PrinterItem::String(fit(right, column), right_color));
+        queue.add_new_line(1);
+    };
+
+    row(
+        &mut queue,
+        (left_title, Color::Yellow),
+        (right_title, Color::Yellow),
+    );
+    let left: Vec<&str> = left.lines().collect();
+    let right: Vec<&str> = right.lines().collect();
+    for change in similar::capture_diff_slices(Algorithm::Myers, &left, &right)
+        .iter()
+        .flat_map(|op| op.iter_changes(&left, &right))
+    {
+        let line = change.value();
+        match change.tag() {
+            ChangeTag::Equal => row(&mut queue, (line, Color::White), (line, Color::White)),
+            ChangeTag::Delete => row(&mut queue, (line, Color::Red), ("", Color::White)),
+            ChangeTag::Insert => row(&mut queue, ("", Color::White), (line, Color::Green)),
+        }
+    }
+    queue
+}
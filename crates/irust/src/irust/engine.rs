@@ -7,6 +7,7 @@ use crossterm::{
     terminal::ClearType,
 };
 use irust_api::Command;
+use irust_repl::jobs::Job;
 use printer::printer::{PrintQueue, PrinterItem};
 
 use crate::irust::IRust;
@@ -180,6 +181,10 @@ impl IRust {
                 self.history.unlock();
                 // Ignore CompleterDisabled error
                 let _ = self.completer.as_mut().map(Completer::unlock_ra_update);
+                if matches!(c, '(' | ',') && self.completer.is_some() {
+                    // Signature help is only a hint, ignore rust-analyzer errors
+                    let _ = self.show_signature_help();
+                }
 
                 Ok(())
             }
@@ -549,11 +554,7 @@ impl IRust {
                                 code: KeyCode::Char('d'),
                                 modifiers: KeyModifiers::CONTROL,
                                 ..
-                            }) => {
-                                if needle.is_empty() {
-                                    break;
-                                }
-                            }
+                            }) if needle.is_empty() => break,
                             _ => (),
                         }
                     }
@@ -680,6 +681,12 @@ impl IRust {
                 if !self.engine.dont_save_options {
                     self.options.save()?;
                 }
+                // Don't leave the builds and the programs of the background jobs running
+                let jobs = std::mem::take(&mut self.jobs);
+                jobs.values().for_each(Job::kill);
+                jobs.into_values().for_each(|job| {
+                    let _ = job.wait();
+                });
                 self.printer.write_newline(&self.buffer);
                 self.printer.cursor.show();
                 Ok(())
@@ -802,19 +809,43 @@ impl IRust {
                 self.printer.cursor.goto(last_input_pos.0, last_input_pos.1);
                 Ok(())
             }
+            Command::Hover => {
+                if self.completer.is_some() {
+                    self.show_hover()?;
+                }
+                Ok(())
+            }
+            Command::ApplyFix => {
+                // Keep the current input if there is nothing to fix
+                if let Ok(fixed) = self.fixed_input() {
+                    self.execute(Command::RemoveRASugesstion)?;
+                    self.buffer = fixed.into();
+                    self.print_input()?;
+                    let last_input_pos = self.printer.cursor.input_last_pos(&self.buffer);
+                    self.buffer.goto_end();
+                    self.printer.cursor.goto(last_input_pos.0, last_input_pos.1);
+                }
+                Ok(())
+            }
             Command::Parse(buf) => {
+                // `:fix` puts the fixed input in the new prompt instead of printing it
+                let fix = buf == ":fix";
                 // parse and handle errors
-                let output = match self.parse(buf) {
-                    Ok(out) => out,
+                // Background jobs that finished are reported before the output
+                let mut output = self.finished_jobs();
+                match self.parse(buf) {
+                    Ok(mut out) => output.append(&mut out),
                     Err(e) => {
-                        let mut printer = PrintQueue::default();
-                        printer.push(PrinterItem::String(e.to_string(), self.options.err_color));
-                        printer.add_new_line(1);
-                        printer
+                        output.push(PrinterItem::String(e.to_string(), self.options.err_color));
+                        output.add_new_line(1);
                     }
-                };
+                }
 
-                self.print_output(output)
+                self.print_output(output)?;
+                if fix {
+                    self.execute(Command::ApplyFix)?;
+                }
+                Ok(())
             }
             Command::PrintOutput(output, color) => {
                 let output = PrinterItem::String(output, color).into();
@@ -875,10 +906,7 @@ enum Dir {
 // helper functions
 
 fn incomplete_input(buffer: &str) -> bool {
-    StringTools::unmatched_brackets(buffer)
-        || buffer
-            .trim_end()
-            .ends_with(|c| c == ':' || c == '.' || c == '=')
+    StringTools::unmatched_brackets(buffer) || buffer.trim_end().ends_with([':', '.', '='])
 }
 
 fn input_is_cmd_or_shell(buffer: &str) -> bool {
@@ -1,4 +1,5 @@
 use crossterm::style::Color;
+use irust_repl::{diagnostics::Level, CompileMode, EvalResult};
 use printer::printer::{PrintQueue, PrinterItem};
 use std::sync::OnceLock;
 
@@ -8,7 +9,17 @@ fn no_color() -> bool {
     *NO_COLOR.get_or_init(|| std::env::var("NO_COLOR").is_ok())
 }
 
-pub fn format_err<'a>(original_output: &'a str, show_warnings: bool, repl_name: &str) -> String {
+pub fn format_err(original_output: &str, show_warnings: bool, repl_name: &str) -> String {
+    format_output_err(original_output, show_warnings, repl_name, false)
+}
+
+/// `miri`: the output is a Miri report, its errors are not always colored
+fn format_output_err<'a>(
+    original_output: &'a str,
+    show_warnings: bool,
+    repl_name: &str,
+    miri: bool,
+) -> String {
     const BEFORE_2021_END_TAG: &str = ": aborting due to ";
     // Relies on --color=always
     const ERROR_TAG: &str = "\u{1b}[0m\u{1b}[1m\u{1b}[38;5;9merror";
@@ -32,7 +43,8 @@ pub fn format_err<'a>(original_output: &'a str, show_warnings: bool, repl_name:
                     if no_color() {
                         !line.starts_with(ERROR_TAG_NO_COLOR)
                     } else {
-                        !line.starts_with(ERROR_TAG)
+                        // Miri reports are not always colored
+                        !(line.starts_with(ERROR_TAG) || miri && line.starts_with("error: "))
                     }
                 })
                 .collect()
@@ -91,17 +103,84 @@ pub fn format_err_printqueue(output: &str, show_warnings: bool, repl_name: &str)
     PrinterItem::String(format_err(output, show_warnings, repl_name), Color::Red).into()
 }
 
+/// Render the compiler diagnostics of a failed build, fallback to parsing the output if there are no errors
+/// (exp: an invalid manifest)
+pub fn format_build_err(result: &EvalResult, show_warnings: bool, repl_name: &str) -> String {
+    format_eval_err(result, show_warnings, repl_name, CompileMode::Debug)
+}
+
+/// Like [`format_build_err`], a failure without errors in the diagnostics is a Miri report with the Miri mode
+fn format_eval_err(
+    result: &EvalResult,
+    show_warnings: bool,
+    repl_name: &str,
+    compile_mode: CompileMode,
+) -> String {
+    if !result
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.level == Level::Error)
+    {
+        // The warnings are in the diagnostics, the output only contains the error
+        return format_output_err(
+            &result.output,
+            false,
+            repl_name,
+            matches!(compile_mode, CompileMode::Miri),
+        );
+    }
+    let rendered: Vec<String> = result
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            diagnostic.level == Level::Error || show_warnings && diagnostic.level == Level::Warning
+        })
+        .map(|diagnostic| diagnostic.render(!no_color()))
+        .collect();
+    rendered.join("\n\n")
+}
+
+fn format_build_err_printqueue(
+    result: &EvalResult,
+    show_warnings: bool,
+    repl_name: &str,
+    compile_mode: CompileMode,
+) -> PrintQueue {
+    PrinterItem::String(
+        format_eval_err(result, show_warnings, repl_name, compile_mode),
+        Color::Red,
+    )
+    .into()
+}
+
 pub fn format_eval_output(
-    status: std::process::ExitStatus,
-    output: String,
+    result: EvalResult,
     prompt: String,
     show_warnings: bool,
     repl_name: &str,
     new_lines_after_output: usize,
+    compile_mode: CompileMode,
 ) -> Option<PrintQueue> {
-    if !status.success() {
-        return Some(format_err_printqueue(&output, show_warnings, repl_name));
+    if let Some(failure) = result.runtime_failure {
+        // What the program printed before failing, then the panic/exit status report
+        let mut eval_output = PrintQueue::default();
+        if !result.output.is_empty() {
+            eval_output.push(PrinterItem::String(prompt, Color::Red));
+            eval_output.push(PrinterItem::String(result.output, Color::White));
+            eval_output.add_new_line(1);
+        }
+        eval_output.push(PrinterItem::String(failure, Color::Red));
+        return Some(eval_output);
+    }
+    if !result.status.success() {
+        return Some(format_build_err_printqueue(
+            &result,
+            show_warnings,
+            repl_name,
+            compile_mode,
+        ));
     }
+    let output = result.output;
     if output.trim() == "()" {
         return None;
     }
@@ -113,17 +192,18 @@ pub fn format_eval_output(
     Some(eval_output)
 }
 
-fn check_is_err(s: &str) -> bool {
-    !s.contains("[unoptimized + debuginfo]")
-}
-
 pub fn format_check_output(
-    output: String,
+    result: EvalResult,
     show_warnings: bool,
     repl_name: &str,
 ) -> Option<PrintQueue> {
-    if check_is_err(&output) {
-        Some(format_err_printqueue(&output, show_warnings, repl_name))
+    if !result.status.success() {
+        Some(format_build_err_printqueue(
+            &result,
+            show_warnings,
+            repl_name,
+            CompileMode::Debug,
+        ))
     } else {
         None
     }
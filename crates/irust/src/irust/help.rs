@@ -1,6 +1,7 @@
 use super::highlight::{highlight, theme::Theme};
 use crate::irust::{IRust, Result};
 use crossterm::style::Color;
+use irust_repl::diagnostics::Level;
 use printer::{
     buffer::Buffer,
     printer::{PrintQueue, PrinterItem},
@@ -17,9 +18,77 @@ impl IRust {
 
         Ok(parse_markdown(&readme.into(), &self.theme, compact))
     }
+
+    /// Explain the given error code, or the error codes of the last failed input
+    pub fn explain(&mut self, buffer: String) -> Result<PrintQueue> {
+        let codes = match buffer.split_whitespace().nth(1) {
+            Some(code) => vec![error_code(code)?],
+            None => {
+                let mut codes: Vec<String> = vec![];
+                for code in self
+                    .last_failure
+                    .iter()
+                    .flat_map(|(_, diagnostics)| diagnostics)
+                    .filter(|diagnostic| diagnostic.level == Level::Error)
+                    .filter_map(|diagnostic| diagnostic.code.clone())
+                {
+                    if !codes.contains(&code) {
+                        codes.push(code);
+                    }
+                }
+                codes
+            }
+        };
+        if codes.is_empty() {
+            return Err("No error code to explain".into());
+        }
+
+        let mut markdown = String::new();
+        for code in codes {
+            let explanation = self
+                .repl
+                .cargo
+                .rustc_explain(&code, self.repl.toolchain())?;
+            markdown += &format!("# {code}\n\n{}\n", mark_rust_code_blocks(&explanation));
+        }
+
+        // Explanations are long, so they are printed here with paging instead of returned
+        self.printer
+            .print_output_paged(parse_markdown(&markdown.into(), &self.theme, false))?;
+        Ok(PrintQueue::default())
+    }
+}
+
+/// Accepts `E0382`, `e0382` or `382`
+fn error_code(code: &str) -> Result<String> {
+    let digits = code.trim_start_matches(['E', 'e']);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid error code: {code}").into());
+    }
+    Ok(format!("E{digits:0>4}"))
+}
+
+/// The code blocks of rustc explanations are rust code, but their fences don't say it
+fn mark_rust_code_blocks(markdown: &str) -> String {
+    let mut in_code_block = false;
+    markdown
+        .lines()
+        .map(|line| {
+            if !line.starts_with("```") {
+                return line;
+            }
+            in_code_block = !in_code_block;
+            if in_code_block && line == "```" {
+                "```rust"
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-fn parse_markdown(buffer: &Buffer, theme: &Theme, compact: bool) -> PrintQueue {
+pub(super) fn parse_markdown(buffer: &Buffer, theme: &Theme, compact: bool) -> PrintQueue {
     let mut queue = PrintQueue::default();
 
     let buffer = buffer.to_string();
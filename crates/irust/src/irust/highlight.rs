@@ -1,11 +1,72 @@
 use crossterm::style::Color;
 use printer::buffer::Buffer;
 use printer::printer::{PrintQueue, PrinterItem};
+use std::ops::Range;
 use theme::Theme;
 pub mod theme;
 
 const PAREN_COLORS: [&str; 4] = ["red", "yellow", "green", "blue"];
 
+/// Like `highlight`, but the chars inside the given ranges (in chars) use the range color
+///
+/// Used to mark the diagnostics spans in the input
+pub fn highlight_ranges(
+    buffer: &Buffer,
+    theme: &Theme,
+    ranges: &[(Range<usize>, Color)],
+) -> PrintQueue {
+    let text = buffer.to_string();
+    let byte_idx = |char_idx: usize| {
+        text.char_indices()
+            .nth(char_idx)
+            .map_or(text.len(), |(idx, _)| idx)
+    };
+    let ranges: Vec<(Range<usize>, Color)> = ranges
+        .iter()
+        .map(|(range, color)| (byte_idx(range.start)..byte_idx(range.end), *color))
+        .collect();
+    let color_at = |idx: usize| {
+        ranges
+            .iter()
+            .find(|(range, _)| range.contains(&idx))
+            .map(|(_, color)| *color)
+    };
+
+    let mut print_queue = PrintQueue::default();
+    for item in highlight(buffer, theme) {
+        let PrinterItem::RcString(string, range, token_color) = item else {
+            print_queue.push(item);
+            continue;
+        };
+        // Split the token where the color changes
+        let mut start = range.start;
+        let mut current_color = None;
+        for (offset, _) in string[range.clone()].char_indices() {
+            let idx = range.start + offset;
+            let color = color_at(idx).unwrap_or(token_color);
+            if current_color != Some(color) {
+                if let Some(current_color) = current_color {
+                    print_queue.push(PrinterItem::RcString(
+                        string.clone(),
+                        start..idx,
+                        current_color,
+                    ));
+                }
+                start = idx;
+                current_color = Some(color);
+            }
+        }
+        if let Some(current_color) = current_color {
+            print_queue.push(PrinterItem::RcString(
+                string,
+                start..range.end,
+                current_color,
+            ));
+        }
+    }
+    print_queue
+}
+
 pub fn highlight(buffer: &Buffer, theme: &Theme) -> PrintQueue {
     let mut print_queue = PrintQueue::default();
 
@@ -0,0 +1,214 @@
+//! Commands that inspect the code with rust-analyzer (hover, signature help, go to definition, diagnostics)
+use super::help::parse_markdown;
+use super::highlight::{highlight, highlight_ranges, theme::Theme};
+use super::ra::Completer;
+use crate::irust::{IRust, Result};
+use crossterm::style::Color;
+use printer::printer::{PrintQueue, PrinterItem};
+use std::ops::Range;
+
+/// The expression given to `:type` is bound to this variable so its type can be hovered
+const TYPE_VAR: &str = "__irust_type";
+
+fn rust_analyzer(completer: &mut Option<Completer>) -> Result<&mut Completer> {
+    completer
+        .as_mut()
+        .ok_or_else(|| "rust-analyzer is not running (see `enable_rust_analyzer`)".into())
+}
+
+impl IRust {
+    /// Type of `expression` as shown by rust-analyzer
+    pub fn ra_type(&mut self, expression: &str) -> Result<Option<String>> {
+        let input = format!("let {TYPE_VAR} = {expression};");
+        let hover =
+            rust_analyzer(&mut self.completer)?.hover(&mut self.repl, &input, "let ".len())?;
+
+        Ok(hover.and_then(|hover| {
+            hover
+                .lines()
+                .find_map(|line| line.split_once(&format!("{TYPE_VAR}: ")))
+                .map(|(_, ty)| ty.trim().to_string())
+        }))
+    }
+
+    pub fn doc(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = path_arg(&buffer, ":doc")?;
+        let ra = rust_analyzer(&mut self.completer)?;
+        let doc = path_inputs(path)
+            .into_iter()
+            .find_map(|(input, pos)| ra.hover(&mut self.repl, &input, pos).ok().flatten())
+            .ok_or(format!("No documentation found for `{path}`"))?;
+
+        self.printer
+            .print_output_paged(parse_markdown(&doc.into(), &self.theme, false))?;
+        Ok(PrintQueue::default())
+    }
+
+    pub fn def(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = path_arg(&buffer, ":def")?;
+        let ra = rust_analyzer(&mut self.completer)?;
+        let location = path_inputs(path)
+            .into_iter()
+            .find_map(|(input, pos)| ra.definition(&mut self.repl, &input, pos).ok().flatten())
+            .ok_or(format!("No definition found for `{path}`"))?;
+
+        let source = std::fs::read_to_string(&location.path)?;
+        let code = source
+            .lines()
+            .skip(*location.lines.start())
+            .take(location.lines.end() + 1 - location.lines.start())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut queue = PrintQueue::default();
+        queue.push(PrinterItem::String(
+            format!("{}:{}", location.path.display(), location.lines.start() + 1),
+            Color::Yellow,
+        ));
+        queue.add_new_line(1);
+        queue.append(&mut highlight(&code.into(), &self.theme));
+        queue.add_new_line(1);
+        self.printer.print_output_paged(queue)?;
+        Ok(PrintQueue::default())
+    }
+
+    /// Show the hover documentation of the symbol under the cursor below the input
+    pub fn show_hover(&mut self) -> Result<()> {
+        let input = self.buffer.to_string();
+        let pos = self.buffer.buffer_pos;
+        let queue = match rust_analyzer(&mut self.completer)?.hover(&mut self.repl, &input, pos) {
+            Ok(Some(hover)) => render_hover(&hover, &self.theme),
+            Ok(None) => PrinterItem::Str("No documentation found", self.options.err_color).into(),
+            Err(e) => PrinterItem::String(e.to_string(), self.options.err_color).into(),
+        };
+        self.printer.print_below_input(queue, &self.buffer)
+    }
+
+    /// Show the signature of the call surrounding the cursor below the input, the active parameter is highlighted
+    pub fn show_signature_help(&mut self) -> Result<()> {
+        let input = self.buffer.to_string();
+        let pos = self.buffer.buffer_pos;
+        let Some(signature) =
+            rust_analyzer(&mut self.completer)?.signature_help(&mut self.repl, &input, pos)?
+        else {
+            return Ok(());
+        };
+
+        let mut queue = PrintQueue::default();
+        let color = self.options.ra_inline_suggestion_color;
+        let active = signature.active_parameter.unwrap_or_default();
+        for (idx, c) in signature.label.chars().enumerate() {
+            if active.contains(&idx) {
+                queue.push(PrinterItem::Char(c, self.options.ok_color));
+            } else {
+                queue.push(PrinterItem::Char(c, color));
+            }
+        }
+        self.printer.print_below_input(queue, &self.buffer)
+    }
+
+    /// Mark the rust-analyzer diagnostics of the input and show the first message below it
+    ///
+    /// Called when the user stops typing, does nothing if the input didn't change since the last call
+    pub fn show_live_diagnostics(&mut self) -> Result<()> {
+        let input = self.buffer.to_string();
+        let ra = rust_analyzer(&mut self.completer)?;
+        if ra.diagnosed_input.as_ref() == Some(&input) {
+            return Ok(());
+        }
+        ra.diagnosed_input = Some(input.clone());
+        if input.trim().is_empty() || input.starts_with(':') {
+            return Ok(());
+        }
+
+        let diagnostics = ra.diagnostics(&mut self.repl, &input)?;
+        // The buffer could have been printed with an active suggestion
+        if diagnostics.is_empty() || ra.active_suggestion.is_some() {
+            return Ok(());
+        }
+        let color = |is_error| {
+            if is_error {
+                self.options.err_color
+            } else {
+                self.options.irust_warn_color
+            }
+        };
+        let ranges: Vec<(Range<usize>, Color)> = diagnostics
+            .iter()
+            .map(|(range, diagnostic)| (range.clone(), color(diagnostic.is_error)))
+            .collect();
+        let (_, diagnostic) = diagnostics
+            .iter()
+            .find(|(_, diagnostic)| diagnostic.is_error)
+            .unwrap_or(&diagnostics[0]);
+        let (level, color) = if diagnostic.is_error {
+            ("error", color(true))
+        } else {
+            ("warning", color(false))
+        };
+
+        self.printer.print_input_from_queue(
+            highlight_ranges(&self.buffer, &self.theme, &ranges),
+            &self.buffer,
+        )?;
+        self.printer.print_below_input(
+            PrinterItem::String(
+                // Only the first line, the message is a hint
+                format!(
+                    "{level}: {}",
+                    diagnostic.message.lines().next().unwrap_or_default()
+                ),
+                color,
+            )
+            .into(),
+            &self.buffer,
+        )
+    }
+}
+
+/// Compact rendering of the hover markdown: highlighted code blocks without fences, and the text lines
+fn render_hover(hover: &str, theme: &Theme) -> PrintQueue {
+    let mut queue = PrintQueue::default();
+    let mut in_code_block = false;
+    for line in hover.lines() {
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if line.trim().is_empty() || line == "---" {
+            continue;
+        }
+        if in_code_block {
+            queue.append(&mut highlight(&line.into(), theme));
+        } else {
+            queue.push(PrinterItem::String(line.to_string(), Color::White));
+        }
+        queue.add_new_line(1);
+    }
+    queue
+}
+
+fn path_arg<'a>(buffer: &'a str, cmd: &str) -> Result<&'a str> {
+    let path = buffer
+        .strip_prefix(cmd)
+        .expect("already checked")
+        .trim()
+        // macros
+        .trim_end_matches('!');
+    if path.is_empty() {
+        return Err("No path specified".into());
+    }
+    Ok(path)
+}
+
+/// Inputs that reference `path` with the position of its last segment
+///
+/// Items (modules, types, macros) can be imported, while methods and values can only be used in expressions
+fn path_inputs(path: &str) -> [(String, usize); 2] {
+    let last_segment = path.rfind("::").map(|idx| idx + 2).unwrap_or(0);
+    let last_segment = path[..last_segment].chars().count();
+    [
+        (format!("use {path};"), "use ".len() + last_segment),
+        (format!("let _ = {path};"), "let _ = ".len() + last_segment),
+    ]
+}
@@ -1,6 +1,8 @@
 use crate::irust::{IRust, Result};
 use crossterm::style::Color;
-use irust_repl::{CompileMode, Edition, Executor, MainResult, ToolChain, DEFAULT_EVALUATOR};
+use irust_repl::{
+    CompileMode, Edition, Executor, Limits, MainResult, Profile, ToolChain, DEFAULT_EVALUATOR,
+};
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 
@@ -25,7 +27,9 @@ pub struct Options {
     pub ra_max_suggestions: usize,
     pub first_irust_run: bool,
     pub enable_rust_analyzer: bool,
+    pub ra_live_diagnostics: bool,
     pub toolchain: ToolChain,
+    pub use_toolchain_file: bool,
     pub check_statements: bool,
     pub auto_insert_semicolon: bool,
     pub replace_marker: String,
@@ -41,11 +45,17 @@ pub struct Options {
     pub edition: Edition,
     pub debugger: Debugger,
     pub shell_interpolate: bool,
-    pub local_server: bool,
-    pub local_server_adress: std::net::SocketAddrV4,
     pub theme: String,
     pub compile_mode: CompileMode,
     pub new_lines_after_output: usize,
+    pub persistent: bool,
+    pub show_types: bool,
+    pub resource_usage: bool,
+    pub sandbox: bool,
+    pub streaming: bool,
+    // Tables needs to be last in a toml document
+    pub profile: Profile,
+    pub limits: Limits,
 }
 
 impl Default for Options {
@@ -76,10 +86,12 @@ impl Default for Options {
             ra_suggestions_table_color: Color::Green,
             ra_selected_suggestion_color: Color::DarkRed,
             ra_max_suggestions: 5,
+            ra_live_diagnostics: true,
 
             //other
             first_irust_run: true,
             toolchain: ToolChain::Default,
+            use_toolchain_file: false,
             check_statements: true,
             auto_insert_semicolon: true,
 
@@ -101,11 +113,16 @@ impl Default for Options {
             edition: Edition::E2021,
             debugger: Debugger::LLDB,
             shell_interpolate: true,
-            local_server: false,
-            local_server_adress: "127.0.0.1:9000".parse().expect("correct"),
             theme: "default".into(),
             compile_mode: CompileMode::Debug,
             new_lines_after_output: 1,
+            persistent: false,
+            show_types: false,
+            resource_usage: false,
+            sandbox: false,
+            streaming: false,
+            profile: Profile::default(),
+            limits: Limits::default(),
         }
     }
 }
@@ -4,18 +4,31 @@ use std::str::FromStr;
 use std::time::Instant;
 use std::{env, process};
 
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use crossterm::style::Color;
 
+use super::bench;
 use super::format::format_err_printqueue;
 use super::highlight::highlight;
+use super::session::Session;
+use super::streaming::StreamingTerminal;
 use crate::irust::{IRust, Result};
 use crate::utils::{copy_dir, stdout_and_stderr};
 use crate::utils::{find_workpace_root, patch_name_to};
 use crate::{
-    irust::format::{format_check_output, format_eval_output},
+    irust::format::{format_build_err, format_check_output, format_eval_output},
     utils::ctrlc_cancel,
 };
-use irust_repl::{CompileMode, EvalConfig, EvalResult, Executor, MainResult, ToolChain};
+use irust_repl::{
+    diagnostics,
+    emit::EmitKind,
+    jobs::JobStatus,
+    runtime,
+    test_runner::{self, TestOutcome},
+    type_name::{self, TYPED_EVALUATOR},
+    CompileMode, Edition, EvalConfig, EvalResult, Executor, Limits, MainResult, Profile, Repl,
+    ResourceUsage, Terminal, ToolChain, DEFAULT_EVALUATOR,
+};
 use printer::printer::{PrintQueue, PrinterItem};
 
 const SUCCESS: &str = "Ok!";
@@ -52,15 +65,24 @@ impl IRust {
             ":irust" => self.irust(),
             ":sync" => self.sync(),
             ":exit" | ":quit" => self.exit(),
+            ":fix" => self.fix(),
+            ":jobs" => self.jobs(),
             cmd if cmd.starts_with(":help") => self.help(buffer),
             cmd if cmd.starts_with("::") => self.run_cmd(buffer),
+            cmd if cmd.starts_with(":edition") => self.edition(buffer),
             cmd if cmd.starts_with(":edit") => self.extern_edit(buffer),
             cmd if cmd.starts_with(":add") => self.add_dep(buffer),
+            cmd if cmd.starts_with(":deps") => self.deps(),
+            cmd if cmd.starts_with(":rm") => self.rm_dep(buffer),
+            cmd if cmd.starts_with(":update") => self.update_dep(buffer),
+            cmd if cmd.starts_with(":features") => self.features(buffer),
             cmd if cmd.starts_with(":hard_load_crate") => self.hard_load_crate(buffer),
             cmd if cmd.starts_with(":hard_load") => self.hard_load(buffer),
             cmd if cmd.starts_with(":load") => self.load(buffer),
             cmd if cmd.starts_with(":reload") => self.reload(),
             cmd if cmd.starts_with(":type") => self.show_type(),
+            cmd if cmd.starts_with(":doc") => self.doc(buffer),
+            cmd if cmd.starts_with(":def") => self.def(buffer),
             cmd if cmd.starts_with(":del") => self.del(buffer),
             cmd if cmd.starts_with(":dbg") => self.dbg(buffer),
             cmd if cmd.starts_with(":color") => self.color(buffer),
@@ -71,14 +93,31 @@ impl IRust {
             cmd if cmd.starts_with(":check_statements") => self.check_statements(buffer),
             cmd if cmd.starts_with(":time_release") => self.time_release(buffer),
             cmd if cmd.starts_with(":time") => self.time(buffer),
-            cmd if cmd.starts_with(":bench") => self.bench(),
-            cmd if cmd.starts_with(":asm") => self.asm(buffer),
+            cmd if cmd.starts_with(":bench") => self.bench(buffer),
+            cmd if cmd.starts_with(":test") => self.test(buffer),
+            cmd if cmd.starts_with(":asm") => self.emit(buffer, EmitKind::Asm),
+            cmd if cmd.starts_with(":llvm_ir") => self.emit(buffer, EmitKind::LlvmIr),
+            cmd if cmd.starts_with(":mir") => self.emit(buffer, EmitKind::Mir),
             cmd if cmd.starts_with(":executor") => self.executor(buffer),
             cmd if cmd.starts_with(":evaluator") => self.evaluator(buffer),
             cmd if cmd.starts_with(":scripts") => self.scripts(buffer),
             cmd if cmd.starts_with(":compile_time") => self.compile_time(buffer),
             cmd if cmd.starts_with(":compile_mode") => self.compile_mode(buffer),
+            cmd if cmd.starts_with(":profile") => self.profile(buffer),
+            cmd if cmd.starts_with(":limits") => self.limits(buffer),
+            cmd if cmd.starts_with(":resource_usage") => self.resource_usage(buffer),
+            cmd if cmd.starts_with(":sandbox") => self.sandbox(buffer),
+            cmd if cmd.starts_with(":streaming") => self.streaming(buffer),
+            cmd if cmd.starts_with(":bg") => self.bg(buffer),
+            cmd if cmd.starts_with(":fg") => self.fg(buffer),
+            cmd if cmd.starts_with(":kill") => self.kill(buffer),
             cmd if cmd.starts_with(":expand") => self.expand(buffer),
+            cmd if cmd.starts_with(":persistent") => self.persistent(buffer),
+            cmd if cmd.starts_with(":show_types") => self.show_types(buffer),
+            cmd if cmd.starts_with(":save") => self.save(buffer),
+            cmd if cmd.starts_with(":restore") => self.restore(buffer),
+            cmd if cmd.starts_with(":export") => self.export(buffer),
+            cmd if cmd.starts_with(":explain") => self.explain(buffer),
             cmd if self.options.shell_interpolate && cmd.contains("$$") => {
                 let buffer = self.shell_interpolate(buffer)?;
                 self.parse_second_order(buffer)
@@ -121,11 +160,20 @@ impl IRust {
 
         if let Some(toolchain) = toolchain {
             let toolchain = ToolChain::from_str(toolchain)?;
-            self.repl.set_toolchain(toolchain);
+            toolchain.check_installed()?;
+            self.repl.set_toolchain(toolchain.clone())?;
             self.options.toolchain = toolchain;
+            if let Err(e) = self
+                .options
+                .compile_mode
+                .check_available(&self.repl.toolchain())
+            {
+                self.options.compile_mode = CompileMode::Debug;
+                return print_queue!(format!("{e}\nUsing debug mode instead"), Color::Red);
+            }
             success!()
         } else {
-            print_queue!(self.options.toolchain.to_string(), Color::Blue)
+            print_queue!(self.repl.toolchain().to_string(), Color::Blue)
         }
     }
 
@@ -174,6 +222,51 @@ impl IRust {
         }
     }
 
+    fn edition(&mut self, buffer: String) -> Result<PrintQueue> {
+        let Some(edition) = buffer.split_whitespace().nth(1) else {
+            return print_queue!(self.repl.edition().to_string(), Color::Blue);
+        };
+        let edition = Edition::from_str(edition)?;
+        let errors = self.repl.set_edition(edition)?;
+        self.options.edition = edition;
+        if errors.is_empty() {
+            return success!();
+        }
+
+        // Show the lines that need to be fixed or removed
+        let mut print_queue = PrintQueue::default();
+        print_queue.push(PrinterItem::String(
+            format!(
+                "These lines don't compile with the {edition} edition (see `:show` and `:del`):"
+            ),
+            self.options.irust_warn_color,
+        ));
+        print_queue.add_new_line(1);
+        for error in errors {
+            if let Some(span) = error
+                .spans
+                .iter()
+                .find(|span| span.is_primary && span.origin == diagnostics::Origin::Repl)
+            {
+                print_queue.push(PrinterItem::String(
+                    format!("{}: ", span.line_start),
+                    Color::Yellow,
+                ));
+                print_queue.append(&mut highlight(
+                    &span.text.join("\n").trim().into(),
+                    &self.theme,
+                ));
+                print_queue.add_new_line(1);
+            }
+            print_queue.push(PrinterItem::String(
+                format!("  error: {}", error.message),
+                self.options.err_color,
+            ));
+            print_queue.add_new_line(1);
+        }
+        Ok(print_queue)
+    }
+
     fn add_dep(&mut self, buffer: String) -> Result<PrintQueue> {
         let mut dep: Vec<String> = crate::utils::split_args(buffer);
         dep.remove(0); //drop :add
@@ -207,14 +300,37 @@ impl IRust {
         //     }
         // }
 
+        // Path and git dependencies are written directly to Cargo.toml
+        if let [flag, path] = dep.as_slice() {
+            if flag == "--path" {
+                let path = self.global_variables.get_cwd().join(path);
+                let name = self
+                    .repl
+                    .cargo
+                    .add_path_dependency(&path, self.repl.toolchain())?;
+                self.dependencies_changed()?;
+                return print_queue!(format!("Added {name}"), self.options.ok_color);
+            }
+        }
+        if let Some(url) = dep.first().filter(|dep| is_git_url(dep)) {
+            let reference = match &dep[1..] {
+                [] => None,
+                [kind, value] => Some((kind.trim_start_matches("--"), value.as_str())),
+                _ => return Err("Usage: :add <git url> [--branch|--tag|--rev <value>]".into()),
+            };
+            let name = self
+                .repl
+                .cargo
+                .add_git_dependency(url, reference, self.repl.toolchain())?;
+            self.dependencies_changed()?;
+            return print_queue!(format!("Added {name}"), self.options.ok_color);
+        }
+
         self.wait_add(self.repl.add_dep(&dep)?, "Add")?;
         self.wait_add(self.repl.build()?, "Build")?;
 
         if self.options.check_statements {
-            self.wait_add(
-                self.repl.cargo.cargo_check(self.options.toolchain)?,
-                "Check",
-            )?;
+            self.wait_add(self.repl.cargo.cargo_check(self.repl.toolchain())?, "Check")?;
         }
 
         if let Some(ra) = self.completer.as_mut() {
@@ -224,6 +340,128 @@ impl IRust {
         success!()
     }
 
+    fn deps(&mut self) -> Result<PrintQueue> {
+        let dependencies = self.repl.cargo.dependencies(self.repl.toolchain())?;
+        if dependencies.is_empty() {
+            return print_queue!("No dependencies".into(), self.options.ok_color);
+        }
+
+        let mut print_queue = PrintQueue::default();
+        for dependency in dependencies {
+            print_queue.push(PrinterItem::String(dependency.name, Color::Yellow));
+            print_queue.push(PrinterItem::String(
+                format!(" {}", dependency.source),
+                Color::White,
+            ));
+            if let Some(version) = dependency.version {
+                print_queue.push(PrinterItem::String(
+                    format!(" ({version})"),
+                    self.options.ok_color,
+                ));
+            }
+            if !dependency.features.is_empty() {
+                print_queue.push(PrinterItem::String(
+                    format!(" [{}]", dependency.features.join(", ")),
+                    Color::DarkCyan,
+                ));
+            }
+            print_queue.add_new_line(1);
+        }
+        Ok(print_queue)
+    }
+
+    fn rm_dep(&mut self, buffer: String) -> Result<PrintQueue> {
+        let deps: Vec<&str> = buffer.split_whitespace().skip(1).collect();
+        if deps.is_empty() {
+            return Err("No dependency specified".into());
+        }
+        self.repl
+            .cargo
+            .remove_dependencies(&deps, self.repl.toolchain())?;
+        self.dependencies_changed()?;
+        success!()
+    }
+
+    fn update_dep(&mut self, buffer: String) -> Result<PrintQueue> {
+        let mut args = buffer.split_whitespace().skip(1);
+        let dep = args.next().ok_or("No dependency specified")?;
+        match args.next() {
+            Some(version) => {
+                self.repl
+                    .cargo
+                    .set_dependency_version(dep, version, self.repl.toolchain())?
+            }
+            None => self
+                .repl
+                .cargo
+                .update_dependency(dep, self.repl.toolchain())?,
+        }
+        self.dependencies_changed()?;
+        success!()
+    }
+
+    fn features(&mut self, buffer: String) -> Result<PrintQueue> {
+        let mut args = buffer.split_whitespace().skip(1);
+        let dep = args.next().ok_or("No dependency specified")?;
+        let features: Vec<&str> = args.collect();
+
+        let dependency = self
+            .repl
+            .cargo
+            .dependencies(self.repl.toolchain())?
+            .into_iter()
+            .find(|dependency| dependency.name == dep)
+            .ok_or_else(|| format!("`{dep}` is not a dependency"))?;
+        if features.is_empty() {
+            // Show the available features, the enabled ones are highlighted
+            let mut print_queue = PrintQueue::default();
+            if dependency.available_features.is_empty() {
+                print_queue.push(PrinterItem::Str("No features", self.options.ok_color));
+            }
+            for feature in dependency.available_features {
+                let color = if dependency.features.contains(&feature) {
+                    self.options.ok_color
+                } else {
+                    Color::White
+                };
+                print_queue.push(PrinterItem::String(feature, color));
+                print_queue.push(PrinterItem::Char(' ', color));
+            }
+            print_queue.add_new_line(1);
+            return Ok(print_queue);
+        }
+
+        // `+feature` enables, `-feature` disables and `feature` toggles
+        let features: Vec<(&str, bool)> = features
+            .into_iter()
+            .map(|feature| {
+                if let Some(feature) = feature.strip_prefix('+') {
+                    (feature, true)
+                } else if let Some(feature) = feature.strip_prefix('-') {
+                    (feature, false)
+                } else {
+                    (feature, !dependency.features.iter().any(|f| f == feature))
+                }
+            })
+            .collect();
+        self.repl
+            .cargo
+            .set_dependency_features(dep, &features, self.repl.toolchain())?;
+        self.dependencies_changed()?;
+        success!()
+    }
+
+    /// Rebuild the repl in the background and let rust-analyzer know about the new dependencies
+    fn dependencies_changed(&mut self) -> Result<()> {
+        let mut build = self.repl.build()?;
+        std::thread::spawn(move || build.wait());
+
+        if let Some(ra) = self.completer.as_mut() {
+            ra.rust_analyzer.reload_workspace()?;
+        }
+        Ok(())
+    }
+
     fn color(&mut self, buffer: String) -> Result<PrintQueue> {
         let mut buffer = buffer.split_whitespace().skip(1).peekable();
 
@@ -341,7 +579,7 @@ impl IRust {
         let code = std::fs::read_to_string(path)?;
 
         // build the code
-        let EvalResult { output, status } = self.repl.eval_build(code.clone())?;
+        let EvalResult { output, status, .. } = self.repl.eval_build(code.clone())?;
 
         if !status.success() {
             Ok(format_err_printqueue(
@@ -355,53 +593,137 @@ impl IRust {
         }
     }
 
-    fn show_type(&mut self) -> Result<PrintQueue> {
-        // TODO
-        // We should probably use the `Any` trait instead of the current method
-        // Current method might break with compiler updates
-        // On the other hand `Any` is more limited
+    fn save(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = path_argument(&buffer, ":save").ok_or("No path specified")?;
+        Session::new(&self.repl, &self.options)?.save(&path)?;
+        success!()
+    }
 
-        const TYPE_FOUND_MSG: &str = "expected `()`, found ";
-        const EMPTY_TYPE_MSG: &str = "dev [unoptimized + debuginfo]";
+    fn restore(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = path_argument(&buffer, ":restore").ok_or("No path specified")?;
+        self.restore_session(path)
+    }
 
-        let variable = self
-            .buffer
-            .to_string()
-            .trim_start_matches(":type")
-            .to_string();
-        let mut raw_out = String::new();
+    pub fn restore_session(&mut self, path: PathBuf) -> Result<PrintQueue> {
+        let session = Session::load(&path)?;
+        if session.persistent && self.options.streaming {
+            return Err(
+                "The session uses the persistent mode, it can't be used with the streaming mode"
+                    .into(),
+            );
+        }
 
-        let toolchain = self.options.toolchain;
-        let get_type = format!("let _:() = {variable};");
+        let mut options = self.options.clone();
+        options.toolchain = session.toolchain.clone();
+        options.executor = session.executor;
+        options.main_result = session.main_result;
+        options.edition = session.edition;
+        options.compile_mode = session.compile_mode;
+        options.evaluator = session.evaluator.clone();
+        options.persistent = session.persistent;
+        options.sandbox = session.sandbox;
+        options.profile = session.profile.clone();
+        options.limits = session.limits.clone();
+
+        // Start from a fresh repl, so the settings that can only be set at creation (edition) are applied
+        // The new repl shares the project of the current one, its Cargo.toml is put back if the session
+        // can't be applied
+        let cargo_toml_file = self.repl.cargo.paths.cargo_toml_file.clone();
+        let cargo_toml = std::fs::read_to_string(&cargo_toml_file)?;
+        let toolchain = Self::session_toolchain(&options);
+        let repl = (|| -> Result<Repl> {
+            let mut repl =
+                Self::new_repl(&options, toolchain.as_ref().cloned().unwrap_or_default())?;
+            session.write_dependencies(&repl.cargo.paths.cargo_toml_file)?;
+            repl.set_sandbox(options.sandbox)?;
+            repl.set_persistent(options.persistent)?;
+            repl.hard_load(session.body, session.cursor);
+            for test in session.tests {
+                repl.add_test(test)?;
+            }
+            Ok(repl)
+        })();
+        let repl = match repl {
+            Ok(repl) => repl,
+            Err(e) => {
+                std::fs::write(&cargo_toml_file, cargo_toml)?;
+                return Err(e);
+            }
+        };
+        self.repl = repl;
+        self.options = options;
 
-        let cargo = self.repl.cargo.clone();
-        self.repl.eval_in_tmp_repl(get_type, |_| -> Result<()> {
-            let (_status, out) = cargo.cargo_build_output(false, false, toolchain)?;
-            raw_out = out;
-            Ok(())
-        })?;
+        // build the code (this also fetches the dependencies)
+        let EvalResult { output, status, .. } = self.repl.eval_build(String::new())?;
 
-        let var_type = if raw_out.contains(TYPE_FOUND_MSG) {
-            raw_out
-                .lines()
-                // there is a case where there could be 2 found msg
-                // the second one is more detailed
-                .rev()
-                .find(|l| l.contains("found"))
-                // safe
-                .unwrap()
-                .rsplit("found ")
-                .next()
-                // safe
-                .unwrap()
-                .to_string()
-        } else if raw_out.contains(EMPTY_TYPE_MSG) {
-            "()".into()
+        if let Some(ra) = self.completer.as_mut() {
+            ra.rust_analyzer.reload_workspace()?;
+        }
+
+        if !status.success() {
+            Ok(format_err_printqueue(
+                &output,
+                self.options.show_warnings,
+                &self.repl.cargo.name,
+            ))
+        } else if let Err(e) = toolchain {
+            print_queue!(
+                format!("{e}\nUsing the default toolchain instead"),
+                self.options.err_color
+            )
         } else {
-            "Unknown".into()
-        };
+            success!()
+        }
+    }
 
-        print_queue!(var_type, self.options.ok_color)
+    fn export(&mut self, buffer: String) -> Result<PrintQueue> {
+        let path = path_argument(&buffer, ":export").ok_or("No path specified")?;
+        self.repl.export(&path)?;
+        success!()
+    }
+
+    /// The fixed input is put in the next prompt by the engine (see `Command::ApplyFix`)
+    fn fix(&mut self) -> Result<PrintQueue> {
+        self.fixed_input()?;
+        Ok(PrintQueue::default())
+    }
+
+    /// The last failed input with the compiler suggestions applied
+    pub fn fixed_input(&self) -> Result<String> {
+        let (input, diagnostics) = self.last_failure.as_ref().ok_or("No failed input to fix")?;
+        diagnostics::apply_suggestions(input, diagnostics)
+            .ok_or_else(|| "No compiler suggestions to apply".into())
+    }
+
+    fn show_type(&mut self) -> Result<PrintQueue> {
+        let expression = self
+            .buffer
+            .to_string()
+            .trim_start_matches(":type")
+            .trim()
+            .to_string();
+        if expression.is_empty() {
+            return Err("No expression specified".into());
+        }
+        if self.completer.is_some() {
+            if let Ok(Some(var_type)) = self.ra_type(&expression) {
+                return print_queue!(var_type, self.options.ok_color);
+            }
+        }
+
+        self.before_compiling_hook();
+        let result = self.repl.type_of(expression);
+        self.after_compiling_hook();
+        let result = result?;
+        if !result.status.success() {
+            return Ok(format_check_output(
+                result,
+                self.options.show_warnings,
+                &self.repl.cargo.name,
+            )
+            .expect("the build failed"));
+        }
+        print_queue!(result.output, self.options.ok_color)
     }
 
     fn run_cmd(&mut self, buffer: String) -> Result<PrintQueue> {
@@ -413,11 +735,11 @@ impl IRust {
             std::process::Command::new("cmd")
                 .arg("/C")
                 .arg(cmd.next().unwrap_or_default())
-                .args(&cmd.collect::<Vec<&str>>())
+                .args(cmd.collect::<Vec<&str>>())
                 .output()?
         } else {
             std::process::Command::new(cmd.next().unwrap_or_default())
-                .args(&cmd.collect::<Vec<&str>>())
+                .args(cmd.collect::<Vec<&str>>())
                 .output()?
         })
         .trim()
@@ -448,45 +770,27 @@ impl IRust {
         // This trimmed buffer should not be inserted nor evaluated
         let buffer_trimmed = buffer.trim();
 
+        // Resources used by the evaluated program
+        let mut usage = None;
+
         let mut print_queue = if buffer_trimmed.is_empty() {
             PrintQueue::default()
-        } else if buffer_trimmed.ends_with(';')
-            || self.options.auto_insert_semicolon
-                // These patterns are used to detect statements that don't require to be terminated with ';'
-                // Note: `loop` can return a value so we don't add it here, exp: `loop {break 4}`
-                && match buffer_trimmed
-                    .split_whitespace()
-                    .collect::<Vec<_>>()
-                    .as_slice()
-                {
-                    // async fn|const fn|unsafe fn
-                    [_, "fn", ..]
-                    | ["fn", ..]
-                    | ["enum", ..]
-                    | ["struct", ..]
-                    | ["trait", ..]
-                    | ["impl", ..]
-                    | ["pub", ..]
-                    | ["extern", ..]
-                    | ["macro", ..] => true,
-                    | ["macro_rules!", ..] => true,
-                    // attribute exp:
-                    // #[derive(Debug)]
-                    // struct B{}
-                    [tag, ..] if tag.starts_with('#') => true,
-                    _ => false,
-                }
-        {
+        } else if test_runner::is_test(buffer_trimmed) {
+            self.add_test(buffer)?
+        } else if is_statement(buffer_trimmed, self.options.auto_insert_semicolon) {
             let mut print_queue = PrintQueue::default();
 
             let mut insert_flag = true;
 
             if self.options.check_statements {
                 self.before_compiling_hook();
-                let check_result = self.repl.eval_check(buffer.clone());
+                let check_result = self.repl.eval_check(buffer.clone())?;
                 self.after_compiling_hook();
+                if !check_result.status.success() {
+                    self.last_failure = Some((buffer.clone(), check_result.diagnostics.clone()));
+                }
                 if let Some(mut e) = format_check_output(
-                    check_result?.output,
+                    check_result,
                     self.options.show_warnings,
                     &self.repl.cargo.name,
                 ) {
@@ -505,34 +809,42 @@ impl IRust {
         } else {
             let mut outputs = PrintQueue::default();
 
+            // Miri doesn't run the program attached to a terminal
+            let streaming =
+                self.options.streaming && !matches!(self.options.compile_mode, CompileMode::Miri);
+            // Custom evaluators are kept as is, the streamed output is shown as the program prints it
+            let typed = self.options.show_types
+                && self.options.evaluator == *DEFAULT_EVALUATOR
+                && !streaming;
+            let output_prompt = streaming.then(|| self.get_output_prompt());
             self.before_compiling_hook();
+            let mut terminal =
+                output_prompt.map(|prompt| StreamingTerminal::new(&mut self.printer, prompt));
             let result = self.repl.eval_with_configuration(EvalConfig {
-                input: buffer,
+                input: buffer.clone(),
                 interactive_function: Some(ctrlc_cancel),
                 color: true,
-                evaluator: &self.options.evaluator,
+                evaluator: if typed {
+                    &*TYPED_EVALUATOR
+                } else {
+                    &self.options.evaluator
+                },
                 compile_mode: self.options.compile_mode,
+                terminal: terminal
+                    .as_mut()
+                    .map(|terminal| terminal as &mut dyn Terminal),
             });
+            let streamed = terminal.is_some_and(|terminal| terminal.shown());
             self.after_compiling_hook();
-            let EvalResult { output, status } = result?;
-
-            // Save output if it was a success
-            if status.success() {
-                self.global_variables.set_last_output(output.clone());
-            }
-
-            let output_prompt = self.get_output_prompt();
-            if let Some(mut eval_output) = format_eval_output(
-                status,
-                output,
-                output_prompt,
-                self.options.show_warnings,
-                &self.repl.cargo.name,
-                self.options.new_lines_after_output,
-            ) {
-                outputs.append(&mut eval_output);
-            }
-
+            let mut result = result?;
+            usage = result.usage.take().map(|usage| (usage, result.status));
+            outputs.append(&mut self.eval_output(
+                buffer,
+                result,
+                typed,
+                self.options.compile_mode,
+                streamed,
+            ));
             outputs
         };
 
@@ -540,8 +852,9 @@ impl IRust {
         if let Some(timer) = timer {
             let time = PrinterItem::String(
                 format!(
-                    "[-] compiling took: {} millisseconds",
-                    timer.elapsed().as_millis()
+                    "[-] compiling took: {} millisseconds ({} mode)",
+                    timer.elapsed().as_millis(),
+                    self.options.compile_mode
                 ),
                 Color::Magenta,
             );
@@ -549,9 +862,83 @@ impl IRust {
             print_queue.push(time);
         }
 
+        self.push_usage(&mut print_queue, usage);
+
         Ok(print_queue)
     }
 
+    /// Show the result of an evaluation, its output is saved if it was a success
+    ///
+    /// If the output of the program was `streamed`, only the failure report is left to show
+    fn eval_output(
+        &mut self,
+        buffer: String,
+        mut result: EvalResult,
+        typed: bool,
+        compile_mode: CompileMode,
+        streamed: bool,
+    ) -> PrintQueue {
+        let mut outputs = PrintQueue::default();
+
+        // Save output if it was a success
+        if result.status.success() {
+            let typed_output = typed
+                .then(|| type_name::split_typed_output(&result.output))
+                .flatten()
+                .map(|(value, type_name)| (value.to_string(), type_name));
+            if let Some((value, type_name)) = typed_output {
+                self.global_variables.set_last_output(value.clone());
+                // `()` is not printed
+                result.output = if value.lines().last() == Some("()") {
+                    value
+                } else {
+                    format!("{value}: {type_name}")
+                };
+            } else {
+                self.global_variables.set_last_output(result.output.clone());
+            }
+        } else if !result.diagnostics.is_empty() {
+            self.last_failure = Some((buffer, result.diagnostics.clone()));
+        }
+
+        if streamed {
+            outputs.add_new_line(1);
+            if let Some(failure) = result.runtime_failure {
+                outputs.push(PrinterItem::String(failure, Color::Red));
+            }
+        } else if let Some(mut eval_output) = format_eval_output(
+            result,
+            self.get_output_prompt(),
+            self.options.show_warnings,
+            &self.repl.cargo.name,
+            self.options.new_lines_after_output,
+            compile_mode,
+        ) {
+            outputs.append(&mut eval_output);
+        }
+        outputs
+    }
+
+    /// Print the resources used by the evaluated program and how it exited, if `:resource_usage` is on
+    fn push_usage(
+        &self,
+        print_queue: &mut PrintQueue,
+        usage: Option<(ResourceUsage, process::ExitStatus)>,
+    ) {
+        if let Some((usage, status)) = usage.filter(|_| self.options.resource_usage) {
+            let exit = if usage.timed_out {
+                "timed out".to_string()
+            } else {
+                runtime::exit_reason(status)
+            };
+            print_queue.add_new_line(1);
+            print_queue.push(PrinterItem::String(
+                format!("[-] {usage}, process {exit}"),
+                Color::Magenta,
+            ));
+        }
+    }
+
     pub fn sync(&mut self) -> Result<PrintQueue> {
         match self.repl.update_from_extern_main_file() {
             Ok(_) => success!(),
@@ -675,55 +1062,195 @@ impl IRust {
         ",
         );
 
-        let toolchain = self.options.toolchain;
-        let mut raw_out = String::new();
-        let mut status = None;
-
-        let cargo = self.repl.cargo.clone();
-        self.repl.eval_in_tmp_repl(time, |_| -> Result<()> {
-            let (s, out) = cargo.cargo_run(true, release, toolchain, Some(ctrlc_cancel))?;
-            raw_out = out;
-            status = Some(s);
-            Ok(())
+        let compile_mode = if release {
+            CompileMode::Release
+        } else {
+            CompileMode::Debug
+        };
+        let result = self.repl.eval_with_configuration(EvalConfig {
+            input: time,
+            interactive_function: Some(ctrlc_cancel),
+            color: true,
+            evaluator: &[String::new(), String::new()],
+            compile_mode,
+            terminal: None,
         })?;
 
         let output_prompt = self.get_output_prompt();
-        // safe unwrap
         Ok(format_eval_output(
-            status.unwrap(),
-            raw_out,
+            result,
             output_prompt,
             self.options.show_warnings,
             &self.repl.cargo.name,
             self.options.new_lines_after_output,
+            compile_mode,
         )
         .ok_or("failed to bench function")?)
     }
 
-    fn bench(&mut self) -> Result<PrintQueue> {
+    fn bench(&mut self, buffer: String) -> Result<PrintQueue> {
+        let expr = buffer
+            .strip_prefix(":bench")
+            .expect("already checked")
+            .trim();
+        if !expr.is_empty() {
+            return self.bench_expr(expr);
+        }
+
+        if self.repl.is_sandboxed() {
+            return Err("The benchmarks can't run in the sandbox".into());
+        }
         //make sure we have the latest changes in main.rs
         self.repl.write()?;
         let out = self
             .repl
             .cargo
-            .cargo_bench(self.options.toolchain)?
+            .cargo_bench(self.repl.toolchain())?
             .trim()
             .to_owned();
 
         print_queue!(out, self.options.eval_color)
     }
 
-    fn asm(&mut self, buffer: String) -> Result<PrintQueue> {
-        let fnn = buffer.strip_prefix(":asm").expect("already checked").trim();
-        if fnn.is_empty() {
-            return Err("No function specified".into());
+    /// Check the test like a statement, then add it to the repl tests
+    fn add_test(&mut self, buffer: String) -> Result<PrintQueue> {
+        if self.options.check_statements {
+            // `#[test]` functions are removed outside of test builds, blank the attribute (keeping the columns) so
+            // the function is checked
+            let check_buffer = buffer.replacen("#[test]", "       ", 1);
+            self.before_compiling_hook();
+            let check_result = self.repl.eval_check(check_buffer)?;
+            self.after_compiling_hook();
+            if !check_result.status.success() {
+                self.last_failure = Some((buffer.clone(), check_result.diagnostics.clone()));
+            }
+            if let Some(e) = format_check_output(
+                check_result,
+                self.options.show_warnings,
+                &self.repl.cargo.name,
+            ) {
+                return Ok(e);
+            }
         }
+        self.repl.add_test(buffer)?;
+        Ok(PrintQueue::default())
+    }
 
-        let asm = self
-            .repl
-            .with_lib(|| self.repl.cargo.cargo_asm(fnn, self.options.toolchain))??;
+    fn test(&mut self, buffer: String) -> Result<PrintQueue> {
+        let filter = buffer
+            .strip_prefix(":test")
+            .expect("already checked")
+            .trim();
+
+        self.before_compiling_hook();
+        let report = self.repl.test(
+            (!filter.is_empty()).then_some(filter),
+            true,
+            Some(ctrlc_cancel),
+        );
+        self.after_compiling_hook();
+        let report = report?;
+        if let Some(build_failure) = report.build_failure {
+            return print_queue!(
+                format_build_err(
+                    &build_failure,
+                    self.options.show_warnings,
+                    &self.repl.cargo.name
+                ),
+                Color::Red
+            );
+        }
+
+        let outcome_color = |outcome| match outcome {
+            TestOutcome::Passed => self.options.ok_color,
+            TestOutcome::Failed => Color::Red,
+            TestOutcome::Ignored => Color::Yellow,
+        };
+        let mut print_queue = PrintQueue::default();
+        for test in &report.tests {
+            let outcome = match test.outcome {
+                TestOutcome::Passed => "ok",
+                TestOutcome::Failed => "FAILED",
+                TestOutcome::Ignored => "ignored",
+            };
+            print_queue.push(PrinterItem::String(
+                format!("test {} ... ", test.name),
+                Color::White,
+            ));
+            print_queue.push(PrinterItem::String(
+                outcome.to_string(),
+                outcome_color(test.outcome),
+            ));
+            print_queue.add_new_line(1);
+        }
+        // Captured output
+        for test in report.tests.iter().filter(|test| !test.output.is_empty()) {
+            print_queue.add_new_line(1);
+            print_queue.push(PrinterItem::String(
+                format!("---- {} ----", test.name),
+                outcome_color(test.outcome),
+            ));
+            print_queue.add_new_line(1);
+            print_queue.push(PrinterItem::String(test.output.clone(), Color::White));
+            print_queue.add_new_line(1);
+        }
+
+        let count = |outcome| {
+            report
+                .tests
+                .iter()
+                .filter(|test| test.outcome == outcome)
+                .count()
+        };
+        let failed = count(TestOutcome::Failed);
+        if !report.tests.is_empty() {
+            print_queue.add_new_line(1);
+        }
+        print_queue.push(PrinterItem::String(
+            format!(
+                "test result: {} passed; {failed} failed; {} ignored",
+                count(TestOutcome::Passed),
+                count(TestOutcome::Ignored)
+            ),
+            if failed == 0 {
+                self.options.ok_color
+            } else {
+                Color::Red
+            },
+        ));
+        print_queue.add_new_line(1);
+        Ok(print_queue)
+    }
+
+    fn bench_expr(&mut self, expr: &str) -> Result<PrintQueue> {
+        self.before_compiling_hook();
+        let result = self.repl.eval_with_configuration(EvalConfig {
+            input: expr,
+            interactive_function: Some(ctrlc_cancel),
+            color: true,
+            evaluator: &bench::evaluator(),
+            compile_mode: CompileMode::Release,
+            terminal: None,
+        });
+        self.after_compiling_hook();
+        let result = result?;
+        if !result.status.success() {
+            return Ok(format_eval_output(
+                result,
+                self.get_output_prompt(),
+                self.options.show_warnings,
+                &self.repl.cargo.name,
+                self.options.new_lines_after_output,
+                CompileMode::Release,
+            )
+            .ok_or("failed to bench the expression")?);
+        }
 
-        print_queue!(asm, self.options.eval_color)
+        let estimate =
+            bench::Estimate::from_output(&result.output).ok_or("failed to bench the expression")?;
+        let report = estimate.report(self.bench_history.get(expr));
+        self.bench_history.insert(expr.to_string(), estimate);
+        print_queue!(report, self.options.eval_color)
     }
 
     fn executor(&mut self, buffer: String) -> Result<PrintQueue> {
@@ -895,6 +1422,241 @@ impl IRust {
         }
     }
 
+    fn persistent(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":persistent")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        match buffer.len() {
+            0 => {
+                print_queue!(self.repl.is_persistent().to_string(), Color::Blue)
+            }
+            1 => match buffer[0].to_lowercase().as_str() {
+                "on" if self.options.streaming => {
+                    Err("The persistent mode can't be used with the streaming mode".into())
+                }
+                "on" => {
+                    self.repl.set_persistent(true)?;
+                    self.options.persistent = true;
+                    success!()
+                }
+                "off" => {
+                    self.repl.set_persistent(false)?;
+                    self.options.persistent = false;
+                    success!()
+                }
+                _ => Err("Invalid argument (only accepts on/off)".into()),
+            },
+            _ => Err("Invalid number of arguments".into()),
+        }
+    }
+
+    fn show_types(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":show_types")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        match buffer.as_slice() {
+            [] => print_queue!(self.options.show_types.to_string(), Color::Blue),
+            [value] => {
+                self.options.show_types = match value.to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Invalid argument (only accepts on/off)".into()),
+                };
+                success!()
+            }
+            _ => Err("Invalid number of arguments".into()),
+        }
+    }
+
+    fn resource_usage(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":resource_usage")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        match buffer.as_slice() {
+            [] => print_queue!(self.options.resource_usage.to_string(), Color::Blue),
+            [value] => {
+                self.options.resource_usage = match value.to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Invalid argument (only accepts on/off)".into()),
+                };
+                success!()
+            }
+            _ => Err("Invalid number of arguments".into()),
+        }
+    }
+
+    fn sandbox(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":sandbox")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        match buffer.as_slice() {
+            [] => print_queue!(self.repl.is_sandboxed().to_string(), Color::Blue),
+            [value] => {
+                let sandbox = match value.to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Invalid argument (only accepts on/off)".into()),
+                };
+                self.repl.set_sandbox(sandbox)?;
+                self.options.sandbox = sandbox;
+                success!()
+            }
+            _ => Err("Invalid number of arguments".into()),
+        }
+    }
+
+    fn streaming(&mut self, buffer: String) -> Result<PrintQueue> {
+        let buffer: Vec<&str> = buffer
+            .strip_prefix(":streaming")
+            .expect("already checked")
+            .split_whitespace()
+            .collect();
+        match buffer.as_slice() {
+            [] => print_queue!(self.options.streaming.to_string(), Color::Blue),
+            [value] => {
+                self.options.streaming = match value.to_lowercase().as_str() {
+                    "on" if !cfg!(unix) => {
+                        return Err("The streaming mode is only supported on unix".into())
+                    }
+                    "on" if self.repl.is_persistent() => {
+                        return Err("The streaming mode can't be used in persistent mode".into())
+                    }
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("Invalid argument (only accepts on/off)".into()),
+                };
+                success!()
+            }
+            _ => Err("Invalid number of arguments".into()),
+        }
+    }
+
+    fn bg(&mut self, buffer: String) -> Result<PrintQueue> {
+        let input = buffer.strip_prefix(":bg").expect("already checked").trim();
+        if input.is_empty() {
+            return Err("No expression specified".into());
+        }
+        let typed = self.options.show_types && self.options.evaluator == *DEFAULT_EVALUATOR;
+        let job = self.repl.spawn_job(
+            input,
+            true,
+            if typed {
+                &*TYPED_EVALUATOR
+            } else {
+                &self.options.evaluator
+            },
+            self.options.compile_mode,
+        )?;
+        let id = self.next_job_id;
+        self.next_job_id += 1;
+        self.jobs.insert(id, job);
+        print_queue!(format!("[{id}] {input}"), Color::Blue)
+    }
+
+    fn jobs(&mut self) -> Result<PrintQueue> {
+        if self.jobs.is_empty() {
+            return print_queue!("No jobs".to_string(), Color::Blue);
+        }
+        let mut print_queue = PrintQueue::default();
+        for (id, job) in &mut self.jobs {
+            let status = job.status();
+            let color = match status {
+                JobStatus::Running => Color::Yellow,
+                JobStatus::Done => Color::Green,
+                JobStatus::Failed | JobStatus::Killed => Color::Red,
+            };
+            print_queue.push(PrinterItem::String(format!("[{id}] "), Color::Blue));
+            print_queue.push(PrinterItem::String(format!("{status:<8}"), color));
+            print_queue.push(PrinterItem::String(
+                format!(
+                    "{:>9.2}s  {}",
+                    job.elapsed().as_secs_f64(),
+                    job.input.lines().next().unwrap_or_default()
+                ),
+                Color::Blue,
+            ));
+            print_queue.add_new_line(1);
+        }
+        Ok(print_queue)
+    }
+
+    /// Wait for a job (the last one by default) and show its output, ctrl-c kills it
+    fn fg(&mut self, buffer: String) -> Result<PrintQueue> {
+        let id = match buffer.split_whitespace().nth(1) {
+            Some(id) => id.parse().map_err(|_| format!("Invalid job id `{id}`"))?,
+            None => *self.jobs.keys().next_back().ok_or("No jobs")?,
+        };
+        let mut job = self.jobs.remove(&id).ok_or(format!("No job [{id}]"))?;
+
+        self.before_compiling_hook();
+        while job.status() == JobStatus::Running {
+            if crossterm::event::poll(std::time::Duration::from_millis(10))? {
+                if let Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) = crossterm::event::read()?
+                {
+                    job.kill();
+                }
+            }
+        }
+        let typed = job.evaluator() == &TYPED_EVALUATOR[..];
+        let compile_mode = job.compile_mode();
+        let input = job.input.clone();
+        let result = job.wait();
+        self.after_compiling_hook();
+        let mut result = result?;
+        let usage = result.usage.take().map(|usage| (usage, result.status));
+
+        let mut print_queue = PrintQueue::default();
+        print_queue.push(PrinterItem::String(format!("[{id}] {input}"), Color::Blue));
+        print_queue.add_new_line(1);
+        print_queue.append(&mut self.eval_output(input, result, typed, compile_mode, false));
+        self.push_usage(&mut print_queue, usage);
+        Ok(print_queue)
+    }
+
+    fn kill(&mut self, buffer: String) -> Result<PrintQueue> {
+        let id = buffer.split_whitespace().nth(1).ok_or("No job specified")?;
+        let id: usize = id.parse().map_err(|_| format!("Invalid job id `{id}`"))?;
+        // The job stops its program when it's dropped
+        self.jobs
+            .remove(&id)
+            .ok_or(format!("No job [{id}]"))?
+            .kill();
+        success!()
+    }
+
+    /// The jobs that finished since the last check, they stay in the list until `:fg` or `:kill`
+    pub fn finished_jobs(&mut self) -> PrintQueue {
+        let mut print_queue = PrintQueue::default();
+        for (id, job) in &mut self.jobs {
+            if job.poll() {
+                print_queue.push(PrinterItem::String(
+                    format!(
+                        "[{id}] {} {}",
+                        job.status(),
+                        job.input.lines().next().unwrap_or_default()
+                    ),
+                    Color::Magenta,
+                ));
+                print_queue.add_new_line(1);
+            }
+        }
+        print_queue
+    }
+
     fn compile_mode(&mut self, buffer: String) -> Result<PrintQueue> {
         let mode = buffer
             .strip_prefix(":compile_mode")
@@ -905,11 +1667,64 @@ impl IRust {
             return print_queue!(self.options.compile_mode.to_string(), Color::Blue);
         }
 
-        self.options.compile_mode = CompileMode::from_str(mode)?;
+        let compile_mode = CompileMode::from_str(mode)?;
+        if let Err(e) = compile_mode.check_available(&self.repl.toolchain()) {
+            self.options.compile_mode = CompileMode::Debug;
+            return print_queue!(format!("{e}\nUsing debug mode instead"), Color::Red);
+        }
+        self.options.compile_mode = compile_mode;
+        success!()
+    }
+
+    fn profile(&mut self, buffer: String) -> Result<PrintQueue> {
+        let args = buffer
+            .strip_prefix(":profile")
+            .expect("already checked")
+            .trim();
+        let profile = match args.split_once(char::is_whitespace) {
+            // The value is the rest of the input, so `rustflags` can contain spaces
+            Some((key, value)) => {
+                let mut profile = self.repl.profile().clone();
+                profile.set(key, value.trim())?;
+                profile
+            }
+            None if args.is_empty() => {
+                return print_queue!(self.repl.profile().to_string(), Color::Blue)
+            }
+            None if args == "reset" => Profile::default(),
+            None => return Err("Usage: :profile [reset | <setting> <value>]".into()),
+        };
+        self.repl.set_profile(profile.clone())?;
+        self.options.profile = profile;
+        success!()
+    }
+
+    fn limits(&mut self, buffer: String) -> Result<PrintQueue> {
+        let args = buffer
+            .strip_prefix(":limits")
+            .expect("already checked")
+            .trim();
+        let limits = match args.split_once(char::is_whitespace) {
+            Some((key, value)) => {
+                let mut limits = self.repl.limits().clone();
+                limits.set(key, value.trim())?;
+                limits
+            }
+            None if args.is_empty() => {
+                return print_queue!(self.repl.limits().to_string(), Color::Blue)
+            }
+            None if args == "reset" => Limits::default(),
+            None => return Err("Usage: :limits [reset | <limit> <value>]".into()),
+        };
+        self.repl.set_limits(limits.clone())?;
+        self.options.limits = limits;
         success!()
     }
 
     fn dbg(&mut self, buffer: String) -> Result<PrintQueue> {
+        if self.repl.is_sandboxed() {
+            return Err("The debugger can't be used in the sandbox".into());
+        }
         let expression = buffer
             .strip_prefix(":dbg")
             .expect("already checked")
@@ -933,9 +1748,10 @@ impl IRust {
 
         let expr_line_num = self.repl.lines_count();
 
+        let toolchain = self.repl.toolchain();
         let cargo = self.repl.cargo.clone();
         self.repl.eval_in_tmp_repl(expression, |_| -> Result<()> {
-            let (status, _out) = cargo.cargo_build_output(true, false, self.options.toolchain)?;
+            let (status, _out) = cargo.cargo_build_output(true, false, toolchain.clone())?;
             if !status.success() {
                 return Err("Failed to execute expression".into());
             }
@@ -1050,12 +1866,12 @@ impl IRust {
             let r = self.repl.with_lib(|| {
                 self.repl
                     .cargo
-                    .cargo_expand(Some(fnn), self.options.toolchain)
+                    .cargo_expand(Some(fnn), self.repl.toolchain())
             })??;
             print_queue!(r, Color::White)
         } else {
             print_queue!(
-                self.repl.cargo.cargo_expand(None, self.options.toolchain)?,
+                self.repl.cargo.cargo_expand(None, self.repl.toolchain())?,
                 Color::White
             )
         }
@@ -1066,3 +1882,59 @@ impl IRust {
         Ok(PrintQueue::default())
     }
 }
+
+/// Whether the input should be inserted in the repl instead of evaluated
+pub fn is_statement(buffer: &str, auto_insert_semicolon: bool) -> bool {
+    buffer.ends_with(';')
+        || auto_insert_semicolon
+            // These patterns are used to detect statements that don't require to be terminated with ';'
+            // Note: `loop` can return a value so we don't add it here, exp: `loop {break 4}`
+            && match buffer
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                // async fn|const fn|unsafe fn
+                [_, "fn", ..]
+                | ["fn", ..]
+                | ["enum", ..]
+                | ["struct", ..]
+                | ["trait", ..]
+                | ["impl", ..]
+                | ["pub", ..]
+                | ["extern", ..]
+                | ["macro", ..] => true,
+                | ["macro_rules!", ..] => true,
+                // attribute exp:
+                // #[derive(Debug)]
+                // struct B{}
+                [tag, ..] if tag.starts_with('#') => true,
+                _ => false,
+            }
+}
+
+fn is_git_url(dep: &str) -> bool {
+    ["https://", "http://", "ssh://", "git@"]
+        .iter()
+        .any(|prefix| dep.starts_with(prefix))
+}
+
+/// The rest of the line after `command`, so paths can contain spaces
+fn path_argument(buffer: &str, command: &str) -> Option<PathBuf> {
+    let path = buffer.strip_prefix(command)?.trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+#[test]
+fn path_argument_test() {
+    assert_eq!(
+        path_argument(":save session.toml", ":save"),
+        Some(PathBuf::from("session.toml"))
+    );
+    assert_eq!(
+        path_argument(":restore  /tmp/my sessions/a b.toml ", ":restore"),
+        Some(PathBuf::from("/tmp/my sessions/a b.toml"))
+    );
+    assert_eq!(path_argument(":save", ":save"), None);
+    assert_eq!(path_argument(":save   ", ":save"), None);
+}
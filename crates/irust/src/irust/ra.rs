@@ -1,5 +1,6 @@
 mod rust_analyzer;
 use self::rust_analyzer::RustAnalyzer;
+pub use self::rust_analyzer::{Diagnostic, Location, Signature};
 
 use super::{
     highlight::{highlight, theme::Theme},
@@ -10,6 +11,7 @@ use crossterm::{style::Color, terminal::ClearType};
 use irust_repl::Repl;
 use printer::printer::{PrintQueue, Printer, PrinterItem};
 use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
 
 pub enum Cycle {
@@ -23,9 +25,11 @@ pub struct Completer {
     // suggestions: (Name, definition)
     suggestions: Vec<(String, String)>,
     suggestion_idx: usize,
-    cmds: [String; 30],
+    cmds: [String; 56],
     update_lock: bool,
     pub active_suggestion: Option<String>,
+    /// The input the live diagnostics were last shown for
+    pub diagnosed_input: Option<String>,
 }
 
 impl Completer {
@@ -42,10 +46,17 @@ impl Completer {
             "exit".to_string(),
             "quit".to_string(),
             "edit".to_string(),
+            "edition".to_string(),
             "add".to_string(),
+            "deps".to_string(),
+            "rm".to_string(),
+            "update".to_string(),
+            "features".to_string(),
             "load".to_string(),
             "reload".to_string(),
             "type".to_string(),
+            "doc".to_string(),
+            "def".to_string(),
             "del".to_string(),
             "dbg".to_string(),
             "cd".to_string(),
@@ -58,12 +69,31 @@ impl Completer {
             "time".to_string(),
             "bench".to_string(),
             "asm".to_string(),
+            "llvm_ir".to_string(),
+            "mir".to_string(),
             "expand".to_string(),
             "executor".to_string(),
             "evaluator".to_string(),
             "scripts".to_string(),
             "compile_time".to_string(),
             "compile_mode".to_string(),
+            "profile".to_string(),
+            "limits".to_string(),
+            "resource_usage".to_string(),
+            "sandbox".to_string(),
+            "streaming".to_string(),
+            "bg".to_string(),
+            "jobs".to_string(),
+            "fg".to_string(),
+            "kill".to_string(),
+            "test".to_string(),
+            "persistent".to_string(),
+            "show_types".to_string(),
+            "save".to_string(),
+            "restore".to_string(),
+            "export".to_string(),
+            "fix".to_string(),
+            "explain".to_string(),
         ];
 
         Some(Completer {
@@ -73,6 +103,7 @@ impl Completer {
             cmds,
             update_lock: false,
             active_suggestion: None,
+            diagnosed_input: None,
             rust_analyzer,
         })
     }
@@ -166,6 +197,16 @@ impl Completer {
         Ok(())
     }
 
+    /// Names of the suggestions for `buffer` (the input up to the cursor)
+    pub fn complete(&mut self, buffer: String, repl: &mut Repl) -> Result<Vec<String>> {
+        self.show_suggestions_inner(buffer, repl)?;
+        Ok(self
+            .suggestions
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+
     fn show_suggestions_inner(&mut self, buffer: String, repl: &mut Repl) -> Result<()> {
         if buffer.starts_with(':') {
             // Auto complete IRust commands
@@ -194,7 +235,6 @@ impl Completer {
             let buf_ref = &buffer;
             repl.eval_in_tmp_repl(buffer.clone(), move |repl| -> Result<()> {
                 ra.complete_code_ra(&repl.cargo.paths.main_file, repl.body(), buf_ref)
-                    .map_err(From::from)
             })?;
         }
 
@@ -379,6 +419,92 @@ impl Completer {
         Ok(())
     }
 
+    /// Hover documentation of the symbol at `pos` (in chars) in `input`
+    pub fn hover(&mut self, repl: &mut Repl, input: &str, pos: usize) -> Result<Option<String>> {
+        self.with_input(repl, input, pos, |ra, main_file, position| {
+            ra.hover(main_file, position)
+        })
+    }
+
+    /// Signature of the call surrounding `pos` (in chars) in `input`
+    pub fn signature_help(
+        &mut self,
+        repl: &mut Repl,
+        input: &str,
+        pos: usize,
+    ) -> Result<Option<Signature>> {
+        self.with_input(repl, input, pos, |ra, main_file, position| {
+            ra.signature_help(main_file, position)
+        })
+    }
+
+    /// Definition of the symbol at `pos` (in chars) in `input`
+    pub fn definition(
+        &mut self,
+        repl: &mut Repl,
+        input: &str,
+        pos: usize,
+    ) -> Result<Option<Location>> {
+        self.with_input(repl, input, pos, |ra, main_file, position| {
+            ra.definition(main_file, position)
+        })
+    }
+
+    /// Diagnostics located in `input`, with their ranges in chars of `input`
+    pub fn diagnostics(
+        &mut self,
+        repl: &mut Repl,
+        input: &str,
+    ) -> Result<Vec<(Range<usize>, Diagnostic)>> {
+        let first_line = repl.cursor();
+        let lines: Vec<&str> = input.split('\n').collect();
+        let offset = |(line, character): (usize, usize)| -> Option<usize> {
+            let line = line.checked_sub(first_line)?;
+            let before: usize = lines
+                .get(..line)?
+                .iter()
+                .map(|line| line.chars().count() + 1)
+                .sum();
+            Some(before + character.min(lines.get(line)?.chars().count()))
+        };
+
+        let diagnostics =
+            self.with_input(repl, input, 0, |ra, main_file, _| ra.diagnostics(main_file))?;
+        Ok(diagnostics
+            .into_iter()
+            .filter_map(|diagnostic| {
+                let start = offset(diagnostic.range.start)?;
+                // Spans ending after the input are cut at its end
+                let end = offset(diagnostic.range.end).unwrap_or(input.chars().count());
+                Some((start..end.max(start + 1), diagnostic))
+            })
+            .collect())
+    }
+
+    /// Sync rust-analyzer with `input` inserted in the repl, and map `pos` to a position in `main.rs`
+    fn with_input<T>(
+        &mut self,
+        repl: &mut Repl,
+        input: &str,
+        pos: usize,
+        mut f: impl FnMut(&mut RustAnalyzer, &Path, (usize, usize)) -> Result<T>,
+    ) -> Result<T> {
+        let before: String = input.chars().take(pos).collect();
+        let line = repl.cursor() + StringTools::new_lines_count(&before);
+        let character = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count();
+
+        let ra = &mut self.rust_analyzer;
+        repl.eval_in_tmp_repl_without_io(input.to_string(), |repl| {
+            ra.document_did_change(&repl.cargo.paths.main_file, repl.body())?;
+            f(ra, &repl.cargo.paths.main_file, (line, character))
+        })
+    }
+
     pub fn lock_ra_update(&mut self) -> Result<()> {
         self.update_lock = true;
         Ok(())
@@ -2,10 +2,11 @@ use crate::irust::Result;
 use serde_json::{json, Value};
 use std::io::Write;
 use std::io::{BufRead, Read};
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
 use std::process::{Child, ChildStdin, ChildStdout};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::time::Duration;
 use std::{
     io::BufReader,
     path::Path,
@@ -14,6 +15,25 @@ use std::{
 
 static ID: AtomicUsize = AtomicUsize::new(1);
 
+pub struct Signature {
+    pub label: String,
+    /// Chars range of the active parameter in the label
+    pub active_parameter: Option<Range<usize>>,
+}
+
+pub struct Diagnostic {
+    /// (line, character) positions (starting at 0) in the document
+    pub range: Range<(usize, usize)>,
+    pub is_error: bool,
+    pub message: String,
+}
+
+pub struct Location {
+    pub path: PathBuf,
+    /// Lines range (starting at 0) of the whole definition
+    pub lines: RangeInclusive<usize>,
+}
+
 pub struct RustAnalyzer {
     _process: Child,
     stdin: ChildStdin,
@@ -31,16 +51,17 @@ impl RustAnalyzer {
         let mut stdout = BufReader::new(process.stdout.take().expect("piped"));
 
         // Send a "initialize" request to the language server
+        let initialize_id = ID.fetch_add(1, Ordering::SeqCst);
         let initialize_request = json!({
             "jsonrpc": "2.0",
-            "id":  ID.fetch_add(1, Ordering::SeqCst),
+            "id":  initialize_id,
             "method": "initialize",
             "params": {
               // TODO: make this configurable in irust config
               "initializationOptions": {
                   "checkOnSave": false,
                   "diagnostics": {
-                    "enable": false
+                    "enable": true
                   },
                   "completion": {
                     "privateEditable": {
@@ -52,6 +73,22 @@ impl RustAnalyzer {
                 "rootUri": format!("file://{}",root_uri.display()),
                 "capabilities": {
                     "textDocument": {
+                        "hover": {
+                            "contentFormat": ["markdown", "plaintext"]
+                        },
+                        "signatureHelp": {
+                            "signatureInformation": {
+                                "documentationFormat": ["plaintext"],
+                                "parameterInformation": {
+                                    "labelOffsetSupport": true
+                                }
+                            }
+                        },
+                        "definition": {
+                            "linkSupport": true
+                        },
+                        // Pull diagnostics, so they are only computed when requested
+                        "diagnostic": {},
                         "completion": {
                             "completionItem": {
                                 "documentationFormat": ["plaintext"]
@@ -75,7 +112,7 @@ impl RustAnalyzer {
         send_request(&mut stdin, &initialized_notification)?;
 
         // Wait for "initialize" response
-        let _initialize_response = read_response(&mut stdout)?;
+        let _initialize_response = read_response_to(&mut stdout, &mut stdin, initialize_id)?;
 
         // Send a "textDocument/didOpen" notification to the language server
         let did_open_notification = json!({
@@ -120,13 +157,7 @@ impl RustAnalyzer {
     }
 
     pub fn reload_workspace(&mut self) -> Result<()> {
-        let reload_msg = json!({
-            "jsonrpc": "2.0",
-            "id":  ID.fetch_add(1, Ordering::SeqCst),
-            "method": "rust-analyzer/reloadWorkspace",
-        });
-        send_request(&mut self.stdin, &reload_msg)?;
-        read_response(&mut self.stdout)?;
+        self.request("rust-analyzer/reloadWorkspace", Value::Null)?;
         Ok(())
     }
 
@@ -136,49 +167,260 @@ impl RustAnalyzer {
         uri: &Path,
         (line, character): (usize, usize),
     ) -> Result<Vec<String>> {
-        // Send a "textDocument/completion" request to the language server
-        let completion_request = json!({
-            "jsonrpc": "2.0",
-            "id":  ID.fetch_add(1, Ordering::SeqCst),
-            "method": "textDocument/completion",
-            "params": {
+        let result = self.request(
+            "textDocument/completion",
+            position_params(uri, (line, character)),
+        )?;
+        if let Some(items) = result.get("items") {
+            return Ok(items
+                .as_array()
+                .ok_or("ra items is not an array")?
+                .iter()
+                .filter_map(|item| item.get("filterText"))
+                .map(|item| item.to_string())
+                // remove quotes
+                .map(|item| item[1..item.len() - 1].to_owned())
+                .collect());
+        }
+
+        Ok(vec![])
+    }
+
+    /// Hover documentation (markdown) of the symbol at the given position
+    pub fn hover(&mut self, uri: &Path, position: (usize, usize)) -> Result<Option<String>> {
+        let result = self.request("textDocument/hover", position_params(uri, position))?;
+        let contents = &result["contents"];
+        let hover = match contents {
+            Value::String(value) => value.clone(),
+            Value::Array(values) => values
+                .iter()
+                .filter_map(|value| value.as_str().or(value["value"].as_str()))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            _ => contents["value"].as_str().unwrap_or_default().to_string(),
+        };
+        Ok(Some(hover).filter(|hover| !hover.trim().is_empty()))
+    }
+
+    /// Signature of the call surrounding the given position
+    pub fn signature_help(
+        &mut self,
+        uri: &Path,
+        position: (usize, usize),
+    ) -> Result<Option<Signature>> {
+        let result = self.request("textDocument/signatureHelp", position_params(uri, position))?;
+        let Some(signature) = result["signatures"].as_array().and_then(|signatures| {
+            signatures.get(result["activeSignature"].as_u64().unwrap_or(0) as usize)
+        }) else {
+            return Ok(None);
+        };
+        let label = signature["label"]
+            .as_str()
+            .ok_or("ra signature has no label")?
+            .to_string();
+        let active_parameter = signature["activeParameter"]
+            .as_u64()
+            .or(result["activeParameter"].as_u64())
+            .and_then(|idx| signature["parameters"].get(idx as usize))
+            .and_then(|parameter| match &parameter["label"] {
+                // UTF-16 offsets
+                Value::Array(offsets) => Some(
+                    utf16_to_char(&label, offsets.first()?.as_u64()? as usize)
+                        ..utf16_to_char(&label, offsets.get(1)?.as_u64()? as usize),
+                ),
+                Value::String(name) => {
+                    let start = label.find(name.as_str())?;
+                    let start = label[..start].chars().count();
+                    Some(start..start + name.chars().count())
+                }
+                _ => None,
+            });
+        Ok(Some(Signature {
+            label,
+            active_parameter,
+        }))
+    }
+
+    /// Location of the definition of the symbol at the given position
+    pub fn definition(&mut self, uri: &Path, position: (usize, usize)) -> Result<Option<Location>> {
+        let result = self.request("textDocument/definition", position_params(uri, position))?;
+        // Location | Location[] | LocationLink[]
+        let location = match &result {
+            Value::Array(locations) => match locations.first() {
+                Some(location) => location,
+                None => return Ok(None),
+            },
+            Value::Null => return Ok(None),
+            location => location,
+        };
+        let uri = location["targetUri"]
+            .as_str()
+            .or(location["uri"].as_str())
+            .ok_or("ra location has no uri")?;
+        let range = if location["targetRange"].is_object() {
+            &location["targetRange"]
+        } else {
+            &location["range"]
+        };
+        let line = |key: &str| range[key]["line"].as_u64().map(|line| line as usize);
+        Ok(Some(Location {
+            path: PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri)),
+            lines: line("start").ok_or("ra range has no start")?
+                ..=line("end").ok_or("ra range has no end")?,
+        }))
+    }
+
+    /// Errors and warnings of the document
+    pub fn diagnostics(&mut self, uri: &Path) -> Result<Vec<Diagnostic>> {
+        let result = self.request(
+            "textDocument/diagnostic",
+            json!({
                 "textDocument": {
-                    "uri": format!("file://{}",uri.display()),
+                    "uri": format!("file://{}", uri.display()),
                 },
-                "position": {
-                    "line": line,
-                    "character": character
-                },
-            },
+            }),
+        )?;
+        let position = |position: &Value| -> Option<(usize, usize)> {
+            Some((
+                position["line"].as_u64()? as usize,
+                position["character"].as_u64()? as usize,
+            ))
+        };
+        Ok(result["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                // 1: Error, 2: Warning, 3: Information, 4: Hint
+                let severity = item["severity"].as_u64().unwrap_or(1);
+                if severity > 2 {
+                    return None;
+                }
+                Some(Diagnostic {
+                    range: position(&item["range"]["start"])?..position(&item["range"]["end"])?,
+                    is_error: severity == 1,
+                    message: item["message"].as_str()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = ID.fetch_add(1, Ordering::SeqCst);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
         });
-        send_request(&mut self.stdin, &completion_request)?;
+        send_request(&mut self.stdin, &request)?;
+        let response = read_response_to(&mut self.stdout, &mut self.stdin, id)?;
+        if let Some(error) = response.get("error") {
+            return Err(format!(
+                "rust-analyzer error: {}",
+                error["message"].as_str().unwrap_or_default()
+            )
+            .into());
+        }
+        Ok(response["result"].clone())
+    }
+}
 
-        let completion_response = loop {
-            let completion_response = read_response(&mut self.stdout)?;
-            if completion_response.get("result").is_some() {
-                break completion_response;
+/// Read the response to the request `id`, skipping notifications (exp: `publishDiagnostics`) and answering the
+/// requests from the server, it waits for their reply (exp: `workspace/configuration`)
+fn read_response_to(
+    reader: &mut BufReader<ChildStdout>,
+    stdin: &mut ChildStdin,
+    id: usize,
+) -> Result<Value> {
+    loop {
+        let response = read_response(reader)?;
+        let Some(method) = response["method"].as_str() else {
+            if response["id"] == id {
+                return Ok(response);
             }
-            // NOTE: we block until we get a completion
-            std::thread::sleep(Duration::from_millis(100));
+            continue;
         };
-        if let Some(result) = completion_response.get("result") {
-            if let Some(items) = result.get("items") {
-                return Ok(items
-                    .as_array()
-                    .ok_or("ra items is not an array")?
-                    .iter()
-                    .filter_map(|item| item.get("filterText"))
-                    .map(|item| item.to_string())
-                    // remove quotes
-                    .map(|item| item[1..item.len() - 1].to_owned())
-                    .collect());
-            }
+        if let Some(request_id) = response.get("id") {
+            send_request(
+                stdin,
+                &reply_to_server(request_id, method, &response["params"]),
+            )?;
         }
+    }
+}
 
-        Ok(vec![])
+/// The reply to a request from the server, the client has no settings and accepts the registrations
+fn reply_to_server(id: &Value, method: &str, params: &Value) -> Value {
+    match method {
+        // One (default) setting per requested item
+        "workspace/configuration" => {
+            let items = params["items"].as_array().map_or(0, Vec::len);
+            json!({"jsonrpc": "2.0", "id": id, "result": vec![Value::Null; items]})
+        }
+        "client/registerCapability"
+        | "client/unregisterCapability"
+        | "window/workDoneProgress/create" => {
+            json!({"jsonrpc": "2.0", "id": id, "result": null})
+        }
+        _ => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": -32601, "message": format!("Unhandled method {method}")},
+        }),
     }
 }
 
+#[test]
+fn reply_to_server_test() {
+    let id = json!(3);
+    let params = json!({"items": [{"section": "rust-analyzer"}, {}]});
+    assert_eq!(
+        reply_to_server(&id, "workspace/configuration", &params)["result"],
+        json!([null, null])
+    );
+    let reply = reply_to_server(&id, "client/registerCapability", &json!({}));
+    assert_eq!((&reply["id"], &reply["result"]), (&id, &Value::Null));
+    assert!(reply.get("error").is_none());
+    assert_eq!(
+        reply_to_server(&id, "workspace/unknown", &json!({}))["error"]["code"],
+        -32601
+    );
+}
+
+fn position_params(uri: &Path, (line, character): (usize, usize)) -> Value {
+    json!({
+        "textDocument": {
+            "uri": format!("file://{}", uri.display()),
+        },
+        "position": {
+            "line": line,
+            "character": character
+        },
+    })
+}
+
+/// Convert an offset in UTF-16 code units (the LSP default) to a char index of `s`
+fn utf16_to_char(s: &str, offset: usize) -> usize {
+    let mut units = 0;
+    s.chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= offset
+        })
+        .count()
+}
+
+#[test]
+fn utf16_to_char_test() {
+    let label = "fn f(é: u8, 𝔸: u8)";
+    assert_eq!(utf16_to_char(label, 5), 5);
+    // `𝔸` is 2 UTF-16 code units
+    assert_eq!(utf16_to_char(label, 14), 13);
+    assert_eq!(utf16_to_char(label, 18), 17);
+    assert_eq!(utf16_to_char(label, 100), label.chars().count());
+}
+
 fn send_request(stdin: &mut std::process::ChildStdin, request: &Value) -> Result<()> {
     let request_str = serde_json::to_string(request)?;
     let content_length = request_str.len();
@@ -0,0 +1,439 @@
+//! Line delimited JSON-RPC 2.0 server (`irust --server [address]`)
+//!
+//! Every request is a json object on its own line, and every response is written on its own line.
+//! Without an address the server speaks over stdin/stdout, otherwise it listens on the tcp address
+//! and serves the connections one after the other (they all share the same repl).
+use super::{options::Options, parser::is_statement, ra::Completer, IRust, Result};
+use irust_repl::{
+    diagnostics, CompileMode, EvalConfig, Executor, Limits, MainResult, Repl, ToolChain,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Separates the prints of the evaluated expression from its value
+const VALUE_MARKER: &str = "\u{1}irust_value\n";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+pub fn run(options: Options, address: Option<String>) -> Result<()> {
+    let mut server = Server::new(options)?;
+    match address {
+        Some(address) => {
+            let listener = TcpListener::bind(address)?;
+            for stream in listener.incoming() {
+                let stream = stream?;
+                // A broken connection shouldn't stop the server
+                let _ = server.serve(BufReader::new(stream.try_clone()?), stream);
+            }
+            Ok(())
+        }
+        None => server.serve(std::io::stdin().lock(), std::io::stdout().lock()),
+    }
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for RpcError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        Self::new(SERVER_ERROR, e)
+    }
+}
+
+#[derive(Serialize, Default)]
+struct Evaluation {
+    success: bool,
+    /// Debug representation of the expression value, `None` for statements and `()`
+    value: Option<String>,
+    stdout: String,
+    stderr: String,
+    /// `None` if nothing was executed or if the process was killed by a signal
+    exit_code: Option<i32>,
+    /// Why the program failed: the panic report, the signal or the exceeded limit
+    failure: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+    timings: Timings,
+}
+
+#[derive(Serialize, Default)]
+struct Timings {
+    compile_ms: u128,
+    run_ms: u128,
+}
+
+#[derive(Serialize)]
+struct Diagnostic {
+    #[serde(flatten)]
+    diagnostic: diagnostics::Diagnostic,
+    /// The diagnostic rendered like rustc does, without colors
+    rendered: String,
+}
+
+impl From<diagnostics::Diagnostic> for Diagnostic {
+    fn from(diagnostic: diagnostics::Diagnostic) -> Self {
+        Self {
+            rendered: diagnostic.render(false),
+            diagnostic,
+        }
+    }
+}
+
+struct Server {
+    options: Options,
+    repl: Repl,
+    completer: Option<Completer>,
+}
+
+impl Server {
+    fn new(mut options: Options) -> Result<Self> {
+        // stdout is reserved for the responses
+        let toolchain = IRust::session_toolchain(&options).unwrap_or_else(|e| {
+            eprintln!("{e}\nUsing the default toolchain instead");
+            ToolChain::Default
+        });
+        let mut repl = IRust::new_repl(&options, toolchain)?;
+        if let Err(e) = repl.set_sandbox(options.sandbox) {
+            eprintln!("Could not activate the sandbox: {e}");
+            options.sandbox = false;
+        }
+        Ok(Self {
+            repl,
+            options,
+            completer: None,
+        })
+    }
+
+    fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `None` for notifications (requests without an id)
+    fn handle(&mut self, line: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, e)))),
+        };
+        let result = match request["method"].as_str() {
+            Some(method) => self.call(method, &request["params"]),
+            None => Err(RpcError::new(INVALID_REQUEST, "Missing method")),
+        };
+        Some(response(request.get("id")?.clone(), result))
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> std::result::Result<Value, RpcError> {
+        let result = match method {
+            "eval" => json!(self.eval(str_param(params, "code")?)?),
+            "check" => json!(self.check(str_param(params, "code")?)?),
+            "add_dep" => {
+                let args = params["args"]
+                    .as_array()
+                    .and_then(|args| {
+                        args.iter()
+                            .map(|arg| arg.as_str().map(ToOwned::to_owned))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| {
+                        RpcError::new(INVALID_PARAMS, "`args` must be an array of strings")
+                    })?;
+                self.add_dep(&args)?;
+                Value::Null
+            }
+            "show" => {
+                let code = self.repl.show();
+                let code = code.strip_prefix("Current Repl Code:\n").unwrap_or(&code);
+                json!({ "code": code })
+            }
+            "reset" => {
+                self.repl.reset()?;
+                Value::Null
+            }
+            "complete" => {
+                let code = str_param(params, "code")?;
+                let cursor = params["cursor"]
+                    .as_u64()
+                    .map(|cursor| cursor as usize)
+                    .unwrap_or_else(|| code.chars().count());
+                json!({ "matches": self.complete(code.chars().take(cursor).collect())? })
+            }
+            "set_option" => {
+                self.set_option(str_param(params, "name")?, &params["value"])?;
+                Value::Null
+            }
+            _ => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method: {method}"),
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    fn eval(&mut self, code: &str) -> Result<Evaluation> {
+        let code = code.trim();
+        if code.is_empty() {
+            return Ok(Evaluation {
+                success: true,
+                ..Default::default()
+            });
+        }
+
+        if is_statement(code, self.options.auto_insert_semicolon) {
+            let evaluation = if self.options.check_statements {
+                self.check(code)?
+            } else {
+                Evaluation {
+                    success: true,
+                    ..Default::default()
+                }
+            };
+            if evaluation.success {
+                self.repl.insert(code);
+            }
+            return Ok(evaluation);
+        }
+
+        let evaluator: [String; 2] = self.expression_wrapper().into();
+        let timer = Instant::now();
+        let result = self.repl.eval_with_configuration(EvalConfig {
+            input: code,
+            interactive_function: None,
+            color: false,
+            evaluator: &evaluator,
+            compile_mode: self.options.compile_mode,
+            terminal: None,
+        })?;
+        let elapsed = timer.elapsed();
+        let diagnostics: Vec<Diagnostic> = result
+            .diagnostics
+            .into_iter()
+            .map(Diagnostic::from)
+            .collect();
+
+        // The build failed
+        let Some(usage) = result.usage else {
+            return Ok(Evaluation {
+                stderr: cargo_errors(&result.output, &diagnostics),
+                diagnostics,
+                timings: Timings {
+                    compile_ms: elapsed.as_millis(),
+                    run_ms: 0,
+                },
+                ..Default::default()
+            });
+        };
+
+        let mut evaluation = Evaluation {
+            success: result.status.success() && result.runtime_failure.is_none(),
+            exit_code: result.status.code(),
+            failure: result.runtime_failure,
+            stderr: result.stderr,
+            diagnostics,
+            timings: Timings {
+                compile_ms: elapsed.saturating_sub(usage.wall_time).as_millis(),
+                run_ms: usage.wall_time.as_millis(),
+            },
+            ..Default::default()
+        };
+        match result.output.split_once(VALUE_MARKER) {
+            Some((stdout, value)) => {
+                evaluation.stdout = stdout.to_string();
+                let value = value.trim_end();
+                if value != "()" {
+                    evaluation.value = Some(value.to_string());
+                }
+            }
+            None => evaluation.stdout = result.output,
+        }
+        Ok(evaluation)
+    }
+
+    /// Check the code without running it, it is checked as it would be evaluated
+    fn check(&mut self, code: &str) -> Result<Evaluation> {
+        let code = code.trim();
+        let (prefix, suffix) = if is_statement(code, self.options.auto_insert_semicolon) {
+            (String::new(), String::new())
+        } else {
+            self.expression_wrapper()
+        };
+        let source_map = self.repl.source_map(&prefix, code);
+
+        let toolchain = self.repl.toolchain();
+        let cargo = self.repl.cargo.clone();
+        let timer = Instant::now();
+        let (status, output) = self
+            .repl
+            .eval_in_tmp_repl(format!("{prefix}{code}{suffix}"), |_| {
+                Ok(cargo.cargo_check_output(toolchain.clone())?)
+            })?;
+        let diagnostics = parse_diagnostics(&output, &source_map);
+
+        Ok(Evaluation {
+            success: status.success(),
+            stderr: if status.success() {
+                String::new()
+            } else {
+                cargo_errors(&output, &diagnostics)
+            },
+            diagnostics,
+            timings: Timings {
+                compile_ms: timer.elapsed().as_millis(),
+                run_ms: 0,
+            },
+            ..Default::default()
+        })
+    }
+
+    /// The value is printed with the configured evaluator, after the marker
+    fn expression_wrapper(&self) -> (String, String) {
+        (
+            "{ let __irust_value = {\n".to_string(),
+            format!(
+                "\n}}; print!({VALUE_MARKER:?}); {}__irust_value{} }}",
+                self.options.evaluator[0], self.options.evaluator[1]
+            ),
+        )
+    }
+
+    fn add_dep(&mut self, args: &[String]) -> Result<()> {
+        if !self.repl.add_dep(args)?.wait()?.success() {
+            return Err(format!("Failed to add dependency: {}", args.join(" ")).into());
+        }
+        if !self.repl.build()?.wait()?.success() {
+            return Err("Failed to build dependencies".into());
+        }
+        if let Some(completer) = self.completer.as_mut() {
+            completer.rust_analyzer.reload_workspace()?;
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self, buffer: String) -> Result<Vec<String>> {
+        // rust-analyzer is started on the first completion request
+        if self.completer.is_none() {
+            self.completer = Completer::start_ra(
+                &self.repl.cargo.paths.irust_dir,
+                &self.repl.cargo.paths.main_file,
+                self.repl.body(),
+            );
+        }
+        self.completer
+            .as_mut()
+            .ok_or("Failed to start rust-analyzer")?
+            .complete(buffer, &mut self.repl)
+    }
+
+    fn set_option(&mut self, name: &str, value: &Value) -> Result<()> {
+        let string = || value.as_str().ok_or(format!("`{name}` expects a string"));
+        let bool = || value.as_bool().ok_or(format!("`{name}` expects a boolean"));
+        match name {
+            "toolchain" => {
+                let toolchain = ToolChain::from_str(string()?)?;
+                toolchain.check_installed()?;
+                self.repl.set_toolchain(toolchain.clone())?;
+                self.options.toolchain = toolchain;
+            }
+            "main_result" => {
+                self.options.main_result = MainResult::from_str(string()?)?;
+                self.repl.set_main_result(self.options.main_result);
+            }
+            "executor" => {
+                let executor = Executor::from_str(string()?)?;
+                self.repl.set_executor(executor)?;
+                self.options.executor = executor;
+            }
+            "compile_mode" => {
+                let compile_mode = CompileMode::from_str(string()?)?;
+                if let CompileMode::Miri = compile_mode {
+                    return Err("Miri mode is not supported by the server".into());
+                }
+                compile_mode.check_available(&self.repl.toolchain())?;
+                self.options.compile_mode = compile_mode;
+            }
+            limit if Limits::KEYS.contains(&limit) => {
+                let mut limits = self.options.limits.clone();
+                limits.set(limit, string()?)?;
+                self.repl.set_limits(limits.clone())?;
+                self.options.limits = limits;
+            }
+            "sandbox" => {
+                let sandbox = bool()?;
+                self.repl.set_sandbox(sandbox)?;
+                self.options.sandbox = sandbox;
+            }
+            "check_statements" => self.options.check_statements = bool()?,
+            "auto_insert_semicolon" => self.options.auto_insert_semicolon = bool()?,
+            _ => return Err(format!("Unknown option: {name}").into()),
+        }
+        Ok(())
+    }
+}
+
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError { code, message }) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+fn str_param<'a>(params: &'a Value, name: &str) -> std::result::Result<&'a str, RpcError> {
+    params[name]
+        .as_str()
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing string param `{name}`")))
+}
+
+fn parse_diagnostics(output: &str, source_map: &diagnostics::SourceMap) -> Vec<Diagnostic> {
+    diagnostics::parse(output, source_map)
+        .into_iter()
+        .map(Diagnostic::from)
+        .collect()
+}
+
+/// When the build failed without compiler errors (exp: invalid dependency) report cargo output (without its json
+/// messages)
+fn cargo_errors(output: &str, diagnostics: &[Diagnostic]) -> String {
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.diagnostic.level == diagnostics::Level::Error)
+    {
+        return String::new();
+    }
+    output
+        .lines()
+        .filter(|line| !line.starts_with('{'))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,96 @@
+use super::options::Options;
+use crate::irust::Result;
+use irust_repl::{
+    CompileMode, Edition, Executor, Limits, MainResult, Profile, Repl, ToolChain, PRELUDE_NAME,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything needed to recreate a repl session, saved with `:save` and loaded with `:restore`
+/// or `irust --session`
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub irust_version: String,
+    pub toolchain: ToolChain,
+    pub executor: Executor,
+    pub main_result: MainResult,
+    pub edition: Edition,
+    pub compile_mode: CompileMode,
+    pub evaluator: Vec<String>,
+    pub persistent: bool,
+    #[serde(default)]
+    pub sandbox: bool,
+    pub cursor: usize,
+    pub body: String,
+    #[serde(default)]
+    pub tests: Vec<String>,
+    // Tables needs to be last in a toml document
+    #[serde(default)]
+    pub profile: Profile,
+    #[serde(default)]
+    pub limits: Limits,
+    pub dependencies: toml::Table,
+}
+
+impl Session {
+    pub fn new(repl: &Repl, options: &Options) -> Result<Self> {
+        let mut dependencies = Self::read_dependencies(&repl.cargo.paths.cargo_toml_file)?;
+        // The prelude is specific to each machine, it will be recreated by the repl
+        dependencies.remove(PRELUDE_NAME);
+
+        Ok(Self {
+            irust_version: crate::args::VERSION.to_string(),
+            toolchain: repl.toolchain(),
+            executor: repl.executor(),
+            main_result: repl.main_result(),
+            edition: repl.edition(),
+            compile_mode: options.compile_mode,
+            evaluator: options.evaluator.clone(),
+            persistent: repl.is_persistent(),
+            sandbox: repl.is_sandboxed(),
+            cursor: repl.cursor(),
+            body: repl.body(),
+            tests: repl.tests().to_vec(),
+            profile: repl.profile().clone(),
+            limits: repl.limits().clone(),
+            dependencies,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Add the session dependencies to the repl Cargo.toml
+    /// Dependencies already present (the prelude and the executor dependency) are kept as is
+    pub fn write_dependencies(&self, cargo_toml_file: &Path) -> Result<()> {
+        let mut cargo_toml: toml::Table =
+            toml::from_str(&std::fs::read_to_string(cargo_toml_file)?)?;
+        let dependencies = cargo_toml
+            .entry("dependencies")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or("Cargo.toml dependencies is not a table")?;
+        for (name, dependency) in &self.dependencies {
+            if !dependencies.contains_key(name) {
+                dependencies.insert(name.clone(), dependency.clone());
+            }
+        }
+        std::fs::write(cargo_toml_file, toml::to_string(&cargo_toml)?)?;
+        Ok(())
+    }
+
+    fn read_dependencies(cargo_toml_file: &Path) -> Result<toml::Table> {
+        let mut cargo_toml: toml::Table =
+            toml::from_str(&std::fs::read_to_string(cargo_toml_file)?)?;
+        match cargo_toml.remove("dependencies") {
+            Some(toml::Value::Table(dependencies)) => Ok(dependencies),
+            _ => Ok(toml::Table::new()),
+        }
+    }
+}
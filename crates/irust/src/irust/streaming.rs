@@ -0,0 +1,139 @@
+//! The user terminal of the streaming mode (`:streaming on`), the output of the program is written through the
+//! printer as it arrives and the key presses are forwarded to it
+use crate::irust::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Color;
+use irust_repl::{Terminal, TerminalInput};
+use printer::printer::Printer;
+use std::io::{Stdout, Write};
+use std::time::Duration;
+
+/// What the default evaluator prints for unit values, it's not shown like in the normal mode
+const UNIT_OUTPUT: &[u8] = b"()\r\n";
+
+pub struct StreamingTerminal<'a> {
+    printer: &'a mut Printer<Stdout>,
+    prompt: String,
+    /// The start of the output, held back while it could be `UNIT_OUTPUT`
+    pending: Vec<u8>,
+    shown: bool,
+}
+
+impl<'a> StreamingTerminal<'a> {
+    pub fn new(printer: &'a mut Printer<Stdout>, prompt: String) -> Self {
+        Self {
+            printer,
+            prompt,
+            pending: vec![],
+            shown: false,
+        }
+    }
+
+    /// Whether the program output was shown
+    pub fn shown(&self) -> bool {
+        self.shown
+    }
+}
+
+impl Terminal for StreamingTerminal<'_> {
+    fn write(&mut self, output: &[u8]) -> Result<()> {
+        let raw = &mut self.printer.writer.raw;
+        if !self.shown {
+            self.pending.extend_from_slice(output);
+            if UNIT_OUTPUT.starts_with(&self.pending) {
+                return Ok(());
+            }
+            self.shown = true;
+            raw.write_with_color(&self.prompt, Color::Red)?;
+            raw.write_all(&std::mem::take(&mut self.pending))?;
+        } else {
+            raw.write_all(output)?;
+        }
+        raw.flush()?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Option<TerminalInput>> {
+        if !crossterm::event::poll(Duration::from_millis(1))? {
+            return Ok(None);
+        }
+        Ok(match crossterm::event::read()? {
+            Event::Key(key) => key_bytes(key).map(TerminalInput::Bytes),
+            Event::Paste(text) => Some(TerminalInput::Bytes(text.into_bytes())),
+            Event::Resize(columns, rows) => Some(TerminalInput::Resize(columns, rows)),
+            _ => None,
+        })
+    }
+
+    fn size(&self) -> (u16, u16) {
+        crossterm::terminal::size().unwrap_or((80, 24))
+    }
+}
+
+/// Encode a key press like a terminal does
+fn key_bytes(key: KeyEvent) -> Option<Vec<u8>> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let bytes = match key.code {
+        // ctrl-c is `\x03`, the program gets `SIGINT`
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            if !c.is_ascii() {
+                return None;
+            }
+            vec![c.to_ascii_uppercase() as u8 & 0x1f]
+        }
+        KeyCode::Char(c) => c.to_string().into_bytes(),
+        KeyCode::Enter => b"\r".to_vec(),
+        KeyCode::Backspace => b"\x7f".to_vec(),
+        KeyCode::Tab => b"\t".to_vec(),
+        KeyCode::BackTab => b"\x1b[Z".to_vec(),
+        KeyCode::Esc => b"\x1b".to_vec(),
+        KeyCode::Up => b"\x1b[A".to_vec(),
+        KeyCode::Down => b"\x1b[B".to_vec(),
+        KeyCode::Right => b"\x1b[C".to_vec(),
+        KeyCode::Left => b"\x1b[D".to_vec(),
+        KeyCode::Home => b"\x1b[H".to_vec(),
+        KeyCode::End => b"\x1b[F".to_vec(),
+        KeyCode::Insert => b"\x1b[2~".to_vec(),
+        KeyCode::Delete => b"\x1b[3~".to_vec(),
+        KeyCode::PageUp => b"\x1b[5~".to_vec(),
+        KeyCode::PageDown => b"\x1b[6~".to_vec(),
+        _ => return None,
+    };
+    // alt prefixes the key with escape
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        return Some([b"\x1b".as_slice(), &bytes].concat());
+    }
+    Some(bytes)
+}
+
+#[test]
+fn key_encoding() {
+    let key = |code, modifiers| key_bytes(KeyEvent::new(code, modifiers));
+    assert_eq!(
+        key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+        Some(vec![3])
+    );
+    assert_eq!(
+        key(KeyCode::Char('D'), KeyModifiers::CONTROL),
+        Some(vec![4])
+    );
+    assert_eq!(
+        key(KeyCode::Char('é'), KeyModifiers::NONE),
+        Some("é".as_bytes().to_vec())
+    );
+    assert_eq!(
+        key(KeyCode::Enter, KeyModifiers::NONE),
+        Some(b"\r".to_vec())
+    );
+    assert_eq!(
+        key(KeyCode::Up, KeyModifiers::NONE),
+        Some(b"\x1b[A".to_vec())
+    );
+    assert_eq!(
+        key(KeyCode::Char('b'), KeyModifiers::ALT),
+        Some(b"\x1bb".to_vec())
+    );
+    assert_eq!(key(KeyCode::F(1), KeyModifiers::NONE), None);
+}
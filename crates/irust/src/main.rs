@@ -28,6 +28,17 @@ fn main() {
         exit(0)
     }
 
+    if let ArgsResult::Server(address) = args_result {
+        if !check_required_deps() {
+            exit(1);
+        }
+        if let Err(e) = irust::server::run(options, address) {
+            eprintln!("IRust server exited with error: {e}");
+            exit(1);
+        }
+        exit(0)
+    }
+
     // If no argument are provided, check stdin for some oneshot usage
     if args.is_empty() {
         let mut stdin = std::io::stdin();
@@ -48,6 +59,7 @@ fn main() {
                     color: true,
                     evaluator: &*DEFAULT_EVALUATOR,
                     compile_mode: CompileMode::Debug,
+                    terminal: None,
                 })?;
                 Ok(result)
             })() {
@@ -57,7 +69,7 @@ fn main() {
                     } else {
                         println!(
                             "{}",
-                            irust::format_err(&result.output, false, &repl.cargo.name)
+                            irust::format_build_err(&result, false, &repl.cargo.name)
                         );
                     }
                     exit(0)
@@ -88,18 +100,23 @@ fn main() {
         IRust::new(options)
     };
 
-    // If a script path was provided try to load it
-    if let ArgsResult::ProceedWithScriptPath(script) = args_result {
-        // Ignore if it fails
-        let _ = irust.load_inner(script);
+    match args_result {
+        // If a script path was provided try to load it
+        ArgsResult::ProceedWithScriptPath(script) => {
+            // Ignore if it fails
+            let _ = irust.load_inner(script);
+        }
+        // If a session file was provided try to restore it
+        ArgsResult::ProceedWithSession(session) => {
+            if let Err(e) = irust.restore_session(session) {
+                eprintln!("{}", format!("Failed to restore session: {e}\r").red());
+            }
+        }
+        _ => (),
     }
 
     // Start IRust
-    let err = if let Err(e) = irust.run() {
-        Some(e)
-    } else {
-        None
-    };
+    let err = irust.run().err();
 
     // Now IRust has been dropped we can safely print to stderr
     if let Some(err) = err {
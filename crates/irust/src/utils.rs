@@ -158,45 +158,29 @@ impl StringTools {
         for character in s.chars() {
             // safe unwraps ahead
             match character {
-                '(' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'(').unwrap() += 1;
-                    }
+                '(' if !quote && !double_quote => {
+                    *braces.get_mut(&'(').unwrap() += 1;
                 }
-                ')' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'(').unwrap() -= 1;
-                    }
+                ')' if !quote && !double_quote => {
+                    *braces.get_mut(&'(').unwrap() -= 1;
                 }
-                '[' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'[').unwrap() += 1;
-                    }
+                '[' if !quote && !double_quote => {
+                    *braces.get_mut(&'[').unwrap() += 1;
                 }
-                ']' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'[').unwrap() -= 1;
-                    }
+                ']' if !quote && !double_quote => {
+                    *braces.get_mut(&'[').unwrap() -= 1;
                 }
-                '{' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'{').unwrap() += 1;
-                    }
+                '{' if !quote && !double_quote => {
+                    *braces.get_mut(&'{').unwrap() += 1;
                 }
-                '}' => {
-                    if !quote && !double_quote {
-                        *braces.get_mut(&'{').unwrap() -= 1;
-                    }
+                '}' if !quote && !double_quote => {
+                    *braces.get_mut(&'{').unwrap() -= 1;
                 }
-                '"' => {
-                    if previous_char != '\\' {
-                        double_quote = !double_quote;
-                    }
+                '"' if previous_char != '\\' => {
+                    double_quote = !double_quote;
                 }
-                '\'' => {
-                    if previous_char != '\\' {
-                        quote = !quote;
-                    }
+                '\'' if previous_char != '\\' => {
+                    quote = !quote;
                 }
                 _ => (),
             }
@@ -259,7 +243,7 @@ fn remove_comments(s: &str) -> String {
 }
 
 fn _balanced_quotes(s: &str) -> bool {
-    s.match_indices(|p| p == '"' || p == '\'').count() % 2 == 0
+    s.match_indices(['"', '\'']).count().is_multiple_of(2)
 }
 
 pub fn ctrlc_cancel(process: &mut std::process::Child) -> Result<()> {
@@ -296,7 +280,7 @@ pub fn ctrlc_cancel(process: &mut std::process::Child) -> Result<()> {
                     }) => {
                         use std::io::Write;
                         // Ignore write errors (process might have ended)
-                        let _ = process.stdin.as_mut().unwrap().write_all(&[b'\n']);
+                        let _ = process.stdin.as_mut().unwrap().write_all(b"\n");
                     }
                     _ => (),
                 }
@@ -0,0 +1,89 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+#[test]
+fn server() {
+    let config_dir = std::env::temp_dir().join(format!("irust_server_test_{}", std::process::id()));
+    std::fs::create_dir_all(&config_dir).unwrap();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_irust"))
+        .arg("--server")
+        // use the default options
+        .env("IRUST_CONFIG_DIR", &config_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let mut id = 0;
+    let mut request = |method: &str, params: Value| -> Value {
+        id += 1;
+        let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        writeln!(stdin, "{request}").unwrap();
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let response: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(response["id"], id);
+        response
+    };
+
+    let result = &request("eval", json!({"code": "let a = 4;"}))["result"];
+    assert_eq!(result["success"], true);
+
+    let result = &request(
+        "eval",
+        json!({"code": "{ println!(\"out\"); eprintln!(\"err\"); a + 1 }"}),
+    )["result"];
+    assert_eq!(result["success"], true);
+    assert_eq!(result["value"], "5");
+    assert_eq!(result["stdout"], "out\n");
+    assert_eq!(result["stderr"], "err\n");
+    assert_eq!(result["exit_code"], 0);
+
+    let result = &request("eval", json!({"code": "a + b"}))["result"];
+    assert_eq!(result["success"], false);
+    assert_eq!(result["diagnostics"][0]["level"], "error");
+    assert_eq!(result["diagnostics"][0]["code"], "E0425");
+    assert_eq!(result["diagnostics"][0]["spans"][0]["origin"], "input");
+    assert_eq!(result["diagnostics"][0]["spans"][0]["line_start"], 1);
+    assert_eq!(result["diagnostics"][0]["spans"][0]["column_start"], 5);
+
+    let result = &request("check", json!({"code": "let b: u8 = \"\";"}))["result"];
+    assert_eq!(result["success"], false);
+    assert_eq!(result["diagnostics"][0]["code"], "E0308");
+
+    let result = &request("eval", json!({"code": "std::process::exit(3)"}))["result"];
+    assert_eq!(result["success"], false);
+    assert_eq!(result["exit_code"], 3);
+
+    let result = &request("show", json!({}))["result"];
+    assert!(result["code"].as_str().unwrap().contains("let a = 4;"));
+
+    let response = request(
+        "set_option",
+        json!({"name": "compile_mode", "value": "release"}),
+    );
+    assert_eq!(response["result"], Value::Null);
+    let response = request("set_option", json!({"name": "timeout", "value": "500ms"}));
+    assert_eq!(response["result"], Value::Null);
+    let result = &request("eval", json!({"code": "loop {}"}))["result"];
+    assert_eq!(result["success"], false);
+    assert!(result["failure"]
+        .as_str()
+        .unwrap()
+        .ends_with("timeout exceeded: the program ran for more than 500ms"));
+    let response = request("set_option", json!({"name": "unknown", "value": true}));
+    assert_eq!(response["error"]["code"], -32000);
+
+    request("reset", json!({}));
+    let result = &request("show", json!({}))["result"];
+    assert!(!result["code"].as_str().unwrap().contains("let a = 4;"));
+
+    let response = request("unknown", json!({}));
+    assert_eq!(response["error"]["code"], -32601);
+
+    drop(stdin);
+    assert!(server.wait().unwrap().success());
+    std::fs::remove_dir_all(config_dir).unwrap();
+}
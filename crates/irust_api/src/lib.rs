@@ -120,6 +120,8 @@ pub enum Command {
     ResetPrompt,
     Undo,
     Exit,
+    ApplyFix,
+    Hover,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,34 @@
+use crate::Result;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Connection file written by jupyter, it is passed as an argument to the kernel
+#[derive(Debug, Deserialize)]
+pub struct ConnectionInfo {
+    pub transport: String,
+    pub ip: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    pub key: String,
+    pub signature_scheme: String,
+}
+
+impl ConnectionInfo {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let info: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if info.transport != "tcp" {
+            return Err(format!("Unsupported transport: {}", info.transport).into());
+        }
+        if !info.key.is_empty() && info.signature_scheme != "hmac-sha256" {
+            return Err(format!("Unsupported signature scheme: {}", info.signature_scheme).into());
+        }
+        Ok(info)
+    }
+
+    pub fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::Result;
+use serde_json::json;
+use std::path::PathBuf;
+
+/// Jupyter looks for kernels in `$data_dir/kernels`
+fn jupyter_data_dir() -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("JUPYTER_DATA_DIR") {
+        return Some(dir.into());
+    }
+    if cfg!(target_os = "macos") {
+        return Some(dirs::home_dir()?.join("Library").join("Jupyter"));
+    }
+    // linux: ~/.local/share/jupyter windows: %APPDATA%\jupyter
+    Some(dirs::data_dir()?.join("jupyter"))
+}
+
+/// Write `kernel.json` so jupyter can start this kernel, returns the kernel directory
+pub fn install() -> Result<PathBuf> {
+    let kernel_dir = jupyter_data_dir()
+        .ok_or("Could not find jupyter data directory")?
+        .join("kernels")
+        .join("irust");
+    std::fs::create_dir_all(&kernel_dir)?;
+
+    let exe = std::env::current_exe()?;
+    let kernel_json = json!({
+        "argv": [exe, "{connection_file}"],
+        "display_name": "Rust (IRust)",
+        "language": "rust",
+        "interrupt_mode": "message",
+    });
+    std::fs::write(
+        kernel_dir.join("kernel.json"),
+        serde_json::to_string_pretty(&kernel_json)?,
+    )?;
+
+    Ok(kernel_dir)
+}
@@ -0,0 +1,441 @@
+//! Evaluation of notebook cells with the irust repl
+//!
+//! A cell is split into statements and an optional trailing expression (like IRust input):
+//! the statements are inserted in the repl and the expression value is shown as the cell result.
+use crate::Result;
+use irust_repl::{EvalConfig, EvalResult, Repl};
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Separates the cell prints from the expression value in the evaluation output
+///
+/// It's printed by cells without a value too, so the output is never empty and is not replaced by stderr
+const VALUE_MARKER: &str = "\u{1}irust_value\n";
+
+pub enum Output {
+    Stream(String),
+    Stderr(String),
+    Value(String),
+    Error {
+        ename: String,
+        evalue: String,
+        traceback: Vec<String>,
+    },
+}
+
+pub struct Kernel {
+    repl: Repl,
+    value_evaluator: [String; 2],
+    statements_evaluator: [String; 2],
+}
+
+/// Request the running evaluation (if any) to stop
+pub fn interrupt() {
+    INTERRUPT.store(true, Ordering::SeqCst);
+}
+
+fn interruptible(child: &mut Child) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    if INTERRUPT.swap(false, Ordering::SeqCst) {
+        child.kill()?;
+        return Err("Interrupted".into());
+    }
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    Ok(())
+}
+
+impl Kernel {
+    pub fn new() -> Result<Self> {
+        let mut repl = Repl::new(
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+        // Cells are executed one after the other, so don't re-run the previous cells if possible
+        if cfg!(unix) {
+            repl.set_persistent(true).map_err(|e| e.to_string())?;
+        }
+
+        Ok(Self {
+            repl,
+            value_evaluator: [
+                "{ let __irust_value = {\n".into(),
+                format!("\n}}; print!({VALUE_MARKER:?}); println!(\"{{:?}}\", __irust_value); }}"),
+            ],
+            statements_evaluator: ["".into(), format!("; println!({VALUE_MARKER:?});")],
+        })
+    }
+
+    pub fn execute(&mut self, code: &str) -> Vec<Output> {
+        INTERRUPT.store(false, Ordering::SeqCst);
+        let code = code.trim();
+        if code.is_empty() {
+            return vec![];
+        }
+        if code.starts_with(':') {
+            return match self.command(code) {
+                Ok(output) => output,
+                Err(e) => vec![error("CommandError", e.to_string())],
+            };
+        }
+
+        let (statements, expression) = split_cell(code);
+        let orig_body = self.repl.body();
+        let orig_cursor = self.repl.cursor();
+        if !statements.is_empty() {
+            self.repl.insert(statements);
+        }
+        let (input, evaluator) = match expression {
+            Some(expression) => (expression, &self.value_evaluator),
+            None => ("()", &self.statements_evaluator),
+        };
+        let result = self.repl.eval_with_configuration(EvalConfig {
+            input,
+            interactive_function: Some(interruptible),
+            color: false,
+            evaluator,
+            compile_mode: irust_repl::CompileMode::Debug,
+            terminal: None,
+        });
+
+        match result {
+            Ok(EvalResult {
+                output,
+                status,
+                stderr,
+                ..
+            }) if status.success() => {
+                let mut outputs = vec![];
+                let (stream, value) = match output.split_once(VALUE_MARKER) {
+                    Some((stream, value)) => (stream, Some(value)),
+                    None => (output.as_str(), None),
+                };
+                if !stream.is_empty() {
+                    outputs.push(Output::Stream(stream.to_string()));
+                }
+                if !stderr.is_empty() {
+                    outputs.push(Output::Stderr(stderr));
+                }
+                if let Some(value) = value.map(str::trim_end).filter(|value| !value.is_empty()) {
+                    outputs.push(Output::Value(value.to_string()));
+                }
+                outputs
+            }
+            Ok(EvalResult {
+                output,
+                runtime_failure: Some(failure),
+                ..
+            }) => {
+                self.repl.hard_load(orig_body, orig_cursor);
+                let mut outputs = vec![];
+                if !output.is_empty() {
+                    outputs.push(Output::Stream(output));
+                }
+                outputs.push(error("RuntimeError", failure));
+                outputs
+            }
+            Ok(EvalResult {
+                output,
+                diagnostics,
+                ..
+            }) => {
+                self.repl.hard_load(orig_body, orig_cursor);
+                if !diagnostics.is_empty() || output.contains("could not compile") {
+                    vec![error("CompileError", clean_cargo_output(&output))]
+                } else {
+                    vec![error("RuntimeError", output)]
+                }
+            }
+            Err(e) => {
+                self.repl.hard_load(orig_body, orig_cursor);
+                vec![error("EvalError", e.to_string())]
+            }
+        }
+    }
+
+    /// IRust commands that make sense in a notebook
+    fn command(&mut self, code: &str) -> Result<Vec<Output>> {
+        let mut args = code.split_whitespace();
+        let cmd = args.next().expect("code is not empty");
+        let args: Vec<String> = args.map(ToOwned::to_owned).collect();
+        let map_err = |e: Box<dyn std::error::Error>| e.to_string();
+        match cmd {
+            ":add" => {
+                if !self.repl.add_dep(&args)?.wait()?.success() {
+                    return Err(format!("Failed to add dependency: {args:?}").into());
+                }
+                self.repl.build()?.wait()?;
+            }
+            ":reset" => self.repl.reset().map_err(map_err)?,
+            ":pop" => self.repl.pop(),
+            ":show" => return Ok(vec![Output::Stream(self.repl.show())]),
+            ":persistent" => match args.first().map(String::as_str) {
+                Some("on") => self.repl.set_persistent(true).map_err(map_err)?,
+                Some("off") => self.repl.set_persistent(false).map_err(map_err)?,
+                _ => return Ok(vec![Output::Value(self.repl.is_persistent().to_string())]),
+            },
+            ":sandbox" => match args.first().map(String::as_str) {
+                Some("on") => self.repl.set_sandbox(true).map_err(map_err)?,
+                Some("off") => self.repl.set_sandbox(false).map_err(map_err)?,
+                _ => return Ok(vec![Output::Value(self.repl.is_sandboxed().to_string())]),
+            },
+            _ => return Err(format!("Unknown command: {cmd}").into()),
+        }
+        Ok(vec![Output::Stream("Ok!\n".into())])
+    }
+
+    /// Returns the matches and the range (in chars) of the text that they replace
+    pub fn complete(&self, code: &str, cursor_pos: usize) -> (Vec<String>, usize, usize) {
+        let before: Vec<char> = code.chars().take(cursor_pos).collect();
+        let prefix: String = before
+            .iter()
+            .rev()
+            .take_while(|c| is_ident_char(**c))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        if prefix.is_empty() {
+            return (vec![], cursor_pos, cursor_pos);
+        }
+
+        let body = self.repl.body();
+        let mut matches: Vec<String> = KEYWORDS
+            .iter()
+            .map(ToString::to_string)
+            .chain(idents(&body))
+            .chain(idents(code))
+            .filter(|ident| ident.starts_with(&prefix) && ident != &prefix)
+            .filter(|ident| !ident.starts_with("__irust"))
+            .collect();
+        matches.sort();
+        matches.dedup();
+
+        (matches, cursor_pos - prefix.chars().count(), cursor_pos)
+    }
+
+    /// Type of the expression under the cursor
+    pub fn inspect(&mut self, code: &str, cursor_pos: usize) -> Option<String> {
+        let chars: Vec<char> = code.chars().collect();
+        let cursor_pos = cursor_pos.min(chars.len());
+        let start = chars[..cursor_pos]
+            .iter()
+            .rposition(|c| !is_ident_char(*c))
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        let end = chars[cursor_pos..]
+            .iter()
+            .position(|c| !is_ident_char(*c))
+            .map(|pos| cursor_pos + pos)
+            .unwrap_or(chars.len());
+        let name: String = chars[start..end].iter().collect();
+        if name.is_empty() {
+            return None;
+        }
+
+        let result = self.repl.type_of(&name).ok()?;
+        if !result.status.success() {
+            return None;
+        }
+
+        Some(format!("{name}: {}", result.output))
+    }
+}
+
+fn error(ename: &str, evalue: String) -> Output {
+    Output::Error {
+        ename: ename.to_string(),
+        traceback: evalue.lines().map(ToOwned::to_owned).collect(),
+        evalue,
+    }
+}
+
+/// Remove cargo progress lines, only keep the compiler diagnostics
+fn clean_cargo_output(output: &str) -> String {
+    output
+        .lines()
+        .filter(|line| {
+            let line = line.trim_start();
+            !line.starts_with("Compiling")
+                && !line.starts_with("Checking")
+                && !line.starts_with("Finished")
+                && !line.starts_with("error: could not compile")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn idents(code: &str) -> impl Iterator<Item = String> + '_ {
+    code.split(|c: char| !is_ident_char(c))
+        .filter(|word| word.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        .map(ToOwned::to_owned)
+}
+
+const KEYWORDS: &[&str] = &[
+    "as",
+    "async",
+    "await",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "pub",
+    "ref",
+    "return",
+    "self",
+    "Self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "type",
+    "unsafe",
+    "use",
+    "where",
+    "while",
+    "println",
+    "print",
+    "eprintln",
+    "format",
+    "vec",
+    "assert",
+    "assert_eq",
+    "dbg",
+    "String",
+    "Vec",
+    "Option",
+    "Some",
+    "None",
+    "Result",
+    "Ok",
+    "Err",
+    "Box",
+    "HashMap",
+];
+
+/// Whether the cell needs more input (unclosed brackets or strings)
+pub fn is_complete(code: &str) -> bool {
+    let mut depth = 0_isize;
+    let mut in_string = false;
+    let mut chars = code.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '\'' if !in_string => skip_char_literal(code, idx, &mut chars),
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            _ => (),
+        }
+    }
+    depth <= 0 && !in_string
+}
+
+/// Skip the char literal that starts at `idx` (`'{'`, `'\''`, `'\u{7b}'`), a lifetime is left as is
+fn skip_char_literal(
+    code: &str,
+    idx: usize,
+    chars: &mut std::iter::Peekable<std::str::CharIndices>,
+) {
+    let mut literal = code[idx..].char_indices().skip(1);
+    let end = match literal.next() {
+        // The escaped char can be a quote
+        Some((_, '\\')) => literal.skip(1).find(|(_, c)| *c == '\''),
+        Some(_) => literal.next().filter(|(_, c)| *c == '\''),
+        None => None,
+    };
+    if let Some((end, _)) = end {
+        while chars.next_if(|(i, _)| *i <= idx + end).is_some() {}
+    }
+}
+
+/// Split the cell into the statements and the trailing expression (if any)
+fn split_cell(code: &str) -> (&str, Option<&str>) {
+    let mut depth = 0_isize;
+    let mut in_string = false;
+    let mut last_boundary = 0;
+    let mut chars = code.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '\'' if !in_string => skip_char_literal(code, idx, &mut chars),
+            '/' if !in_string && chars.peek().map(|(_, c)| *c) == Some('/') => {
+                // skip the comment
+                while chars.peek().is_some_and(|(_, c)| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            ';' if !in_string && depth == 0 => last_boundary = idx + 1,
+            // A line ending with a block is a statement (unless its the last one)
+            '\n' if !in_string
+                && depth == 0
+                && code[last_boundary..idx].trim_end().ends_with('}') =>
+            {
+                last_boundary = idx + 1;
+            }
+            _ => (),
+        }
+    }
+
+    let (statements, rest) = code.split_at(last_boundary);
+    let rest_code = rest
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let rest_code = rest_code.trim();
+    if rest_code.is_empty() || is_item(rest_code) {
+        (code, None)
+    } else {
+        (statements.trim(), Some(rest.trim()))
+    }
+}
+
+fn is_item(code: &str) -> bool {
+    matches!(
+        code.split_whitespace().collect::<Vec<_>>().as_slice(),
+        [_, "fn", ..]
+            | ["fn", ..]
+            | ["use", ..]
+            | ["enum", ..]
+            | ["struct", ..]
+            | ["trait", ..]
+            | ["impl", ..]
+            | ["pub", ..]
+            | ["extern", ..]
+            | ["mod", ..]
+            | ["macro_rules!", ..]
+    ) || code.starts_with('#')
+}
@@ -0,0 +1,404 @@
+mod connection;
+mod install;
+mod kernel;
+mod message;
+
+use connection::ConnectionInfo;
+use kernel::{Kernel, Output};
+use message::{Message, Wire, PROTOCOL_VERSION};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None | Some("-h") | Some("--help") => {
+            println!(
+                "IRust jupyter kernel
+        version: {VERSION}\n
+        irust_kernel install => install the kernel spec (kernel.json) for the current user
+        irust_kernel {{connection_file}} => start the kernel (this is done by jupyter)"
+            );
+        }
+        Some("-v") | Some("--version") => println!("{VERSION}"),
+        Some("install") => match install::install() {
+            Ok(dir) => println!("Installed IRust kernel in {}", dir.display()),
+            Err(e) => {
+                eprintln!("Failed to install IRust kernel: {e}");
+                std::process::exit(1);
+            }
+        },
+        Some(connection_file) => {
+            let runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+            if let Err(e) = runtime.block_on(run(Path::new(connection_file))) {
+                eprintln!("IRust kernel exited with error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+struct Channels {
+    wire: Wire,
+    iopub: AsyncMutex<PubSocket>,
+    kernel: Mutex<Kernel>,
+}
+
+async fn run(connection_file: &Path) -> Result<()> {
+    let connection = ConnectionInfo::from_file(connection_file)?;
+
+    let mut shell = RouterSocket::new();
+    shell
+        .bind(&connection.endpoint(connection.shell_port))
+        .await?;
+    let mut control = RouterSocket::new();
+    control
+        .bind(&connection.endpoint(connection.control_port))
+        .await?;
+    // stdin requests are not supported, the socket is bound so clients can connect
+    let mut _stdin = RouterSocket::new();
+    _stdin
+        .bind(&connection.endpoint(connection.stdin_port))
+        .await?;
+    let mut iopub = PubSocket::new();
+    iopub
+        .bind(&connection.endpoint(connection.iopub_port))
+        .await?;
+    let mut heartbeat = RepSocket::new();
+    heartbeat
+        .bind(&connection.endpoint(connection.hb_port))
+        .await?;
+
+    tokio::spawn(async move {
+        while let Ok(ping) = heartbeat.recv().await {
+            if heartbeat.send(ping).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let kernel = tokio::task::spawn_blocking(Kernel::new).await??;
+    let channels = Arc::new(Channels {
+        wire: Wire::new(&connection.key)?,
+        iopub: AsyncMutex::new(iopub),
+        kernel: Mutex::new(kernel),
+    });
+
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::mpsc::channel(1);
+    let control_channels = channels.clone();
+    tokio::spawn(async move {
+        while let Ok(message) = control.recv().await {
+            let Ok(message) = control_channels.wire.parse(message) else {
+                continue;
+            };
+            let (reply, shutdown) = handle_control(&control_channels, &message);
+            if let Some(Ok(reply)) = reply.map(|reply| control_channels.wire.serialize(reply)) {
+                let _ = control.send(reply).await;
+            }
+            if shutdown {
+                let _ = shutdown_tx.send(()).await;
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            message = shell.recv() => {
+                let Ok(message) = channels.wire.parse(message?) else {
+                    continue;
+                };
+                let shutdown = message.msg_type() == "shutdown_request";
+                let reply = handle_shell(channels.clone(), message).await?;
+                if let Some(reply) = reply {
+                    shell.send(channels.wire.serialize(reply)?).await?;
+                }
+                if shutdown {
+                    break;
+                }
+            }
+            _ = shutdown_rx.recv() => break,
+        }
+    }
+
+    Ok(())
+}
+
+fn kernel_info(channels: &Channels, parent: &Message) -> Message {
+    channels.wire.reply(
+        parent,
+        "kernel_info_reply",
+        json!({
+            "status": "ok",
+            "protocol_version": PROTOCOL_VERSION,
+            "implementation": "irust",
+            "implementation_version": VERSION,
+            "language_info": {
+                "name": "rust",
+                "version": rustc_version(),
+                "mimetype": "text/rust",
+                "file_extension": ".rs",
+                "pygments_lexer": "rust",
+                "codemirror_mode": "rust",
+            },
+            "banner": "IRust: Cross Platform Rust Repl",
+            "help_links": [],
+        }),
+    )
+}
+
+fn rustc_version() -> String {
+    std::process::Command::new("rustc")
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|version| version.split_whitespace().nth(1).map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
+
+/// Returns the reply and whether the kernel should shutdown, unsupported requests are ignored
+fn handle_control(channels: &Channels, message: &Message) -> (Option<Message>, bool) {
+    match message.msg_type() {
+        "shutdown_request" => {
+            kernel::interrupt();
+            (
+                Some(channels.wire.reply(
+                    message,
+                    "shutdown_reply",
+                    json!({"status": "ok", "restart": message.content["restart"]}),
+                )),
+                true,
+            )
+        }
+        "interrupt_request" => {
+            kernel::interrupt();
+            (
+                Some(
+                    channels
+                        .wire
+                        .reply(message, "interrupt_reply", json!({"status": "ok"})),
+                ),
+                false,
+            )
+        }
+        "kernel_info_request" => (Some(kernel_info(channels, message)), false),
+        _ => (None, false),
+    }
+}
+
+async fn publish(
+    channels: &Channels,
+    parent: &Message,
+    msg_type: &str,
+    content: Value,
+) -> Result<()> {
+    let message = channels
+        .wire
+        .serialize(channels.wire.broadcast(parent, msg_type, content))?;
+    channels.iopub.lock().await.send(message).await?;
+    Ok(())
+}
+
+async fn handle_shell(channels: Arc<Channels>, message: Message) -> Result<Option<Message>> {
+    publish(
+        &channels,
+        &message,
+        "status",
+        json!({"execution_state": "busy"}),
+    )
+    .await?;
+    let reply = match message.msg_type() {
+        "kernel_info_request" => Some(kernel_info(&channels, &message)),
+        "execute_request" => Some(execute(channels.clone(), &message).await?),
+        "complete_request" => {
+            let code = message.content["code"].as_str().unwrap_or_default();
+            let cursor_pos = message.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+            let (matches, cursor_start, cursor_end) = channels
+                .kernel
+                .lock()
+                .expect("kernel lock is not poisoned")
+                .complete(code, cursor_pos);
+            Some(channels.wire.reply(
+                &message,
+                "complete_reply",
+                json!({
+                    "status": "ok",
+                    "matches": matches,
+                    "cursor_start": cursor_start,
+                    "cursor_end": cursor_end,
+                    "metadata": {},
+                }),
+            ))
+        }
+        "inspect_request" => {
+            let code = message.content["code"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let cursor_pos = message.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+            let inner_channels = channels.clone();
+            let found = tokio::task::spawn_blocking(move || {
+                inner_channels
+                    .kernel
+                    .lock()
+                    .expect("kernel lock is not poisoned")
+                    .inspect(&code, cursor_pos)
+            })
+            .await?;
+            let data = match &found {
+                Some(text) => json!({ "text/plain": text }),
+                None => json!({}),
+            };
+            Some(channels.wire.reply(
+                &message,
+                "inspect_reply",
+                json!({
+                    "status": "ok",
+                    "found": found.is_some(),
+                    "data": data,
+                    "metadata": {},
+                }),
+            ))
+        }
+        "is_complete_request" => {
+            let code = message.content["code"].as_str().unwrap_or_default();
+            let content = if kernel::is_complete(code) {
+                json!({"status": "complete"})
+            } else {
+                json!({"status": "incomplete", "indent": "    "})
+            };
+            Some(channels.wire.reply(&message, "is_complete_reply", content))
+        }
+        "comm_info_request" => Some(channels.wire.reply(
+            &message,
+            "comm_info_reply",
+            json!({"status": "ok", "comms": {}}),
+        )),
+        "history_request" => Some(channels.wire.reply(
+            &message,
+            "history_reply",
+            json!({"status": "ok", "history": []}),
+        )),
+        "shutdown_request" => Some(channels.wire.reply(
+            &message,
+            "shutdown_reply",
+            json!({"status": "ok", "restart": message.content["restart"]}),
+        )),
+        _ => None,
+    };
+    publish(
+        &channels,
+        &message,
+        "status",
+        json!({"execution_state": "idle"}),
+    )
+    .await?;
+    Ok(reply)
+}
+
+async fn execute(channels: Arc<Channels>, message: &Message) -> Result<Message> {
+    static EXECUTION_COUNT: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+    let code = message.content["code"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let silent = message.content["silent"].as_bool().unwrap_or(false);
+    let store_history = message.content["store_history"].as_bool().unwrap_or(true) && !silent;
+    let execution_count = if store_history {
+        EXECUTION_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    } else {
+        EXECUTION_COUNT.load(std::sync::atomic::Ordering::SeqCst)
+    };
+
+    if !silent {
+        publish(
+            &channels,
+            message,
+            "execute_input",
+            json!({"code": code, "execution_count": execution_count}),
+        )
+        .await?;
+    }
+
+    let inner_channels = channels.clone();
+    let outputs = tokio::task::spawn_blocking(move || {
+        inner_channels
+            .kernel
+            .lock()
+            .expect("kernel lock is not poisoned")
+            .execute(&code)
+    })
+    .await?;
+
+    let mut error = None;
+    for output in outputs {
+        match output {
+            Output::Stream(text) if !silent => {
+                publish(
+                    &channels,
+                    message,
+                    "stream",
+                    json!({"name": "stdout", "text": text}),
+                )
+                .await?
+            }
+            Output::Stderr(text) if !silent => {
+                publish(
+                    &channels,
+                    message,
+                    "stream",
+                    json!({"name": "stderr", "text": text}),
+                )
+                .await?
+            }
+            Output::Value(value) if !silent => {
+                publish(
+                    &channels,
+                    message,
+                    "execute_result",
+                    json!({
+                        "execution_count": execution_count,
+                        "data": {"text/plain": value},
+                        "metadata": {},
+                    }),
+                )
+                .await?
+            }
+            Output::Error {
+                ename,
+                evalue,
+                traceback,
+            } => {
+                let content = json!({"ename": ename, "evalue": evalue, "traceback": traceback});
+                if !silent {
+                    publish(&channels, message, "error", content.clone()).await?;
+                }
+                error = Some(content);
+            }
+            _ => (),
+        }
+    }
+
+    let content = match error {
+        Some(mut content) => {
+            content["status"] = "error".into();
+            content["execution_count"] = execution_count.into();
+            content
+        }
+        None => json!({
+            "status": "ok",
+            "execution_count": execution_count,
+            "user_expressions": {},
+            "payload": [],
+        }),
+    };
+    Ok(channels.wire.reply(message, "execute_reply", content))
+}
@@ -0,0 +1,144 @@
+//! Jupyter wire protocol
+//!
+//! A message is sent as multiple frames:
+//! `[identities.., "<IDS|MSG>", signature, header, parent_header, metadata, content, buffers..]`
+use crate::Result;
+use bytes::Bytes;
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use zeromq::ZmqMessage;
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub identities: Vec<Bytes>,
+    pub header: Value,
+    pub parent_header: Value,
+    pub metadata: Value,
+    pub content: Value,
+}
+
+impl Message {
+    pub fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+/// Signs, verifies and creates messages for a kernel session
+pub struct Wire {
+    key: Option<Hmac<Sha256>>,
+    session: String,
+}
+
+impl Wire {
+    pub fn new(key: &str) -> Result<Self> {
+        let key = if key.is_empty() {
+            None
+        } else {
+            Some(Hmac::new_from_slice(key.as_bytes()).map_err(|e| e.to_string())?)
+        };
+        Ok(Self {
+            key,
+            session: uuid::Uuid::new_v4().to_string(),
+        })
+    }
+
+    fn signature(&self, parts: &[&[u8]]) -> String {
+        match &self.key {
+            Some(key) => {
+                let mut mac = key.clone();
+                for part in parts {
+                    mac.update(part);
+                }
+                hex::encode(mac.finalize().into_bytes())
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Check the hex encoded signature of a received message, in constant time
+    fn verify(&self, signature: &[u8], parts: &[&[u8]]) -> Result<()> {
+        let Some(key) = &self.key else {
+            return Ok(());
+        };
+        let mut mac = key.clone();
+        for part in parts {
+            mac.update(part);
+        }
+        let signature = hex::decode(signature).map_err(|_| "Invalid message signature")?;
+        mac.verify_slice(&signature)
+            .map_err(|_| "Invalid message signature".into())
+    }
+
+    fn header(&self, msg_type: &str) -> Value {
+        json!({
+            "msg_id": uuid::Uuid::new_v4().to_string(),
+            "session": self.session,
+            "username": "irust",
+            "date": chrono::Utc::now().to_rfc3339(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        })
+    }
+
+    /// A message sent in response to `parent`, replies on a ROUTER socket are routed back with the parent identities
+    pub fn reply(&self, parent: &Message, msg_type: &str, content: Value) -> Message {
+        Message {
+            identities: parent.identities.clone(),
+            header: self.header(msg_type),
+            parent_header: parent.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    /// A message published on iopub, the topic is the message type
+    pub fn broadcast(&self, parent: &Message, msg_type: &str, content: Value) -> Message {
+        Message {
+            identities: vec![Bytes::from(msg_type.to_string())],
+            ..self.reply(parent, msg_type, content)
+        }
+    }
+
+    pub fn parse(&self, message: ZmqMessage) -> Result<Message> {
+        let mut frames = message.into_vec();
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame.as_ref() == DELIMITER)
+            .ok_or("Missing message delimiter")?;
+        let mut parts = frames.split_off(delimiter + 1);
+        frames.pop(); // delimiter
+        if parts.len() < 5 {
+            return Err("Incomplete message".into());
+        }
+        self.verify(&parts[0], &[&parts[1], &parts[2], &parts[3], &parts[4]])?;
+        let mut parts = parts.drain(1..5).map(|part| serde_json::from_slice(&part));
+
+        Ok(Message {
+            identities: frames,
+            header: parts.next().expect("checked")?,
+            parent_header: parts.next().expect("checked")?,
+            metadata: parts.next().expect("checked")?,
+            content: parts.next().expect("checked")?,
+        })
+    }
+
+    pub fn serialize(&self, message: Message) -> Result<ZmqMessage> {
+        let parts = [
+            serde_json::to_vec(&message.header)?,
+            serde_json::to_vec(&message.parent_header)?,
+            serde_json::to_vec(&message.metadata)?,
+            serde_json::to_vec(&message.content)?,
+        ];
+        let signature = self.signature(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+
+        let mut frames = message.identities;
+        frames.push(Bytes::from_static(DELIMITER));
+        frames.push(Bytes::from(signature));
+        frames.extend(parts.into_iter().map(Bytes::from));
+        Ok(ZmqMessage::try_from(frames).expect("frames are not empty"))
+    }
+}
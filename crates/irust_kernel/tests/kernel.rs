@@ -0,0 +1,238 @@
+use bytes::Bytes;
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use zeromq::{DealerSocket, Socket, SocketRecv, SocketSend, SubSocket, ZmqMessage};
+
+const KEY: &str = "irust-test-key";
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn sign(parts: &[Vec<u8>]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(KEY.as_bytes()).unwrap();
+    for part in parts {
+        mac.update(part);
+    }
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn request(msg_type: &str, content: Value) -> ZmqMessage {
+    let parts = vec![
+        serde_json::to_vec(&json!({
+            "msg_id": uuid::Uuid::new_v4().to_string(),
+            "session": "test",
+            "username": "test",
+            "date": "",
+            "msg_type": msg_type,
+            "version": "5.3",
+        }))
+        .unwrap(),
+        b"{}".to_vec(),
+        b"{}".to_vec(),
+        serde_json::to_vec(&content).unwrap(),
+    ];
+    let mut frames = vec![Bytes::from("<IDS|MSG>"), Bytes::from(sign(&parts))];
+    frames.extend(parts.into_iter().map(Bytes::from));
+    ZmqMessage::try_from(frames).unwrap()
+}
+
+/// Returns the message type and content
+fn parse(message: ZmqMessage) -> (String, Value) {
+    let frames = message.into_vec();
+    let delimiter = frames
+        .iter()
+        .position(|frame| frame.as_ref() == b"<IDS|MSG>")
+        .unwrap();
+    let parts: Vec<Vec<u8>> = frames[delimiter + 2..delimiter + 6]
+        .iter()
+        .map(|part| part.to_vec())
+        .collect();
+    assert_eq!(frames[delimiter + 1], sign(&parts).as_bytes());
+    let header: Value = serde_json::from_slice(&parts[0]).unwrap();
+    (
+        header["msg_type"].as_str().unwrap().to_string(),
+        serde_json::from_slice(&parts[3]).unwrap(),
+    )
+}
+
+struct KillOnDrop(std::process::Child);
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn kernel() {
+    let ports: Vec<u16> = (0..5).map(|_| free_port()).collect();
+    let connection_file =
+        std::env::temp_dir().join(format!("irust_kernel_test_{}.json", std::process::id()));
+    std::fs::write(
+        &connection_file,
+        json!({
+            "transport": "tcp",
+            "ip": "127.0.0.1",
+            "shell_port": ports[0],
+            "iopub_port": ports[1],
+            "stdin_port": ports[2],
+            "control_port": ports[3],
+            "hb_port": ports[4],
+            "key": KEY,
+            "signature_scheme": "hmac-sha256",
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let _kernel = KillOnDrop(
+        std::process::Command::new(env!("CARGO_BIN_EXE_irust_kernel"))
+            .arg(&connection_file)
+            .spawn()
+            .unwrap(),
+    );
+    // wait for the kernel to bind its sockets
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    let mut shell = DealerSocket::new();
+    shell
+        .connect(&format!("tcp://127.0.0.1:{}", ports[0]))
+        .await
+        .unwrap();
+    let mut iopub = SubSocket::new();
+    iopub
+        .connect(&format!("tcp://127.0.0.1:{}", ports[1]))
+        .await
+        .unwrap();
+    iopub.subscribe("").await.unwrap();
+
+    shell
+        .send(request("kernel_info_request", json!({})))
+        .await
+        .unwrap();
+    let (msg_type, content) = parse(shell.recv().await.unwrap());
+    assert_eq!(msg_type, "kernel_info_reply");
+    assert_eq!(content["language_info"]["name"], "rust");
+
+    shell
+        .send(request(
+            "execute_request",
+            json!({"code": "let a = 4;\nlet s = String::new();\nprintln!(\"hello\");\na + 1", "silent": false}),
+        ))
+        .await
+        .unwrap();
+    let (msg_type, content) = parse(shell.recv().await.unwrap());
+    assert_eq!(msg_type, "execute_reply");
+    assert_eq!(content["status"], "ok");
+
+    let mut stream = None;
+    let mut result = None;
+    loop {
+        let (msg_type, content) = parse(iopub.recv().await.unwrap());
+        match msg_type.as_str() {
+            "stream" => stream = Some(content["text"].clone()),
+            "execute_result" => result = Some(content["data"]["text/plain"].clone()),
+            "status" if content["execution_state"] == "idle" && result.is_some() => break,
+            _ => (),
+        }
+    }
+    assert_eq!(stream, Some(json!("hello\n")));
+    assert_eq!(result, Some(json!("5")));
+
+    // A char literal is not an unclosed bracket, stderr is published as its own stream
+    for (code, status) in [
+        ("let c = '{';", "complete"),
+        ("let c = '\\'';", "complete"),
+        ("fn f<'a>(s: &'a str) -> &'a str {", "incomplete"),
+    ] {
+        shell
+            .send(request("is_complete_request", json!({ "code": code })))
+            .await
+            .unwrap();
+        let (_, content) = parse(shell.recv().await.unwrap());
+        assert_eq!(content["status"], status, "{code}");
+    }
+    shell
+        .send(request(
+            "execute_request",
+            json!({"code": "let c = '{';\neprintln!(\"warning\");\nc", "silent": false}),
+        ))
+        .await
+        .unwrap();
+    let (_, content) = parse(shell.recv().await.unwrap());
+    assert_eq!(content["status"], "ok");
+    let mut streams = vec![];
+    let mut result = None;
+    loop {
+        let (msg_type, content) = parse(iopub.recv().await.unwrap());
+        match msg_type.as_str() {
+            "stream" => streams.push((content["name"].clone(), content["text"].clone())),
+            "execute_result" => result = Some(content["data"]["text/plain"].clone()),
+            "status" if content["execution_state"] == "idle" && result.is_some() => break,
+            _ => (),
+        }
+    }
+    assert_eq!(streams, [(json!("stderr"), json!("warning\n"))]);
+    assert_eq!(result, Some(json!("'{'")));
+
+    // Without a value stdout is not replaced by stderr
+    shell
+        .send(request(
+            "execute_request",
+            json!({"code": "eprintln!(\"err\");", "silent": false}),
+        ))
+        .await
+        .unwrap();
+    parse(shell.recv().await.unwrap());
+    let mut streams = vec![];
+    loop {
+        let (msg_type, content) = parse(iopub.recv().await.unwrap());
+        match msg_type.as_str() {
+            "stream" => streams.push((content["name"].clone(), content["text"].clone())),
+            "execute_result" => panic!("unexpected result {content}"),
+            "status" if content["execution_state"] == "idle" && !streams.is_empty() => break,
+            _ => (),
+        }
+    }
+    assert_eq!(streams, [(json!("stderr"), json!("err\n"))]);
+
+    shell
+        .send(request(
+            "execute_request",
+            json!({"code": "a + undefined", "silent": false}),
+        ))
+        .await
+        .unwrap();
+    let (_, content) = parse(shell.recv().await.unwrap());
+    assert_eq!(content["status"], "error");
+    assert_eq!(content["ename"], "CompileError");
+
+    shell
+        .send(request(
+            "complete_request",
+            json!({"code": "print", "cursor_pos": 5}),
+        ))
+        .await
+        .unwrap();
+    let (_, content) = parse(shell.recv().await.unwrap());
+    assert!(content["matches"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("println")));
+
+    shell
+        .send(request(
+            "inspect_request",
+            json!({"code": "s", "cursor_pos": 1, "detail_level": 0}),
+        ))
+        .await
+        .unwrap();
+    let (_, content) = parse(shell.recv().await.unwrap());
+    assert_eq!(content["data"]["text/plain"], "s: String");
+
+    std::fs::remove_file(connection_file).unwrap();
+}
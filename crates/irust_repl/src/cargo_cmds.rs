@@ -1,4 +1,8 @@
-use super::Edition;
+use super::{
+    compile_mode::miri_toolchain,
+    pty::{self, Terminal},
+    sandbox, CompileMode, Edition, Limits, Profile, ResourceUsage,
+};
 use crate::Result;
 use crate::{
     utils::{stdout_and_stderr, ProcessUtils},
@@ -14,7 +18,7 @@ use std::{fs, process};
 
 static NO_COLOR: OnceLock<bool> = OnceLock::new();
 /// Have the top precedence
-fn no_color() -> bool {
+pub(crate) fn no_color() -> bool {
     *NO_COLOR.get_or_init(|| std::env::var("NO_COLOR").is_ok())
 }
 
@@ -24,6 +28,14 @@ const WRITE_LIB_LIMIT: &str = concat!(
     "\nUse :reset to reset the repl in that case"
 );
 
+/// Output of [`Cargo::cargo_run`]
+pub enum RunOutput {
+    /// Output of cargo: the json messages of a failed build, or a Miri run
+    Cargo(ExitStatus, String),
+    /// The executable ran, with the json messages of its build
+    Exe(process::Output, ResourceUsage, String),
+}
+
 #[derive(Debug, Clone)]
 pub struct Cargo {
     pub name: String,
@@ -44,12 +56,19 @@ pub struct CargoPaths {
     pub irust_dir: PathBuf,
     pub irust_target_dir: PathBuf,
     pub cargo_toml_file: PathBuf,
+    /// Cargo configuration of the repl crate, holds the rustc flags of the profile
+    pub cargo_config_file: PathBuf,
     pub irust_src_dir: PathBuf,
     pub main_file: PathBuf,
     pub main_file_extern: PathBuf,
     pub lib_file: PathBuf,
     pub exe_path: PathBuf,
     pub release_exe_path: PathBuf,
+    pub cdylib_path: PathBuf,
+    pub release_cdylib_path: PathBuf,
+    pub persistent_dir: PathBuf,
+    /// The writable directory of the sandboxed program, see [`crate::sandbox`]
+    pub sandbox_dir: PathBuf,
 }
 
 impl CargoPaths {
@@ -71,6 +90,7 @@ impl CargoPaths {
             common_root.join("target")
         })();
         let cargo_toml_file = irust_dir.join("Cargo.toml");
+        let cargo_config_file = irust_dir.join(".cargo").join("config.toml");
         let irust_src_dir = irust_dir.join("src");
         let main_file = irust_src_dir.join("main.rs");
         let main_file_extern = irust_src_dir.join("main_extern.rs");
@@ -85,18 +105,32 @@ impl CargoPaths {
         } else {
             irust_target_dir.join(format!("release/{}", &name))
         };
+        let cdylib_name = format!(
+            "{}{name}{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        );
+        let cdylib_path = irust_target_dir.join("debug").join(&cdylib_name);
+        let release_cdylib_path = irust_target_dir.join("release").join(&cdylib_name);
+        let persistent_dir = irust_dir.join("persistent");
+        let sandbox_dir = irust_dir.join("sandbox");
 
         Self {
             tmp_dir,
             irust_dir,
             irust_target_dir,
             cargo_toml_file,
+            cargo_config_file,
             irust_src_dir,
             main_file,
             main_file_extern,
             lib_file,
             exe_path,
             release_exe_path,
+            cdylib_path,
+            release_cdylib_path,
+            persistent_dir,
+            sandbox_dir,
             common_root,
         }
     }
@@ -137,46 +171,103 @@ edition = \"2021\""
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn cargo_run(
         &self,
         color: bool,
-        release: bool,
+        compile_mode: CompileMode,
         toolchain: ToolChain,
+        limits: &Limits,
+        sandbox: bool,
+        terminal: Option<&mut dyn Terminal>,
         interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
-    ) -> Result<(ExitStatus, String)> {
-        let (status, output) = self.cargo_build_output(color, release, toolchain)?;
-
+    ) -> Result<RunOutput> {
+        if let CompileMode::Miri = compile_mode {
+            if sandbox {
+                return Err("Miri can't run in the sandbox".into());
+            }
+            let (status, output) = self.cargo_miri_run(color, toolchain, interactive_function)?;
+            return Ok(RunOutput::Cargo(status, output));
+        }
+        let (status, build) =
+            self.cargo_build_json(color, compile_mode, toolchain, interactive_function)?;
         if !status.success() {
-            Ok((status, output))
+            return Ok(RunOutput::Cargo(status, build));
+        }
+
+        // Run the exexcutable directly instead of cargo run
+        // This allows to run it without modifying the current working directory
+        // example: std::process::Commmand::new("pwd") will output the expected path instead of `/tmp/irust_host_repl`
+        let exe_path = if compile_mode.is_release() {
+            &self.paths.release_exe_path
         } else {
-            // Run the exexcutable directly instead of cargo run
-            // This allows to run it without modifying the current working directory
-            // example: std::process::Commmand::new("pwd") will output the expected path instead of `/tmp/irust_host_repl`
-            if !release {
-                Ok((
-                    status,
-                    stdout_and_stderr(
-                        std::process::Command::new(&self.paths.exe_path)
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()?
-                            .interactive_output(interactive_function)?,
-                    ),
-                ))
-            } else {
-                Ok((
-                    status,
-                    stdout_and_stderr(
-                        std::process::Command::new(&self.paths.release_exe_path)
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()?
-                            .interactive_output(interactive_function)?,
-                    ),
-                ))
+            &self.paths.exe_path
+        };
+        let mut cmd = std::process::Command::new(exe_path);
+        limits.apply(&mut cmd);
+        if sandbox {
+            sandbox::apply(&mut cmd, &self.paths.sandbox_dir)?;
+        }
+        let (output, usage) = match terminal {
+            Some(terminal) => pty::run(cmd, terminal, limits.timeout())?,
+            None => {
+                // The timeout kills the processes started by the program too
+                #[cfg(unix)]
+                std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+                cmd.stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?
+                    .measured_output(interactive_function, limits.timeout())?
             }
+        };
+        Ok(RunOutput::Exe(output, usage, build))
+    }
+
+    /// Run the code with Miri, if it fails the output contains the json diagnostics of the build and Miri report
+    fn cargo_miri_run(
+        &self,
+        color: bool,
+        toolchain: ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+    ) -> Result<(ExitStatus, String)> {
+        let color = if no_color() {
+            "never"
+        } else if color {
+            "always"
+        } else {
+            "never"
+        };
+        let mut cmd = Command::new("cargo");
+        self.cargo_common(&mut cmd, "miri", miri_toolchain(toolchain))
+            .args(["run", "--quiet"])
+            .args(["--color", color])
+            .args(["--message-format", "json"]);
+        // Isolation makes the code fail when it accesses the environment, the filesystem or the clock
+        if std::env::var_os("MIRIFLAGS").is_none() {
+            cmd.env("MIRIFLAGS", "-Zmiri-disable-isolation");
+        }
+        let output = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .interactive_output(interactive_function)?;
+        let status = output.status;
+        if !status.success() {
+            return Ok((status, json_and_stderr(output)));
+        }
+
+        // The build messages are mixed with the program output
+        let stdout: String = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.starts_with("{\"reason\":"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        if stdout.is_empty() {
+            Ok((status, String::from_utf8_lossy(&output.stderr).into_owned()))
+        } else {
+            Ok((status, stdout))
         }
     }
 
@@ -231,21 +322,14 @@ edition = \"2021\""
 
     pub fn cargo_rm_sync(&self, dep: &str) -> Result<()> {
         // Ignore error if dependency doesn't exist
-        Command::new("cargo-rm")
-            .current_dir(&self.paths.irust_dir)
-            .arg("rm")
-            .arg(dep)
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::piped())
-            .spawn()?
-            .wait()?;
+        let _ = self.remove_dependency_offline(dep);
         Ok(())
     }
 
     // The difference in env flags makes cargo recompiles again!!!
     // => make  sure all build env flags are the same
     // Or even better dont use any
-    fn cargo_common<'a>(
+    pub(crate) fn cargo_common<'a>(
         &self,
         cargo: &'a mut process::Command,
         cmd: &str,
@@ -280,10 +364,11 @@ edition = \"2021\""
         let output = self
             .cargo_common(&mut cmd, "check", toolchain)
             .args(["--color", color])
+            .args(["--message-format", "json"])
             .output()?;
 
         let status = output.status;
-        Ok((status, stdout_and_stderr(output)))
+        Ok((status, json_and_stderr(output)))
     }
 
     pub fn cargo_build(
@@ -327,6 +412,135 @@ edition = \"2021\""
         Ok((status, stdout_and_stderr(output)))
     }
 
+    /// Build with json diagnostics, see [`crate::diagnostics`]
+    /// The build can be stopped by `interactive_function`, cargo is the leader of its process group so the compiler
+    /// processes can be killed with it
+    pub fn cargo_build_json(
+        &self,
+        color: bool,
+        compile_mode: CompileMode,
+        toolchain: ToolChain,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+    ) -> Result<(ExitStatus, String)> {
+        let color = if no_color() {
+            "never"
+        } else if color {
+            "always"
+        } else {
+            "never"
+        };
+        let mut cmd = Command::new("cargo");
+        self.cargo_common(&mut cmd, "build", toolchain)
+            .args(["--color", color])
+            .args(["--message-format", "json"]);
+        compile_mode_args(&mut cmd, compile_mode);
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let output = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .interactive_output(interactive_function)?;
+        let status = output.status;
+
+        Ok((status, json_and_stderr(output)))
+    }
+
+    /// Build `src/lib.rs` as a dynamic library with json diagnostics, used by the persistent mode
+    pub fn cargo_build_cdylib(
+        &self,
+        color: bool,
+        compile_mode: CompileMode,
+        toolchain: ToolChain,
+    ) -> std::result::Result<(ExitStatus, String), io::Error> {
+        let color = if no_color() {
+            "never"
+        } else if color {
+            "always"
+        } else {
+            "never"
+        };
+        let mut cmd = Command::new("cargo");
+        self.cargo_common(&mut cmd, "rustc", toolchain)
+            .args(["--lib", "--crate-type", "cdylib"])
+            .args(["--color", color])
+            .args(["--message-format", "json"]);
+        compile_mode_args(&mut cmd, compile_mode);
+        let output = cmd.output()?;
+        let status = output.status;
+
+        Ok((status, json_and_stderr(output)))
+    }
+
+    /// Run the tests of `main.rs`, the output contains the json diagnostics of the build and the libtest output
+    /// In the sandbox, the tests are built by cargo and their executable runs in the sandbox
+    pub fn cargo_test(
+        &self,
+        filter: Option<&str>,
+        color: bool,
+        toolchain: ToolChain,
+        sandbox: bool,
+        interactive_function: Option<fn(&mut process::Child) -> Result<()>>,
+    ) -> Result<(ExitStatus, String)> {
+        let color = if no_color() {
+            "never"
+        } else if color {
+            "always"
+        } else {
+            "never"
+        };
+        // The libtest output is parsed, see `test_runner`
+        let test_args = |cmd: &mut Command| {
+            cmd.args(["--show-output", "--color", "never"]);
+            if let Some(filter) = filter {
+                cmd.arg(filter);
+            }
+        };
+        let mut cmd = Command::new("cargo");
+        self.cargo_common(&mut cmd, "test", toolchain)
+            .args(["--color", color])
+            .args(["--message-format", "json"]);
+        if sandbox {
+            cmd.arg("--no-run");
+        } else {
+            cmd.arg("--");
+            test_args(&mut cmd);
+        }
+        let output = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .interactive_output(interactive_function)?;
+        if !sandbox || !output.status.success() {
+            return Ok((output.status, json_and_stderr(output)));
+        }
+
+        let build = json_and_stderr(output);
+        let exe = build
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .find(|message| {
+                message["reason"] == "compiler-artifact" && message["profile"]["test"] == true
+            })
+            .and_then(|message| message["executable"].as_str().map(PathBuf::from))
+            .ok_or("The test executable was not built")?;
+        // Like cargo test, the tests run in the crate directory
+        let mut cmd = Command::new(exe);
+        cmd.current_dir(&self.paths.irust_dir);
+        test_args(&mut cmd);
+        sandbox::apply(&mut cmd, &self.paths.sandbox_dir)?;
+        let output = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?
+            .interactive_output(interactive_function)?;
+
+        Ok((output.status, build + &json_and_stderr(output)))
+    }
+
     pub fn cargo_bench(&self, toolchain: ToolChain) -> std::result::Result<String, io::Error> {
         let color = if no_color() { "never" } else { "always" };
         let mut cmd = Command::new("cargo");
@@ -358,24 +572,32 @@ edition = \"2021\""
         Ok(fmt_c)
     }
 
-    pub fn cargo_asm(&self, fnn: &str, toolchain: ToolChain) -> Result<String> {
-        // 0 doesn't activate FORCE_COLOR (tested)
-        let force_color = if no_color() { "0" } else { "1" };
+    /// Emit the code of the library with rustc, `kind` is an `--emit` output type (asm, llvm-ir, mir)
+    pub(crate) fn cargo_emit(
+        &self,
+        kind: &str,
+        output_file: &Path,
+        release: bool,
+        toolchain: ToolChain,
+    ) -> Result<()> {
         let mut cmd = Command::new("cargo");
-        let output = self
-            .cargo_common(&mut cmd, "asm", toolchain)
-            .arg("--lib")
-            .arg(format!("{}::{fnn}", &self.name))
-            .arg("--rust")
-            .env("FORCE_COLOR", force_color)
+        self.cargo_common(&mut cmd, "rustc", toolchain)
+            .args(["--lib", "--quiet", "--color", "never"])
+            // Incremental compilation splits the code in multiple codegen units
+            .env("CARGO_INCREMENTAL", "0");
+        if release {
+            cmd.arg("--release");
+        }
+        let output = cmd
+            .arg("--")
+            .arg(format!("--emit={kind}={}", output_file.display()))
+            // Keep the whole library in one output, including the private and unused functions
+            .args(["-C", "codegen-units=1", "-C", "link-dead-code"])
             .output()?;
         if !output.status.success() {
-            return Err(
-            (stdout_and_stderr(output)
-            + "\nMaybe you should make the function `pub`, see https://github.com/pacak/cargo-show-asm#my-function-isnt-there" +
-                      WRITE_LIB_LIMIT).into());
+            return Err((stdout_and_stderr(output) + WRITE_LIB_LIMIT).into());
         }
-        Ok(stdout_and_stderr(output))
+        Ok(())
     }
 
     pub fn cargo_expand(&self, fnn: Option<&str>, toolchain: ToolChain) -> Result<String> {
@@ -401,6 +623,19 @@ edition = \"2021\""
         Ok(stdout_and_stderr(output).trim().to_owned())
     }
 
+    /// The markdown explanation of a compiler error code (exp: `E0382`)
+    pub fn rustc_explain(&self, code: &str, toolchain: ToolChain) -> Result<String> {
+        let mut cmd = Command::new("rustc");
+        if toolchain != ToolChain::Default {
+            cmd.arg(toolchain.as_arg());
+        }
+        let output = cmd.args(["--explain", code]).output()?;
+        if !output.status.success() {
+            return Err(stdout_and_stderr(output).trim().into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
     pub fn cargo_fmt_file(&self, file: &Path) {
         // Cargo fmt is optional
         let _ = self.try_cargo_fmt_file(file);
@@ -438,6 +673,51 @@ edition = \"{edition}\"",
         Ok(())
     }
 
+    /// Change the edition in `Cargo.toml`
+    pub fn set_edition(&self, edition: Edition) -> Result<()> {
+        let mut manifest: toml_edit::Document =
+            fs::read_to_string(&self.paths.cargo_toml_file)?.parse()?;
+        manifest["package"]["edition"] = toml_edit::value(edition.to_string());
+        fs::write(&self.paths.cargo_toml_file, manifest.to_string())?;
+        Ok(())
+    }
+
+    /// Write the profile settings to `Cargo.toml` and the rustc flags to the repl cargo config
+    pub fn set_profile(&self, profile: &Profile) -> Result<()> {
+        let mut manifest: toml_edit::Document =
+            fs::read_to_string(&self.paths.cargo_toml_file)?.parse()?;
+        manifest.remove("profile");
+        let settings = profile.cargo_settings();
+        if !settings.is_empty() {
+            let mut profiles = toml_edit::Table::new();
+            profiles.set_implicit(true);
+            for name in ["dev", "release"] {
+                let mut table = toml_edit::Table::new();
+                for (key, value) in &settings {
+                    table.insert(key, toml_edit::value(value.clone()));
+                }
+                profiles.insert(name, toml_edit::Item::Table(table));
+            }
+            manifest.insert("profile", toml_edit::Item::Table(profiles));
+        }
+        fs::write(&self.paths.cargo_toml_file, manifest.to_string())?;
+
+        let flags = profile.rustc_flags();
+        if flags.is_empty() {
+            let _ = fs::remove_file(&self.paths.cargo_config_file);
+        } else {
+            let _ = fs::create_dir_all(self.paths.irust_dir.join(".cargo"));
+            fs::write(
+                &self.paths.cargo_config_file,
+                format!(
+                    "[build]\nrustflags = {}\n",
+                    flags.into_iter().collect::<toml_edit::Array>()
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
     fn clean_files(&self) -> io::Result<()> {
         const MAIN_SRC: &str = "fn main() {\n\n}";
         let mut main = fs::File::create(&self.paths.main_file)?;
@@ -447,9 +727,91 @@ edition = \"{edition}\"",
         Ok(())
     }
 
+    /// The repl Cargo.toml, renamed to `name` and with relative paths made absolute
+    /// so it can be used from another location
+    pub fn exported_cargo_toml(&self, name: &str) -> io::Result<String> {
+        let cargo_toml = fs::read_to_string(&self.paths.cargo_toml_file)?;
+        let cargo_toml = cargo_toml.replacen(&self.name, name, 1);
+
+        const PATH_KEY: &str = "path = \"";
+        let mut exported = String::new();
+        let mut rest = cargo_toml.as_str();
+        while let Some(start) = rest.find(PATH_KEY) {
+            let (before, after) = rest.split_at(start + PATH_KEY.len());
+            exported.push_str(before);
+            let end = after.find('"').unwrap_or(after.len());
+            let path = Path::new(&after[..end]);
+            if path.is_relative() {
+                let path = self.paths.irust_dir.join(path);
+                let path = path.canonicalize().unwrap_or(path).display().to_string();
+                if cfg!(windows) {
+                    exported.push_str(&path.replace('\\', "\\\\"));
+                } else {
+                    exported.push_str(&path);
+                }
+            } else {
+                exported.push_str(&after[..end]);
+            }
+            rest = &after[end..];
+        }
+        exported.push_str(rest);
+
+        Ok(exported)
+    }
+
+    /// Copy the repl project to a new project called `name`, it uses the same target directory
+    /// The source files are not copied, they are written by each evaluation
+    pub(crate) fn snapshot(&self, name: &str) -> Result<Self> {
+        let cargo = Self {
+            name: name.to_string(),
+            paths: CargoPaths::new(name),
+        };
+        fs::create_dir_all(&cargo.paths.irust_src_dir)?;
+        fs::write(
+            &cargo.paths.cargo_toml_file,
+            self.exported_cargo_toml(name)?,
+        )?;
+        if self.paths.cargo_config_file.exists() {
+            fs::create_dir_all(cargo.paths.irust_dir.join(".cargo"))?;
+            fs::copy(
+                &self.paths.cargo_config_file,
+                &cargo.paths.cargo_config_file,
+            )?;
+        }
+        // Keep the resolved versions of the dependencies
+        if let Ok(lock) = fs::read_to_string(self.paths.irust_dir.join("Cargo.lock")) {
+            fs::write(
+                cargo.paths.irust_dir.join("Cargo.lock"),
+                lock.replace(&self.name, name),
+            )?;
+        }
+        Ok(cargo)
+    }
+
     /// Delete this repl specific folder, so for example `/tmp/irust_repls/irust_host_repl_$id` will
     /// be deleted
     pub fn delete_project(&self) -> io::Result<()> {
         std::fs::remove_dir_all(&self.paths.irust_dir)
     }
 }
+
+/// Select the cargo profile and the codegen backend of a build
+fn compile_mode_args(cmd: &mut Command, compile_mode: CompileMode) {
+    match compile_mode {
+        // Miri builds are done by `cargo miri run`
+        CompileMode::Debug | CompileMode::Miri => {}
+        CompileMode::Release => {
+            cmd.arg("--release");
+        }
+        // Cranelift uses the dev profile, so the executable path is the debug one
+        CompileMode::Cranelift => {
+            cmd.arg("-Zcodegen-backend")
+                .env("CARGO_PROFILE_DEV_CODEGEN_BACKEND", "cranelift");
+        }
+    }
+}
+
+/// The json messages are on stdout, but cargo errors (exp: an invalid manifest) are still printed on stderr
+fn json_and_stderr(output: process::Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
@@ -1,17 +1,84 @@
+use crate::ToolChain;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use std::process::Command;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum CompileMode {
     Debug,
     Release,
+    /// Debug build with the cranelift codegen backend, it compiles faster than llvm but generates slower code
+    ///
+    /// Requires a nightly toolchain with the `rustc-codegen-cranelift-preview` component
+    Cranelift,
+    /// Interpret the code with Miri (`cargo miri run`) to detect undefined behaviour
+    ///
+    /// Miri runs on nightly (the repl toolchain is used if it's a custom one), with the `miri` component
+    Miri,
 }
 impl CompileMode {
     pub fn is_release(&self) -> bool {
         matches!(self, Self::Release)
     }
+
+    /// Check that the compile mode can be used with this toolchain
+    pub fn check_available(&self, toolchain: &ToolChain) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Debug | Self::Release => Ok(()),
+            Self::Cranelift => {
+                // The component is listed with the host triple, exp: `rustc-codegen-cranelift-preview-x86_64-unknown-linux-gnu`
+                if has_component(toolchain, "rustc-codegen-cranelift")? {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "The cranelift backend is not installed for the `{toolchain}` toolchain, it requires a nightly toolchain, \
+                        you can install it with `rustup component add rustc-codegen-cranelift-preview --toolchain nightly`"
+                    )
+                    .into())
+                }
+            }
+            Self::Miri => {
+                let toolchain = miri_toolchain(toolchain.clone());
+                if has_component(&toolchain, "miri")? {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Miri is not installed for the `{toolchain}` toolchain, \
+                        you can install it with `rustup component add miri --toolchain {toolchain}`"
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+}
+
+/// Miri needs nightly, custom toolchains are used as is since they can be dated nightlies
+pub(crate) fn miri_toolchain(toolchain: ToolChain) -> ToolChain {
+    match toolchain {
+        ToolChain::Nightly | ToolChain::Custom(_) => toolchain,
+        _ => ToolChain::Nightly,
+    }
+}
+
+/// Check if a rustup component whose name starts with `component` is installed
+fn has_component(
+    toolchain: &ToolChain,
+    component: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut cmd = Command::new("rustup");
+    cmd.args(["component", "list", "--installed"]);
+    if *toolchain != ToolChain::Default {
+        cmd.args(["--toolchain", &toolchain.to_string()]);
+    }
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Failed to run rustup: {e}"))?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.starts_with(component)))
 }
 
 impl FromStr for CompileMode {
@@ -20,6 +87,8 @@ impl FromStr for CompileMode {
         match s.to_lowercase().as_ref() {
             "debug" => Ok(CompileMode::Debug),
             "release" => Ok(CompileMode::Release),
+            "cranelift" => Ok(CompileMode::Cranelift),
+            "miri" => Ok(CompileMode::Miri),
             _ => Err("Unknown compile mode".into()),
         }
     }
@@ -30,6 +99,8 @@ impl std::fmt::Display for CompileMode {
         match self {
             CompileMode::Debug => write!(f, "Debug"),
             CompileMode::Release => write!(f, "Release"),
+            CompileMode::Cranelift => write!(f, "Cranelift"),
+            CompileMode::Miri => write!(f, "Miri"),
         }
     }
 }
@@ -0,0 +1,372 @@
+//! Management of the repl dependencies
+//!
+//! The dependencies are edited directly in the repl `Cargo.toml`, then cargo resolves them to make sure the
+//! manifest is still valid, if it's not the previous manifest is restored
+use crate::cargo_cmds::Cargo;
+use crate::{Result, ToolChain};
+use std::path::Path;
+use std::process::Command;
+use toml_edit::{Array, Document, InlineTable, Item, TableLike};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    /// Version requirement, path or git source as written in `Cargo.toml`
+    pub source: String,
+    /// Version selected by cargo (if the dependency is already resolved)
+    pub version: Option<String>,
+    /// Features enabled in the resolved dependency graph, or the requested ones if it isn't resolved yet
+    pub features: Vec<String>,
+    /// All the features of the dependency (if it's resolved)
+    pub available_features: Vec<String>,
+}
+
+impl Cargo {
+    /// Dependencies of the repl with their resolved versions and features
+    pub fn dependencies(&self, toolchain: ToolChain) -> Result<Vec<Dependency>> {
+        let manifest = self.manifest()?;
+        let Some(deps) = manifest.get("dependencies").and_then(Item::as_table_like) else {
+            return Ok(vec![]);
+        };
+        // Resolution is only informative, use what's in the manifest if it fails
+        let metadata = self.metadata(toolchain).ok();
+
+        Ok(deps
+            .iter()
+            .map(|(name, item)| {
+                let (source, mut features) = describe(item);
+                let resolved = metadata
+                    .as_ref()
+                    .and_then(|metadata| resolved(metadata, name));
+                let mut dependency = Dependency {
+                    name: name.to_string(),
+                    source,
+                    version: None,
+                    features: vec![],
+                    available_features: vec![],
+                };
+                if let Some((version, resolved_features, available_features)) = resolved {
+                    dependency.version = Some(version);
+                    features = resolved_features;
+                    dependency.available_features = available_features;
+                }
+                dependency.features = features;
+                dependency
+            })
+            .collect())
+    }
+
+    /// Add a dependency to the crate at `path`
+    ///
+    /// Returns the dependency name
+    pub fn add_path_dependency(&self, path: &Path, toolchain: ToolChain) -> Result<String> {
+        let path = path.canonicalize()?;
+        let dep_manifest: Document = std::fs::read_to_string(path.join("Cargo.toml"))?.parse()?;
+        let name = dep_manifest
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(Item::as_str)
+            .ok_or("The crate has no package name")?
+            .to_string();
+
+        let mut dependency = InlineTable::new();
+        dependency.insert("path", path.display().to_string().into());
+        self.edit_dependencies(toolchain, |deps| {
+            deps.insert(&name, toml_edit::value(dependency.clone()));
+            Ok(())
+        })?;
+        Ok(name)
+    }
+
+    /// Add a dependency from a git repository, `reference` is `branch`, `tag` or `rev` with its value
+    ///
+    /// The dependency is named after the repository
+    /// Returns the dependency name
+    pub fn add_git_dependency(
+        &self,
+        url: &str,
+        reference: Option<(&str, &str)>,
+        toolchain: ToolChain,
+    ) -> Result<String> {
+        let name = url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(['/', ':'])
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or("Could not find the crate name from the git url")?
+            .to_string();
+
+        let mut dependency = InlineTable::new();
+        dependency.insert("git", url.into());
+        if let Some((kind, value)) = reference {
+            if !matches!(kind, "branch" | "tag" | "rev") {
+                return Err(format!("Unknown git reference `{kind}`").into());
+            }
+            dependency.insert(kind, value.into());
+        }
+        self.edit_dependencies(toolchain, |deps| {
+            deps.insert(&name, toml_edit::value(dependency.clone()));
+            Ok(())
+        })?;
+        Ok(name)
+    }
+
+    /// Remove the dependencies, none is removed if one of them is not a dependency
+    pub fn remove_dependencies(&self, names: &[&str], toolchain: ToolChain) -> Result<()> {
+        self.edit_dependencies(toolchain, |deps| {
+            names.iter().try_for_each(|name| remove(deps, name))
+        })
+    }
+
+    /// Remove a dependency without resolving the manifest again, so the network is not accessed
+    ///
+    /// Removing a dependency can't make the manifest invalid
+    pub(crate) fn remove_dependency_offline(&self, name: &str) -> Result<()> {
+        self.write_dependencies(|deps| remove(deps, name))?;
+        Ok(())
+    }
+
+    /// Change the version requirement of a dependency
+    pub fn set_dependency_version(
+        &self,
+        name: &str,
+        version: &str,
+        toolchain: ToolChain,
+    ) -> Result<()> {
+        self.edit_dependency(name, toolchain, |dependency| {
+            dependency.insert("version", toml_edit::value(version));
+        })
+    }
+
+    /// Enable or disable features of a dependency, `default` refers to its default features
+    pub fn set_dependency_features(
+        &self,
+        name: &str,
+        features: &[(&str, bool)],
+        toolchain: ToolChain,
+    ) -> Result<()> {
+        self.edit_dependency(name, toolchain, |dependency| {
+            let mut enabled: Vec<String> = dependency
+                .get("features")
+                .and_then(Item::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|feature| feature.as_str().map(ToOwned::to_owned))
+                .collect();
+            for (feature, enable) in features {
+                if *feature == "default" {
+                    if *enable {
+                        dependency.remove("default-features");
+                    } else {
+                        dependency.insert("default-features", toml_edit::value(false));
+                    }
+                    continue;
+                }
+                enabled.retain(|enabled| enabled != feature);
+                if *enable {
+                    enabled.push(feature.to_string());
+                }
+            }
+
+            if enabled.is_empty() {
+                dependency.remove("features");
+            } else {
+                dependency.insert(
+                    "features",
+                    toml_edit::value(enabled.into_iter().collect::<Array>()),
+                );
+            }
+        })
+    }
+
+    /// Update the dependency to the latest version compatible with its requirement
+    pub fn update_dependency(&self, name: &str, toolchain: ToolChain) -> Result<()> {
+        if !self
+            .manifest()?
+            .get("dependencies")
+            .and_then(Item::as_table_like)
+            .is_some_and(|deps| deps.contains_key(name))
+        {
+            return Err(not_a_dependency(name));
+        }
+        self.cargo_resolve("update", &["--quiet", "--package", name], toolchain)
+    }
+
+    fn manifest(&self) -> Result<Document> {
+        Ok(std::fs::read_to_string(&self.paths.cargo_toml_file)?.parse()?)
+    }
+
+    fn edit_dependency(
+        &self,
+        name: &str,
+        toolchain: ToolChain,
+        mut f: impl FnMut(&mut dyn TableLike),
+    ) -> Result<()> {
+        self.edit_dependencies(toolchain, |deps| {
+            let dependency = deps.get_mut(name).ok_or_else(|| not_a_dependency(name))?;
+            // `dep = "version"` is written as `dep = { version = "version" }` so it can be edited
+            if let Some(version) = dependency.as_str() {
+                let mut table = InlineTable::new();
+                table.insert("version", version.into());
+                *dependency = toml_edit::value(table);
+            }
+            f(dependency
+                .as_table_like_mut()
+                .ok_or_else(|| format!("Invalid dependency `{name}` in Cargo.toml"))?);
+            Ok(())
+        })
+    }
+
+    /// Edit the dependencies table of the manifest, the previous manifest is restored if the new one can't be resolved
+    fn edit_dependencies(
+        &self,
+        toolchain: ToolChain,
+        f: impl FnOnce(&mut dyn TableLike) -> Result<()>,
+    ) -> Result<()> {
+        let original = self.write_dependencies(f)?;
+        if let Err(e) = self.cargo_resolve("fetch", &["--quiet"], toolchain) {
+            std::fs::write(&self.paths.cargo_toml_file, original)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Edit the dependencies table of the manifest, returns the previous manifest
+    fn write_dependencies(
+        &self,
+        f: impl FnOnce(&mut dyn TableLike) -> Result<()>,
+    ) -> Result<String> {
+        let original = std::fs::read_to_string(&self.paths.cargo_toml_file)?;
+        let mut manifest: Document = original.parse()?;
+        if !manifest.contains_key("dependencies") {
+            manifest["dependencies"] = toml_edit::table();
+        }
+        f(manifest["dependencies"]
+            .as_table_like_mut()
+            .ok_or("Invalid dependencies table in Cargo.toml")?)?;
+        std::fs::write(&self.paths.cargo_toml_file, manifest.to_string())?;
+        Ok(original)
+    }
+
+    /// Run a cargo command that resolves the dependencies, with a hint in case the network is unreachable
+    fn cargo_resolve(&self, cmd: &str, args: &[&str], toolchain: ToolChain) -> Result<()> {
+        let output = self
+            .cargo_common(&mut Command::new("cargo"), cmd, toolchain)
+            .args(args)
+            .output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let mut error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        const NETWORK_ERRORS: &[&str] = &[
+            "resolve host",
+            "network failure",
+            "failed to download",
+            "failed to fetch",
+            "failed to get",
+        ];
+        if NETWORK_ERRORS.iter().any(|e| error.contains(e)) {
+            error.push_str(
+                "\nIt seems the network is unreachable, only dependencies in the cargo cache can be used while offline",
+            );
+        }
+        Err(error.into())
+    }
+
+    /// `cargo metadata` of the repl, without accessing the network
+    fn metadata(&self, toolchain: ToolChain) -> Result<serde_json::Value> {
+        let output = self
+            .cargo_common(&mut Command::new("cargo"), "metadata", toolchain)
+            .args(["--format-version", "1", "--offline"])
+            .output()?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+fn remove(deps: &mut dyn TableLike, name: &str) -> Result<()> {
+    deps.remove(name)
+        .map(|_| ())
+        .ok_or_else(|| not_a_dependency(name))
+}
+
+fn not_a_dependency(name: &str) -> Box<dyn std::error::Error> {
+    format!("`{name}` is not a dependency").into()
+}
+
+/// Source and requested features of a manifest dependency
+fn describe(item: &Item) -> (String, Vec<String>) {
+    if let Some(version) = item.as_str() {
+        return (version.to_string(), vec![]);
+    }
+    let Some(dependency) = item.as_table_like() else {
+        return (String::new(), vec![]);
+    };
+    let get = |key| dependency.get(key).and_then(Item::as_str);
+    let source = if let Some(path) = get("path") {
+        format!("path {path}")
+    } else if let Some(git) = get("git") {
+        let reference = ["branch", "tag", "rev"]
+            .into_iter()
+            .find_map(|kind| Some(format!(" ({kind} {})", get(kind)?)))
+            .unwrap_or_default();
+        format!("git {git}{reference}")
+    } else {
+        get("version").unwrap_or("*").to_string()
+    };
+    let features = dependency
+        .get("features")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|feature| feature.as_str().map(ToOwned::to_owned))
+        .collect();
+    (source, features)
+}
+
+/// Resolved version, enabled features and available features of the root package dependency `name`
+fn resolved(
+    metadata: &serde_json::Value,
+    name: &str,
+) -> Option<(String, Vec<String>, Vec<String>)> {
+    let resolve = &metadata["resolve"];
+    let root = resolve["root"].as_str()?;
+    let nodes = resolve["nodes"].as_array()?;
+    let node = |id: &str| nodes.iter().find(|node| node["id"] == id);
+    // The dependency name in the resolve graph uses underscores
+    let id = node(root)?["deps"]
+        .as_array()?
+        .iter()
+        .find(|dep| dep["name"] == name.replace('-', "_"))?["pkg"]
+        .as_str()?;
+    let package = metadata["packages"]
+        .as_array()?
+        .iter()
+        .find(|package| package["id"] == id)?;
+
+    let strings = |value: &serde_json::Value| -> Vec<String> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|s| s.as_str().map(ToOwned::to_owned))
+            .collect()
+    };
+    let mut available: Vec<String> = package["features"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(feature, _)| feature.clone())
+        .collect();
+    available.sort();
+
+    Some((
+        package["version"].as_str()?.to_string(),
+        strings(&node(id)?["features"]),
+        available,
+    ))
+}
@@ -0,0 +1,566 @@
+//! Compiler diagnostics parsed from cargo json messages (`--message-format json`)
+//!
+//! The spans point to the generated `main.rs`, a [`SourceMap`] maps them back to the user input or
+//! to the repl lines, spans inside the code added by IRust (main function, evaluator..) are hidden.
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+/// Where a span is located
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    /// The evaluated input, lines start at 1
+    Input,
+    /// A line previously inserted in the repl, lines are the ones used by `Repl::del`
+    Repl,
+}
+
+/// How confident rustc is about a suggested replacement
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Applicability {
+    MachineApplicable,
+    MaybeIncorrect,
+    HasPlaceholders,
+    Unspecified,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// Error code, exp: `E0425`
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<Span>,
+    /// Notes and help messages attached to this diagnostic
+    pub children: Vec<Diagnostic>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub origin: Origin,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// Columns are in chars and start at 1, the end is exclusive
+    pub column_start: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+    pub label: Option<String>,
+    pub suggested_replacement: Option<String>,
+    pub suggestion_applicability: Option<Applicability>,
+    /// The source lines covered by the span
+    pub text: Vec<String>,
+}
+
+/// Maps the lines of the generated `main.rs` back to their origin
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Indexed by `main.rs` line (starting at 0)
+    pub(crate) lines: Vec<Option<(Origin, usize)>>,
+    /// Chars added by IRust before the input on its first line
+    pub(crate) column_offset: usize,
+    /// The generated file is `lib.rs` (persistent mode) instead of `main.rs`
+    pub(crate) lib: bool,
+}
+
+impl SourceMap {
+    pub(crate) fn file(&self) -> &'static str {
+        if self.lib {
+            "lib.rs"
+        } else {
+            "main.rs"
+        }
+    }
+
+    /// Map the next lines to `input`, inserted after `prefix`
+    pub(crate) fn push_input(&mut self, prefix: &str, input: &str) {
+        self.lines
+            .extend(std::iter::repeat_n(None, prefix.matches('\n').count()));
+        self.lines
+            .extend((1..=input.lines().count().max(1)).map(|line| Some((Origin::Input, line))));
+        self.column_offset = prefix
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count();
+    }
+
+    fn line(&self, line: usize) -> Option<(Origin, usize)> {
+        *self.lines.get(line.checked_sub(1)?)?
+    }
+
+    pub(crate) fn locate(&self, line: usize, column: usize) -> Option<(Origin, usize, usize)> {
+        let (origin, line) = self.line(line)?;
+        if origin == Origin::Input && line == 1 {
+            Some((origin, line, column.checked_sub(self.column_offset)?))
+        } else {
+            Some((origin, line, column))
+        }
+    }
+}
+
+/// Collect the diagnostics from cargo json output
+///
+/// Diagnostics about the code added by IRust are dropped unless they are errors
+pub fn parse(output: &str, source_map: &SourceMap) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-message")
+        .filter_map(|message| parse_diagnostic(&message["message"], source_map))
+        .filter(|diagnostic| diagnostic.level == Level::Error || !diagnostic.spans.is_empty())
+        .collect()
+}
+
+/// Map the `src/main.rs` locations of diagnostics rendered by rustc (exp: a Miri report) back to their origin
+///
+/// The gutter line numbers are mapped too, the output can contain ansi colors
+pub fn map_rendered(output: &str, source_map: &SourceMap) -> String {
+    output
+        .lines()
+        .map(|line| map_rendered_line(line, source_map))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn map_rendered_line(line: &str, source_map: &SourceMap) -> String {
+    // (is an escape code, text)
+    let mut segments: Vec<(bool, &str)> = vec![];
+    let mut rest = line;
+    while !rest.is_empty() {
+        let end = if let Some(escape) = rest.strip_prefix("\x1b[") {
+            escape
+                .find(|c: char| c.is_ascii_alphabetic())
+                .map_or(rest.len(), |end| end + 3)
+        } else {
+            rest.char_indices()
+                .skip(1)
+                .find(|(_, c)| *c == '\x1b')
+                .map_or(rest.len(), |(end, _)| end)
+        };
+        segments.push((rest.starts_with("\x1b["), &rest[..end]));
+        rest = &rest[end..];
+    }
+
+    let visible: String = segments
+        .iter()
+        .filter(|(escape, _)| !escape)
+        .map(|(_, text)| *text)
+        .collect();
+    // `12 | code`, the number is the first digits of the line
+    let gutter_line = visible
+        .split_once(" |")
+        .map(|(number, _)| number.trim())
+        .filter(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+        .and_then(|number| number.parse().ok())
+        .and_then(|number| source_map.line(number));
+
+    let mut mapped = String::new();
+    let mut gutter_mapped = false;
+    for (escape, text) in segments {
+        if escape {
+            mapped.push_str(text);
+            continue;
+        }
+        let mut text = map_locations(text, source_map);
+        if let (Some((_, line)), false) = (gutter_line, gutter_mapped) {
+            if let Some(start) = text.find(|c: char| c.is_ascii_digit()) {
+                let len = text[start..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(text.len() - start);
+                // Keep the gutter width, the mapped line is never bigger than the `main.rs` one
+                text.replace_range(start..start + len, &format!("{line:<len$}"));
+                gutter_mapped = true;
+            }
+        }
+        mapped.push_str(&text);
+    }
+    mapped
+}
+
+/// Replace `src/main.rs:line:column` with `input:line:column` or `repl:line:column`
+fn map_locations(text: &str, source_map: &SourceMap) -> String {
+    let file = format!("src/{}:", source_map.file());
+    let mut mapped = String::new();
+    let mut rest = text;
+    // `line:column` at the start of `s` mapped, with its length
+    let position = |s: &str| -> Option<(Origin, usize, usize, usize)> {
+        let number = |s: &str| -> Option<(usize, usize)> {
+            let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            Some((s[..len].parse().ok()?, len))
+        };
+        let (line, line_len) = number(s)?;
+        let (column, column_len) = number(s[line_len..].strip_prefix(':')?)?;
+        let (origin, line, column) = source_map.locate(line, column)?;
+        Some((origin, line, column, line_len + 1 + column_len))
+    };
+    while let Some(start) = rest.find(&file) {
+        mapped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match position(&rest[file.len()..]) {
+            Some((origin, line, column, len)) => {
+                let _ = write!(mapped, "{}:{line}:{column}", origin.name());
+                rest = &rest[file.len() + len..];
+                // Miri backtraces contain the end of the span, exp: `src/main.rs:5:22: 5:32`
+                if let Some((_, line, column, len)) = rest.strip_prefix(": ").and_then(&position) {
+                    let _ = write!(mapped, ": {line}:{column}");
+                    rest = &rest[2 + len..];
+                }
+            }
+            None => {
+                mapped.push_str(&file);
+                rest = &rest[file.len()..];
+            }
+        }
+    }
+    mapped.push_str(rest);
+    mapped
+}
+
+fn parse_diagnostic(message: &Value, source_map: &SourceMap) -> Option<Diagnostic> {
+    let level = match message["level"].as_str()? {
+        "warning" => Level::Warning,
+        "note" => Level::Note,
+        "help" => Level::Help,
+        // error | error: internal compiler error
+        level if level.starts_with("error") => Level::Error,
+        // failure-note: `For more information about this error ..`
+        _ => return None,
+    };
+    let text = message["message"].as_str()?.to_string();
+    if text.starts_with("aborting due to") {
+        return None;
+    }
+
+    Some(Diagnostic {
+        level,
+        code: message["code"]["code"].as_str().map(ToOwned::to_owned),
+        message: text,
+        spans: message["spans"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|span| parse_span(span, source_map))
+            .collect(),
+        children: message["children"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|child| parse_diagnostic(child, source_map))
+            .collect(),
+    })
+}
+
+fn parse_span(span: &Value, source_map: &SourceMap) -> Option<Span> {
+    // Spans inside macros point to the macro definition, use the invocation instead
+    if span["file_name"] != format!("src/{}", source_map.file()) {
+        let expansion = &span["expansion"]["span"];
+        if expansion.is_null() {
+            return None;
+        }
+        return parse_span(expansion, source_map).map(|expansion| Span {
+            is_primary: span["is_primary"].as_bool().unwrap_or_default(),
+            label: span["label"].as_str().map(ToOwned::to_owned),
+            ..expansion
+        });
+    }
+
+    let position = |key: &str| span[key].as_u64().map(|n| n as usize);
+    let Some((origin, line_start, column_start)) =
+        source_map.locate(position("line_start")?, position("column_start")?)
+    else {
+        return parse_import_suggestion(span);
+    };
+    let (_, line_end, column_end) = source_map
+        .locate(position("line_end")?, position("column_end")?)
+        // The span starts in the repl lines and ends in the input, their lines can't be compared
+        .filter(|(end_origin, ..)| *end_origin == origin)
+        // The span ends inside the code added after the input
+        .unwrap_or((origin, line_start, usize::MAX));
+    let text = span["text"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|line| line["text"].as_str())
+        .enumerate()
+        .map(|(idx, line)| {
+            if origin == Origin::Input && line_start + idx == 1 {
+                line.chars().skip(source_map.column_offset).collect()
+            } else {
+                line.to_string()
+            }
+        })
+        .take((line_end + 1).saturating_sub(line_start))
+        .collect();
+
+    Some(Span {
+        origin,
+        line_start,
+        line_end,
+        column_start,
+        column_end,
+        is_primary: span["is_primary"].as_bool().unwrap_or_default(),
+        label: span["label"].as_str().map(ToOwned::to_owned),
+        suggested_replacement: span["suggested_replacement"]
+            .as_str()
+            .map(ToOwned::to_owned),
+        suggestion_applicability: parse_applicability(span),
+        text,
+    })
+}
+
+/// Imports are suggested at the top of `main.rs`, point them to the start of the input instead
+fn parse_import_suggestion(span: &Value) -> Option<Span> {
+    let replacement = span["suggested_replacement"].as_str()?;
+    if !replacement.trim_start().starts_with("use ") {
+        return None;
+    }
+    Some(Span {
+        origin: Origin::Input,
+        line_start: 1,
+        line_end: 1,
+        column_start: 1,
+        column_end: 1,
+        is_primary: false,
+        label: None,
+        suggested_replacement: Some(format!("{}\n", replacement.trim())),
+        suggestion_applicability: parse_applicability(span),
+        text: vec![],
+    })
+}
+
+fn parse_applicability(span: &Value) -> Option<Applicability> {
+    match span["suggestion_applicability"].as_str()? {
+        "MachineApplicable" => Some(Applicability::MachineApplicable),
+        "MaybeIncorrect" => Some(Applicability::MaybeIncorrect),
+        "HasPlaceholders" => Some(Applicability::HasPlaceholders),
+        _ => Some(Applicability::Unspecified),
+    }
+}
+
+/// Apply the compiler suggestions that point to `input` (the input the diagnostics were emitted for)
+///
+/// Suggestions that may be incorrect are applied too since the result is meant to be reviewed by the
+/// user, suggestions with placeholders are skipped. Returns `None` if there is nothing to apply.
+pub fn apply_suggestions(input: &str, diagnostics: &[Diagnostic]) -> Option<String> {
+    fn collect<'a>(diagnostics: &'a [Diagnostic], spans: &mut Vec<&'a Span>) {
+        for diagnostic in diagnostics {
+            spans.extend(diagnostic.spans.iter().filter(|span| {
+                span.origin == Origin::Input
+                    && span.suggested_replacement.is_some()
+                    && matches!(
+                        span.suggestion_applicability,
+                        Some(Applicability::MachineApplicable | Applicability::MaybeIncorrect)
+                    )
+            }));
+            collect(&diagnostic.children, spans);
+        }
+    }
+    let mut spans = vec![];
+    collect(diagnostics, &mut spans);
+
+    let lines: Vec<&str> = input.split('\n').collect();
+    // (line, column) -> char offset in the input
+    let offset = |line: usize, column: usize| -> Option<usize> {
+        let line_len = lines.get(line.checked_sub(1)?)?.chars().count();
+        let before: usize = lines[..line - 1]
+            .iter()
+            .map(|line| line.chars().count() + 1)
+            .sum();
+        Some(before + column.saturating_sub(1).min(line_len))
+    };
+    let mut replacements: Vec<(usize, usize, &str)> = spans
+        .into_iter()
+        .filter_map(|span| {
+            Some((
+                offset(span.line_start, span.column_start)?,
+                offset(span.line_end, span.column_end)?,
+                span.suggested_replacement.as_deref()?,
+            ))
+        })
+        .collect();
+    replacements.sort_by_key(|(start, end, _)| (*start, *end));
+    replacements.dedup();
+    if replacements.is_empty() {
+        return None;
+    }
+
+    let mut fixed: Vec<char> = input.chars().collect();
+    // Apply from the end so the previous offsets stay valid, overlapping suggestions are dropped
+    let mut applied_start = usize::MAX;
+    for (start, end, replacement) in replacements.into_iter().rev() {
+        if end > applied_start {
+            continue;
+        }
+        fixed.splice(start..end, replacement.chars());
+        applied_start = start;
+    }
+    Some(fixed.into_iter().collect())
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;91m";
+const YELLOW: &str = "\x1b[1;93m";
+const GREEN: &str = "\x1b[1;92m";
+const BLUE: &str = "\x1b[1;94m";
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        }
+    }
+    fn color(&self) -> &'static str {
+        match self {
+            Level::Error => RED,
+            Level::Warning => YELLOW,
+            Level::Note | Level::Help => GREEN,
+        }
+    }
+}
+
+impl Origin {
+    fn name(&self) -> &'static str {
+        match self {
+            Origin::Input => "input",
+            Origin::Repl => "repl",
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Render the diagnostic like rustc does, but with the user code as the source
+    pub fn render(&self, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{style}{text}{RESET}")
+            } else {
+                text.to_string()
+            }
+        };
+        // Warnings codes are lint names, rustc doesn't show them here
+        let level = match &self.code {
+            Some(code) if self.level == Level::Error => format!("{}[{code}]", self.level.name()),
+            _ => self.level.name().to_string(),
+        };
+        let mut rendered = format!(
+            "{}{}",
+            paint(self.level.color(), &level),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let gutter = self
+            .spans
+            .iter()
+            .chain(self.children.iter().flat_map(|child| &child.spans))
+            .map(|span| span.line_end.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let bar = paint(BLUE, &format!("{:gutter$} |", ""));
+
+        if let Some(span) = self
+            .spans
+            .iter()
+            .find(|span| span.is_primary)
+            .or(self.spans.first())
+        {
+            let _ = write!(
+                rendered,
+                "\n{}{}:{}:{}\n{bar}",
+                paint(BLUE, &format!("{:gutter$}--> ", "")),
+                span.origin.name(),
+                span.line_start,
+                span.column_start
+            );
+        }
+        // Spans on the same line are rendered under the same source line
+        let mut spans: Vec<&Span> = self.spans.iter().collect();
+        spans.sort_by_key(|span| {
+            (
+                span.origin == Origin::Input,
+                span.line_start,
+                span.column_start,
+            )
+        });
+        for (idx, span) in spans.iter().enumerate() {
+            let same_line = idx > 0
+                && spans[idx - 1].origin == span.origin
+                && spans[idx - 1].line_start == span.line_start;
+            if !same_line {
+                let _ = write!(
+                    rendered,
+                    "\n{} {}",
+                    paint(BLUE, &format!("{:<gutter$} |", span.line_start)),
+                    span.text.first().map(String::as_str).unwrap_or_default()
+                );
+            }
+            let line_len = span.text.first().map_or(0, |line| line.chars().count());
+            let end = if span.line_end == span.line_start {
+                span.column_end.min(line_len + 1)
+            } else {
+                line_len + 1
+            };
+            let (marker, style) = if span.is_primary {
+                ("^", self.level.color())
+            } else {
+                ("-", BLUE)
+            };
+            let underline = format!(
+                "{}{}",
+                marker.repeat(end.saturating_sub(span.column_start).max(1)),
+                span.label
+                    .as_ref()
+                    .map(|label| format!(" {label}"))
+                    .unwrap_or_default()
+            );
+            let _ = write!(
+                rendered,
+                "\n{bar} {}{}",
+                " ".repeat(span.column_start.saturating_sub(1)),
+                paint(style, &underline)
+            );
+        }
+
+        for child in &self.children {
+            let replacements: Vec<String> = child
+                .spans
+                .iter()
+                .filter_map(|span| span.suggested_replacement.as_ref())
+                .map(|replacement| format!("`{}`", replacement.trim_end()))
+                .collect();
+            let message = if replacements.is_empty() {
+                child.message.clone()
+            } else {
+                format!("{}: {}", child.message, replacements.join(", "))
+            };
+            let _ = write!(
+                rendered,
+                "\n{} {}: {message}",
+                paint(BLUE, &format!("{:gutter$} =", "")),
+                paint(BOLD, child.level.name()),
+            );
+        }
+
+        rendered
+    }
+}
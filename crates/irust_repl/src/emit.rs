@@ -0,0 +1,229 @@
+//! Assembly, LLVM IR and MIR of the repl functions, emitted by rustc
+//!
+//! The repl items (fn, struct, impl..) are compiled as a library, with `link-dead-code` so private and unused
+//! functions are generated too
+use crate::persistent::repl_chunks;
+use crate::utils::is_allowed_in_lib;
+use crate::{Repl, Result, ToolChain};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitKind {
+    Asm,
+    LlvmIr,
+    Mir,
+}
+
+impl EmitKind {
+    fn rustc_name(&self) -> &'static str {
+        match self {
+            EmitKind::Asm => "asm",
+            EmitKind::LlvmIr => "llvm-ir",
+            EmitKind::Mir => "mir",
+        }
+    }
+}
+
+impl Repl {
+    /// The code of the functions named `function` (or whose path ends with `::function`)
+    pub fn emit(
+        &self,
+        kind: EmitKind,
+        function: &str,
+        release: bool,
+        toolchain: ToolChain,
+    ) -> Result<String> {
+        let (crate_attributes, chunks) = repl_chunks(self);
+        let items = chunks.into_iter().filter(|chunk| {
+            chunk
+                .lines()
+                .find(|line| !line.trim_start().starts_with("//"))
+                .is_some_and(is_allowed_in_lib)
+        });
+        let mut lib = crate_attributes;
+        lib.push("#![allow(unused)]".into());
+        lib.extend(items);
+        std::fs::write(&self.cargo.paths.lib_file, lib.join("\n"))?;
+        let output_file = self
+            .cargo
+            .paths
+            .irust_target_dir
+            .join(format!("irust_emit.{}", kind.rustc_name()));
+        let result = self
+            .cargo
+            .cargo_emit(kind.rustc_name(), &output_file, release, toolchain);
+        std::fs::remove_file(&self.cargo.paths.lib_file)?;
+        result?;
+
+        let emitted = std::fs::read_to_string(&output_file)?;
+        let functions = match kind {
+            EmitKind::Asm => asm_functions(&emitted, function),
+            EmitKind::LlvmIr => llvm_ir_functions(&emitted, function),
+            EmitKind::Mir => mir_functions(&emitted, function),
+        };
+        if functions.is_empty() {
+            return Err(format!("Function `{function}` not found in the repl items").into());
+        }
+        // The repl crate name is noise
+        Ok(functions
+            .join("\n\n")
+            .replace(&format!("{}::", self.cargo.name), ""))
+    }
+}
+
+fn matches_function(name: &str, function: &str) -> bool {
+    name == function || name.ends_with(&format!("::{function}"))
+}
+
+fn asm_functions(asm: &str, function: &str) -> Vec<String> {
+    let mut functions = vec![];
+    let mut lines = asm.lines();
+    while let Some(line) = lines.next() {
+        // Mach-O symbols have an extra `_` prefix
+        let Some(symbol) = line
+            .strip_suffix(':')
+            .map(|symbol| symbol.strip_prefix("__ZN").map_or(symbol, |_| &symbol[1..]))
+        else {
+            continue;
+        };
+        if !demangle(symbol).is_some_and(|name| matches_function(&name, function)) {
+            continue;
+        }
+        let mut code = vec![demangle_symbols(line)];
+        for line in lines.by_ref() {
+            let trimmed = line.trim();
+            if trimmed.starts_with(".Lfunc_end") {
+                break;
+            }
+            // Directives and debug info labels
+            if trimmed.starts_with('.') && !trimmed.ends_with(':')
+                || trimmed.starts_with(".Ltmp")
+                || trimmed.starts_with(".Lfunc_begin")
+            {
+                continue;
+            }
+            code.push(demangle_symbols(line));
+        }
+        functions.push(code.join("\n"));
+    }
+    functions
+}
+
+fn llvm_ir_functions(ir: &str, function: &str) -> Vec<String> {
+    let mut functions = vec![];
+    let mut lines = ir.lines();
+    while let Some(line) = lines.next() {
+        if !line.starts_with("define ") {
+            continue;
+        }
+        let Some(symbol) = line
+            .split_once('@')
+            .map(|(_, symbol)| symbol.trim_start_matches('"'))
+        else {
+            continue;
+        };
+        if !demangle(symbol_prefix(symbol).0).is_some_and(|name| matches_function(&name, function))
+        {
+            continue;
+        }
+        let mut code = vec![line];
+        for line in lines.by_ref() {
+            // Debug info records
+            if line.trim_start().starts_with("#dbg_") {
+                continue;
+            }
+            code.push(line);
+            if line == "}" {
+                break;
+            }
+        }
+        functions.push(
+            code.into_iter()
+                .map(|line| demangle_symbols(&strip_debug_metadata(line)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+    functions
+}
+
+fn mir_functions(mir: &str, function: &str) -> Vec<String> {
+    let mut functions = vec![];
+    let mut lines = mir.lines();
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .strip_prefix("fn ")
+            .and_then(|line| line.split_once('('))
+            .map(|(name, _)| name)
+        else {
+            continue;
+        };
+        if !matches_function(name, function) {
+            continue;
+        }
+        let mut code = vec![line];
+        for line in lines.by_ref() {
+            code.push(line);
+            if line == "}" {
+                break;
+            }
+        }
+        functions.push(code.join("\n"));
+    }
+    functions
+}
+
+/// Remove the `!dbg !12` annotations of the LLVM IR instructions
+fn strip_debug_metadata(line: &str) -> String {
+    match line.find(", !dbg !").or_else(|| line.find(" !dbg !")) {
+        Some(start) => {
+            let end = line[start..]
+                .find(" !dbg !")
+                .map(|dbg| start + dbg + " !dbg !".len())
+                .unwrap_or(line.len());
+            let end = line[end..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(line.len(), |digits| end + digits);
+            format!("{}{}", &line[..start], &line[end..])
+        }
+        None => line.to_string(),
+    }
+}
+
+/// Demangle the mangled symbols of a line
+fn demangle_symbols(line: &str) -> String {
+    let mut demangled = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("_ZN").into_iter().chain(rest.find("_R")).min() {
+        demangled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let (symbol, len) = symbol_prefix(rest);
+        match demangle(symbol) {
+            Some(name) => {
+                demangled.push_str(&name);
+                rest = &rest[len..];
+            }
+            None => {
+                demangled.push_str(&rest[..2]);
+                rest = &rest[2..];
+            }
+        }
+    }
+    demangled.push_str(rest);
+    demangled
+}
+
+/// The symbol at the start of `s`, with its length
+fn symbol_prefix(s: &str) -> (&str, usize) {
+    let len = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.')))
+        .unwrap_or(s.len());
+    (&s[..len], len)
+}
+
+/// Demangle a symbol without its hash, exp: `_ZN15irust_host_repl3add17h0123456789abcdefE` or
+/// `_RNvCs1234_4core5panic`
+pub fn demangle(symbol: &str) -> Option<String> {
+    rustc_demangle::try_demangle(symbol)
+        .ok()
+        .map(|demangled| format!("{demangled:#}"))
+}
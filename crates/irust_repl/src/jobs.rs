@@ -0,0 +1,184 @@
+//! Background evaluations: the repl is copied to its own cargo project when the job is launched, so the job
+//! compiles and runs in a thread while the repl keeps being used and modified
+//!
+//! The projects share the target directory, so the dependencies are not rebuilt (the builds wait for each other)
+use crate::utils::kill_process_group;
+use crate::{CompileMode, EvalConfig, EvalResult, Repl, Result};
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    /// The kill flag of the job running on this thread, see [`kill_point`]
+    static KILLED: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Running,
+    /// The program ran successfully
+    Done,
+    /// The build or the program failed
+    Failed,
+    Killed,
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "running"),
+            JobStatus::Done => write!(f, "done"),
+            JobStatus::Failed => write!(f, "failed"),
+            JobStatus::Killed => write!(f, "killed"),
+        }
+    }
+}
+
+type JobResult = std::result::Result<EvalResult, String>;
+
+/// An evaluation running in the background, see [`Repl::spawn_job`]
+///
+/// Dropping the job kills its program
+pub struct Job {
+    pub input: String,
+    evaluator: Vec<String>,
+    compile_mode: CompileMode,
+    start: Instant,
+    end: Option<Instant>,
+    killed: Arc<AtomicBool>,
+    /// The result and the end time of the job
+    handle: Option<JoinHandle<(JobResult, Instant)>>,
+    result: Option<JobResult>,
+}
+
+impl Job {
+    /// Evaluate `input` with `repl`, the snapshot of the repl
+    pub(crate) fn spawn(
+        mut repl: Repl,
+        input: String,
+        color: bool,
+        evaluator: Vec<String>,
+        compile_mode: CompileMode,
+    ) -> Self {
+        let killed = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let input = input.clone();
+            let evaluator = evaluator.clone();
+            let killed = killed.clone();
+            std::thread::spawn(move || {
+                // The job can be killed before its thread starts
+                if killed.load(Ordering::Relaxed) {
+                    return (Err("Killed!".to_string()), Instant::now());
+                }
+                KILLED.with(|flag| *flag.borrow_mut() = Some(killed));
+                let result = repl
+                    .eval_with_configuration(EvalConfig {
+                        input,
+                        interactive_function: Some(kill_point),
+                        color,
+                        evaluator: &evaluator,
+                        compile_mode,
+                        terminal: None,
+                    })
+                    .map_err(|e| e.to_string());
+                (result, Instant::now())
+            })
+        };
+        Self {
+            input,
+            evaluator,
+            compile_mode,
+            start: Instant::now(),
+            end: None,
+            killed,
+            handle: Some(handle),
+            result: None,
+        }
+    }
+
+    pub fn evaluator(&self) -> &[String] {
+        &self.evaluator
+    }
+
+    pub fn compile_mode(&self) -> CompileMode {
+        self.compile_mode
+    }
+
+    /// Check if the job finished, returns true only the first time it's noticed
+    pub fn poll(&mut self) -> bool {
+        if !self.handle.as_ref().is_some_and(JoinHandle::is_finished) {
+            return false;
+        }
+        self.join();
+        true
+    }
+
+    pub fn status(&mut self) -> JobStatus {
+        self.poll();
+        if self.killed.load(Ordering::Relaxed) {
+            return JobStatus::Killed;
+        }
+        match &self.result {
+            None => JobStatus::Running,
+            Some(Ok(result)) if result.status.success() && result.runtime_failure.is_none() => {
+                JobStatus::Done
+            }
+            Some(_) => JobStatus::Failed,
+        }
+    }
+
+    /// Time since the launch, or how long the job took if it finished
+    pub fn elapsed(&self) -> Duration {
+        self.end.unwrap_or_else(Instant::now) - self.start
+    }
+
+    /// Kill the build or the program of the job
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait for the job to finish and return its result
+    pub fn wait(mut self) -> Result<EvalResult> {
+        self.join();
+        if self.killed.load(Ordering::Relaxed) {
+            return Err("The job was killed".into());
+        }
+        Ok(self.result.take().expect("the job is joined")?)
+    }
+
+    fn join(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let (result, end) = handle
+                .join()
+                .unwrap_or_else(|_| (Err("The job panicked".to_string()), Instant::now()));
+            self.result = Some(result);
+            self.end = Some(end);
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// The interactive function of the jobs, kills the build or the program when its job is killed
+fn kill_point(child: &mut Child) -> Result<()> {
+    let killed = KILLED.with(|killed| {
+        killed
+            .borrow()
+            .as_ref()
+            .is_some_and(|killed| killed.load(Ordering::Relaxed))
+    });
+    if killed {
+        kill_process_group(child)?;
+        return Err("Killed!".into());
+    }
+    std::thread::sleep(Duration::from_millis(1));
+    Ok(())
+}
@@ -10,14 +10,34 @@ mod edition;
 pub use edition::Edition;
 mod compile_mode;
 pub use compile_mode::CompileMode;
+mod profile;
+pub use profile::Profile;
+mod limits;
+pub use limits::{Limits, ResourceUsage};
+pub mod dependencies;
+pub mod diagnostics;
+use diagnostics::{Diagnostic, Level, Origin, SourceMap};
+mod persistent;
+use persistent::PersistentState;
+pub mod emit;
+pub mod jobs;
+use jobs::Job;
+pub mod pty;
+pub use pty::{Terminal, TerminalInput};
+pub mod runtime;
+pub mod sandbox;
+pub mod test_runner;
+pub mod type_name;
 
 use once_cell::sync::Lazy;
 mod utils;
+use utils::stdout_and_stderr;
 
 use std::{
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::{Child, ExitStatus},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -31,12 +51,24 @@ pub struct EvalConfig<'a, S: ToString> {
     pub color: bool,
     pub evaluator: &'a [String],
     pub compile_mode: CompileMode,
+    /// Streaming mode: run the program attached to this terminal instead of collecting its output (unix only),
+    /// `interactive_function` is not used, the terminal is ignored in persistent mode and with Miri
+    pub terminal: Option<&'a mut dyn Terminal>,
 }
 
 #[derive(Debug)]
 pub struct EvalResult {
     pub output: String,
     pub status: ExitStatus,
+    /// Compiler diagnostics (the warnings too when the program ran), if the compilation failed the output contains
+    /// the rendered errors
+    pub diagnostics: Vec<Diagnostic>,
+    /// What the program printed to stderr, when it ran
+    pub stderr: String,
+    /// The program failed at runtime (panic, exit code, signal), the output contains what it printed to stdout
+    pub runtime_failure: Option<String>,
+    /// Resources used by the program, when it ran (not in persistent or Miri mode)
+    pub usage: Option<ResourceUsage>,
 }
 
 impl From<(ExitStatus, String)> for EvalResult {
@@ -44,6 +76,45 @@ impl From<(ExitStatus, String)> for EvalResult {
         Self {
             output: result.1,
             status: result.0,
+            diagnostics: vec![],
+            stderr: String::new(),
+            runtime_failure: None,
+            usage: None,
+        }
+    }
+}
+
+impl EvalResult {
+    /// Parse the json diagnostics of a failed build
+    fn build_failure(
+        status: ExitStatus,
+        output: String,
+        source_map: &SourceMap,
+        color: bool,
+    ) -> Self {
+        let diagnostics = diagnostics::parse(&output, source_map);
+        let errors: Vec<String> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == Level::Error)
+            .map(|diagnostic| diagnostic.render(color && !no_color()))
+            .collect();
+        let output = if errors.is_empty() {
+            // Not a compiler error, only keep cargo output
+            output
+                .lines()
+                .filter(|line| !line.starts_with('{'))
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            errors.join("\n\n")
+        };
+        Self {
+            output,
+            status,
+            diagnostics,
+            stderr: String::new(),
+            runtime_failure: None,
+            usage: None,
         }
     }
 }
@@ -56,7 +127,14 @@ pub struct Repl {
     executor: Executor,
     main_result: MainResult,
     edition: Edition,
+    profile: Profile,
+    limits: Limits,
+    /// Run the evaluated program in the [`sandbox`]
+    sandbox: bool,
+    /// `#[test]` functions, see [`test_runner`]
+    tests: Vec<String>,
     prelude: Option<PathBuf>,
+    persistent: Option<PersistentState>,
     pub cargo: Cargo,
 }
 impl Default for Repl {
@@ -77,7 +155,7 @@ impl Drop for Repl {
     }
 }
 
-const PRELUDE_NAME: &str = "irust_prelude";
+pub const PRELUDE_NAME: &str = "irust_prelude";
 
 impl Repl {
     pub fn new(
@@ -103,7 +181,7 @@ impl Repl {
             // NOTE: This code blocks
             cargo.cargo_add_sync(&dependecy)?;
         }
-        cargo.cargo_build(toolchain)?;
+        cargo.cargo_build(toolchain.clone())?;
 
         let (header, footer) = Self::generate_body_delimiters(executor, main_result);
         let (body, cursor) = if prelude_parent_path.is_some() {
@@ -126,7 +204,12 @@ impl Repl {
             executor,
             main_result,
             edition,
+            profile: Profile::default(),
+            limits: Limits::default(),
+            sandbox: false,
+            tests: vec![],
             prelude: prelude_parent_path,
+            persistent: None,
             cargo,
         })
     }
@@ -141,6 +224,9 @@ impl Repl {
     }
 
     pub fn set_executor(&mut self, executor: Executor) -> Result<()> {
+        if self.persistent.is_some() && !matches!(executor, Executor::Sync) {
+            return Err("The persistent mode only supports the sync executor".into());
+        }
         // remove old dependecy if it exists
         if let Some(dependecy) = self.executor.dependecy() {
             // cargo rm needs only the crate name
@@ -204,27 +290,78 @@ impl Repl {
     }
 
     pub fn reset(&mut self) -> Result<()> {
+        let persistent = self.is_persistent();
+        let profile = self.profile.clone();
+        let limits = self.limits.clone();
+        let sandbox = self.sandbox;
         *self = Self::new(
-            self.toolchain,
+            self.toolchain.clone(),
             self.executor,
             self.main_result,
             self.edition,
             self.prelude.clone(),
         )?;
+        self.set_profile(profile)?;
+        self.limits = limits;
+        self.sandbox = sandbox;
+        self.set_persistent(persistent)?;
+        Ok(())
+    }
+
+    /// In persistent mode the values of the `let` bindings are kept alive in a host process between
+    /// evaluations, so previous statements are not re-run on each evaluation
+    pub fn set_persistent(&mut self, persistent: bool) -> Result<()> {
+        if !persistent {
+            self.persistent = None;
+            return Ok(());
+        }
+        if !cfg!(unix) {
+            return Err("The persistent mode is only supported on unix".into());
+        }
+        if !matches!(self.executor, Executor::Sync) {
+            return Err("The persistent mode only supports the sync executor".into());
+        }
+        if self.sandbox {
+            return Err("The persistent mode can't be used in the sandbox".into());
+        }
+        if self.limits != Limits::default() {
+            return Err("The persistent mode can't be used with limits".into());
+        }
+        if self.persistent.is_none() {
+            self.persistent = Some(PersistentState::default());
+        }
         Ok(())
     }
 
+    pub fn is_persistent(&self) -> bool {
+        self.persistent.is_some()
+    }
+
     pub fn show(&self) -> String {
         let mut current_code = self.body.join("\n");
         // If cargo fmt is present format output else ignore
         if let Ok(fmt_code) = self.cargo.cargo_fmt(&current_code) {
             current_code = fmt_code;
         }
+        if !self.tests.is_empty() {
+            let mut tests = self.tests.join("\n");
+            if let Ok(fmt_tests) = self.cargo.cargo_fmt(&tests) {
+                tests = fmt_tests;
+            }
+            current_code = format!("{current_code}\n\nTests:\n{tests}");
+        }
         format!("Current Repl Code:\n{current_code}")
     }
 
     pub fn eval(&mut self, input: impl ToString) -> Result<EvalResult> {
-        self.eval_inner(input, None, false, &*DEFAULT_EVALUATOR, CompileMode::Debug)
+        self.eval_inner(
+            input,
+            None,
+            false,
+            &*DEFAULT_EVALUATOR,
+            CompileMode::Debug,
+            None,
+        )
     }
     //Note: These inputs should become a Config struct
     pub fn eval_with_configuration(
@@ -237,8 +374,16 @@ impl Repl {
             color,
             evaluator,
             compile_mode,
+            terminal,
         } = eval_config;
-        self.eval_inner(input, interactive_function, color, evaluator, compile_mode)
+        self.eval_inner(
+            input,
+            interactive_function,
+            color,
+            evaluator,
+            compile_mode,
+            terminal,
+        )
     }
 
     fn eval_inner(
@@ -248,47 +393,196 @@ impl Repl {
         color: bool,
         evaluator: &[String],
         compile_mode: CompileMode,
+        terminal: Option<&mut dyn Terminal>,
     ) -> Result<EvalResult> {
         let input = input.to_string();
-        // `\n{}\n` to avoid print appearing in error messages
-        let eval_statement = format!(
-            "{}{}{}std::process::exit(0);", // exit(0) allows :hard_load functions to inspect variables that are used after this line
-            evaluator[0], input, evaluator[1]
-        );
-        let toolchain = self.toolchain;
-
-        let cargo = self.cargo.clone();
-        let (status, mut eval_result) = self.eval_in_tmp_repl(eval_statement, |_| {
-            cargo.cargo_run(
+        if self.persistent.is_some() && matches!(compile_mode, CompileMode::Miri) {
+            return Err(
+                "Miri can't be used in persistent mode, the evaluations are loaded as dynamic libraries"
+                    .into(),
+            );
+        }
+        if self.persistent.is_some() && terminal.is_some() {
+            return Err("The program can't run on a terminal in persistent mode".into());
+        }
+        let limits = self.limits.clone();
+        let sandbox = self.sandbox;
+        let (run, source_map) = if let Some(mut persistent) = self.persistent.take() {
+            let result = persistent.eval(
+                self,
+                evaluator,
+                &input,
                 color,
-                compile_mode.is_release(),
-                toolchain,
+                compile_mode,
                 interactive_function,
-            )
-        })?;
+            );
+            self.persistent = Some(persistent);
+            result?
+        } else {
+            let source_map = self.source_map(&evaluator[0], &input);
+            // `\n{}\n` to avoid print appearing in error messages
+            let eval_statement = format!(
+                "{}{}{}std::process::exit(0);", // exit(0) allows :hard_load functions to inspect variables that are used after this line
+                evaluator[0], input, evaluator[1]
+            );
+            let toolchain = self.toolchain.clone();
+
+            let cargo = self.cargo.clone();
+            let mut terminal = terminal;
+            let run = self.eval_in_tmp_repl(eval_statement, |_| {
+                cargo.cargo_run(
+                    color,
+                    compile_mode,
+                    toolchain.clone(),
+                    &limits,
+                    sandbox,
+                    terminal
+                        .as_mut()
+                        .map(|terminal| &mut **terminal as &mut dyn Terminal),
+                    interactive_function,
+                )
+            })?;
+            (run, source_map)
+        };
+        let (status, mut eval_result) = match run {
+            RunOutput::Cargo(status, output) => (status, output),
+            RunOutput::Exe(output, usage, build) if output.status.success() => {
+                let status = output.status;
+                let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+                let mut result: EvalResult = (status, stdout_and_stderr(output)).into();
+                // remove trailing new line
+                result.output.pop();
+                result.diagnostics = diagnostics::parse(&build, &source_map);
+                result.stderr = stderr;
+                result.usage = Some(usage);
+                return Ok(result);
+            }
+            RunOutput::Exe(output, usage, build) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let mut failure =
+                    runtime::report(&stderr, output.status, &source_map, &self.cargo.name);
+                if let Some(exceeded) = limits.exceeded(&stderr, output.status, &usage) {
+                    failure.push('\n');
+                    failure.push_str(&exceeded);
+                }
+                let violation = sandbox
+                    .then(|| {
+                        sandbox::violation(&stderr, output.status, &self.cargo.paths.sandbox_dir)
+                    })
+                    .flatten();
+                if let Some(violation) = violation {
+                    failure.push('\n');
+                    failure.push_str(&violation);
+                }
+                return Ok(EvalResult {
+                    output: String::from_utf8_lossy(&output.stdout)
+                        .trim_end_matches('\n')
+                        .to_string(),
+                    status: output.status,
+                    diagnostics: diagnostics::parse(&build, &source_map),
+                    stderr: stderr.into_owned(),
+                    runtime_failure: Some(failure),
+                    usage: Some(usage),
+                });
+            }
+        };
 
+        if !status.success() {
+            let mut result = EvalResult::build_failure(status, eval_result, &source_map, color);
+            // The build succeeded, this is a Miri report
+            if matches!(compile_mode, CompileMode::Miri)
+                && !result
+                    .diagnostics
+                    .iter()
+                    .any(|diagnostic| diagnostic.level == Level::Error)
+            {
+                result.output = diagnostics::map_rendered(&result.output, &source_map);
+            }
+            return Ok(result);
+        }
         // remove trailing new line
         eval_result.pop();
         Ok((status, eval_result).into())
     }
 
+    /// Evaluate `input` in the background, on a copy of the repl as it is now, see [`jobs`]
+    ///
+    /// The copy is never in persistent mode, the statements of the repl run again in the job
+    pub fn spawn_job(
+        &self,
+        input: impl ToString,
+        color: bool,
+        evaluator: &[String],
+        compile_mode: CompileMode,
+    ) -> Result<Job> {
+        static JOBS: AtomicUsize = AtomicUsize::new(0);
+        let id = JOBS.fetch_add(1, Ordering::Relaxed);
+        let cargo = self
+            .cargo
+            .snapshot(&format!("{}_job{id}", self.cargo.name))?;
+        let mut repl = self.clone();
+        repl.cargo = cargo;
+        repl.persistent = None;
+        Ok(Job::spawn(
+            repl,
+            input.to_string(),
+            color,
+            evaluator.to_vec(),
+            compile_mode,
+        ))
+    }
+
     pub fn eval_build(&mut self, input: impl ToString) -> Result<EvalResult> {
         let input = input.to_string();
-        let toolchain = self.toolchain;
+        let toolchain = self.toolchain.clone();
         let cargo = self.cargo.clone();
         Ok(self
             .eval_in_tmp_repl(input, |_| -> Result<(ExitStatus, String)> {
-                Ok(cargo.cargo_build_output(true, false, toolchain)?)
+                Ok(cargo.cargo_build_output(true, false, toolchain.clone())?)
             })?
             .into())
     }
 
     pub fn eval_check(&mut self, buffer: String) -> Result<EvalResult> {
-        let toolchain = self.toolchain;
+        let toolchain = self.toolchain.clone();
         let cargo = self.cargo.clone();
-        Ok(self
-            .eval_in_tmp_repl(buffer, |_| Ok(cargo.cargo_check_output(toolchain)?))?
-            .into())
+        let source_map = self.source_map("", &buffer);
+        let (status, output) =
+            self.eval_in_tmp_repl(buffer, |_| Ok(cargo.cargo_check_output(toolchain.clone())?))?;
+        if !status.success() {
+            return Ok(EvalResult::build_failure(status, output, &source_map, true));
+        }
+        Ok(EvalResult {
+            diagnostics: diagnostics::parse(&output, &source_map),
+            output: String::new(),
+            stderr: String::new(),
+            status,
+            runtime_failure: None,
+            usage: None,
+        })
+    }
+
+    /// Where the lines of `main.rs` come from, when `input` is inserted at the cursor after `prefix`
+    pub fn source_map(&self, prefix: &str, input: &str) -> SourceMap {
+        let header = Self::generate_body_delimiters(self.executor, self.main_result).0;
+        let prelude = format!("#[allow(unused_imports)]use {PRELUDE_NAME}::*;");
+        let lines: Vec<Option<(Origin, usize)>> = self.body[..self.cursor]
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| {
+                if *line == header || *line == prelude {
+                    None
+                } else {
+                    Some((Origin::Repl, idx))
+                }
+            })
+            .collect();
+        let mut source_map = SourceMap {
+            lines,
+            ..Default::default()
+        };
+        source_map.push_input(prefix, input);
+        source_map
     }
 
     pub fn eval_in_tmp_repl_without_io<T>(
@@ -327,11 +621,103 @@ impl Repl {
     }
 
     pub fn toolchain(&self) -> ToolChain {
-        self.toolchain
+        self.toolchain.clone()
+    }
+
+    pub fn executor(&self) -> Executor {
+        self.executor
+    }
+
+    pub fn main_result(&self) -> MainResult {
+        self.main_result
+    }
+
+    pub fn edition(&self) -> Edition {
+        self.edition
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    /// Fails in persistent mode if the profile changes, see [`persistent`]
+    pub fn set_profile(&mut self, profile: Profile) -> Result<()> {
+        if self.is_persistent() && profile != self.profile {
+            return Err("The profile can't be changed in persistent mode".into());
+        }
+        self.cargo.set_profile(&profile)?;
+        self.profile = profile;
+        Ok(())
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Limits of the evaluated program, see [`Limits`]
+    ///
+    /// Fails in persistent mode, the evaluations run in the long-lived host process
+    pub fn set_limits(&mut self, limits: Limits) -> Result<()> {
+        if self.is_persistent() && limits != Limits::default() {
+            return Err("Limits can't be used in persistent mode".into());
+        }
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Run the evaluated program isolated from the filesystem and the network (Linux only), see [`sandbox`]
+    ///
+    /// Fails if the sandbox can't be created on this system, or in persistent mode
+    pub fn set_sandbox(&mut self, sandbox: bool) -> Result<()> {
+        if sandbox {
+            if self.is_persistent() {
+                return Err("The sandbox can't be used in persistent mode".into());
+            }
+            sandbox::check_available(&self.cargo.paths.sandbox_dir)?;
+        }
+        self.sandbox = sandbox;
+        Ok(())
+    }
+
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandbox
+    }
+
+    /// Switch the repl crate to another edition
+    ///
+    /// Returns the errors of the previously inserted lines that don't compile with the new edition,
+    /// if the crate can't be checked at all (exp: the toolchain doesn't support that edition) the edition is not changed
+    pub fn set_edition(&mut self, edition: Edition) -> Result<Vec<Diagnostic>> {
+        let previous_edition = self.edition;
+        self.cargo.set_edition(edition)?;
+        self.edition = edition;
+
+        let result = self.eval_check(String::new())?;
+        let errors: Vec<Diagnostic> = result
+            .diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.level == Level::Error)
+            .collect();
+        if !result.status.success() && errors.is_empty() {
+            self.cargo.set_edition(previous_edition)?;
+            self.edition = previous_edition;
+            return Err(result.output.into());
+        }
+        Ok(errors)
+    }
+
+    /// Line index where the next input will be inserted
+    pub fn cursor(&self) -> usize {
+        self.cursor
     }
 
-    pub fn set_toolchain(&mut self, toolchain: ToolChain) {
+    /// Fails in persistent mode if the toolchain changes, see [`persistent`]
+    pub fn set_toolchain(&mut self, toolchain: ToolChain) -> Result<()> {
+        if self.is_persistent() && toolchain != self.toolchain {
+            return Err("The toolchain can't be changed in persistent mode".into());
+        }
         self.toolchain = toolchain;
+        Ok(())
     }
 
     pub fn set_main_result(&mut self, main_result: MainResult) {
@@ -348,12 +734,19 @@ impl Repl {
     }
 
     pub fn build(&self) -> std::io::Result<std::process::Child> {
-        self.cargo.cargo_build(self.toolchain)
+        self.cargo.cargo_build(self.toolchain.clone())
     }
 
     pub fn write(&self) -> io::Result<()> {
         let mut main_file = std::fs::File::create(&self.cargo.paths.main_file)?;
-        write!(main_file, "{}", self.body.join("\n"))?;
+        // The hook is on the main function line, so it doesn't appear in `show` or shift the lines
+        write!(
+            main_file,
+            "{}{}\n{}",
+            self.body[0],
+            runtime::BACKTRACE_HOOK,
+            self.body[1..].join("\n")
+        )?;
 
         Ok(())
     }
@@ -420,6 +813,57 @@ impl Repl {
         Err("Incorrect line number".into())
     }
 
+    /// Write the repl as a standalone cargo project in `dir` (it must not exist or be empty)
+    /// The project name is derived from the directory name
+    pub fn export(&self, dir: &Path) -> Result<()> {
+        if dir.exists() && dir.read_dir()?.next().is_some() {
+            return Err(format!("{} already exists and is not empty", dir.display()).into());
+        }
+        let name: String = dir
+            .file_name()
+            .ok_or("Invalid project directory")?
+            .to_string_lossy()
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = if name.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("_{name}")
+        } else {
+            name
+        };
+
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::write(
+            dir.join("Cargo.toml"),
+            self.cargo.exported_cargo_toml(&name)?,
+        )?;
+        std::fs::write(dir.join(".gitignore"), "/target\n")?;
+
+        // The footer is an IRust marker, the main function only needs to return its result
+        let mut body = self.body.clone();
+        let footer_pos = body.len() - 2;
+        match self.main_result {
+            MainResult::Unit => {
+                body.remove(footer_pos);
+            }
+            MainResult::Result => body[footer_pos] = self.main_result.instance().to_string(),
+        }
+        if !self.tests.is_empty() {
+            body.push(self.test_module());
+        }
+        let main_file = dir.join("src").join("main.rs");
+        std::fs::write(&main_file, body.join("\n") + "\n")?;
+        self.cargo.cargo_fmt_file(&main_file);
+
+        Ok(())
+    }
+
     pub fn lines(&self) -> impl Iterator<Item = &String> {
         self.body.iter()
     }
@@ -0,0 +1,243 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+/// Limits of the evaluated program, unset values mean no limit
+///
+/// The memory (address space) and CPU time limits are rlimits, they are only applied on unix (the memory limit is
+/// not enforced on macOS), the timeout is the wall clock time after which the program is killed
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// In milliseconds
+    pub timeout: Option<u64>,
+    /// In MiB
+    pub memory: Option<u64>,
+    /// In seconds
+    pub cpu: Option<u64>,
+}
+
+/// Resources used by a run of the evaluated program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub wall_time: Duration,
+    /// User and system CPU time and peak resident set size (in bytes), only available on unix
+    pub user_time: Option<Duration>,
+    pub system_time: Option<Duration>,
+    pub peak_rss: Option<u64>,
+    /// The program was killed because it exceeded the timeout
+    pub timed_out: bool,
+}
+
+impl Limits {
+    pub const KEYS: [&'static str; 3] = ["timeout", "memory", "cpu"];
+
+    /// Set a limit, `default` removes it
+    ///
+    /// Durations accept `ms`, `s` (the default) and `m` suffixes, memory accepts `K`, `M` (the default) and `G`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let value = (value != "default").then_some(value);
+        match key {
+            "timeout" => self.timeout = value.map(parse_timeout).transpose()?,
+            "memory" => self.memory = value.map(parse_memory).transpose()?,
+            "cpu" => {
+                self.cpu = value
+                    .map(|value| parse_duration(value).map(|duration| duration.as_secs().max(1)))
+                    .transpose()?
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown limit `{key}`, available limits are {}",
+                    Self::KEYS.join(" ")
+                )
+                .into())
+            }
+        }
+        Ok(())
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_millis)
+    }
+
+    /// Apply the rlimits to the process before it starts
+    pub(crate) fn apply(&self, cmd: &mut Command) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            let memory = self.memory.map(|memory| memory.saturating_mul(1024 * 1024));
+            let cpu = self.cpu;
+            if memory.is_none() && cpu.is_none() {
+                return;
+            }
+            let set_limit = |resource, soft: u64, hard: u64| {
+                let limit = libc::rlimit {
+                    rlim_cur: soft as libc::rlim_t,
+                    rlim_max: hard as libc::rlim_t,
+                };
+                // SAFETY: setrlimit only reads the limit
+                if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            };
+            // SAFETY: setrlimit is async-signal-safe
+            unsafe {
+                cmd.pre_exec(move || {
+                    if let Some(memory) = memory {
+                        set_limit(libc::RLIMIT_AS, memory, memory)?;
+                    }
+                    // The process gets SIGXCPU at the soft limit and SIGKILL at the hard limit
+                    if let Some(cpu) = cpu {
+                        set_limit(libc::RLIMIT_CPU, cpu, cpu.saturating_add(1))?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = cmd;
+    }
+
+    /// Which limit the program exceeded, if it did
+    pub(crate) fn exceeded(
+        &self,
+        stderr: &str,
+        status: ExitStatus,
+        usage: &ResourceUsage,
+    ) -> Option<String> {
+        if usage.timed_out {
+            return Some(format!(
+                "timeout exceeded: the program ran for more than {}",
+                format_duration(self.timeout()?)
+            ));
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            // SIGXCPU is only sent at the soft limit, SIGKILL at the hard limit if it's ignored
+            let cpu_exceeded = |cpu| match status.signal() {
+                Some(libc::SIGXCPU) => true,
+                Some(libc::SIGKILL) => usage
+                    .user_time
+                    .zip(usage.system_time)
+                    .is_some_and(|(user, system)| (user + system).as_secs() >= cpu),
+                _ => false,
+            };
+            if let Some(cpu) = self.cpu.filter(|&cpu| cpu_exceeded(cpu)) {
+                return Some(format!("CPU time limit exceeded: {cpu}s"));
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = status;
+        // Rust aborts when an allocation fails
+        let memory = self.memory?;
+        stderr
+            .contains("memory allocation of ")
+            .then(|| format!("memory limit exceeded: {memory} MiB"))
+    }
+}
+
+impl Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = [
+            self.timeout().map(format_duration),
+            self.memory.map(|memory| format!("{memory} MiB")),
+            self.cpu.map(|cpu| format!("{cpu}s")),
+        ];
+        let mut first = true;
+        for (key, value) in Self::KEYS.iter().zip(values) {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{key} = {}", value.as_deref().unwrap_or("none"))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(peak_rss) = self.peak_rss {
+            write!(
+                f,
+                "peak memory: {:.2} MiB, ",
+                peak_rss as f64 / 1024. / 1024.
+            )?;
+        }
+        if let Some((user, system)) = self.user_time.zip(self.system_time) {
+            write!(
+                f,
+                "user: {}, sys: {}, ",
+                format_duration(user),
+                format_duration(system)
+            )?;
+        }
+        write!(f, "wall: {}", format_duration(self.wall_time))
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+/// `500ms`, `10s`, `10` (seconds) or `2m`
+fn parse_duration(value: &str) -> Result<Duration, Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid duration `{value}`, exp: 500ms 10s 2m");
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(value.len()),
+    );
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.,
+        "" | "s" => number,
+        "m" => number * 60.,
+        _ => return Err(invalid().into()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid().into())
+}
+
+/// In milliseconds, a timeout of 0 would kill the program before it starts
+fn parse_timeout(value: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let timeout = parse_duration(value)?.as_millis() as u64;
+    if timeout == 0 {
+        return Err(format!("Invalid timeout `{value}`, it must be at least 1ms").into());
+    }
+    Ok(timeout)
+}
+
+/// In MiB: `512K`, `512` or `512M`, `2G`
+fn parse_memory(value: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let invalid = || format!("Invalid memory size `{value}`, exp: 512M 2G");
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let memory = match unit
+        .to_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "K" => number / 1024,
+        "" | "M" => number,
+        "G" => number.checked_mul(1024).ok_or_else(invalid)?,
+        _ => return Err(invalid().into()),
+    };
+    // The limit is applied in bytes
+    if memory == 0 || memory.checked_mul(1024 * 1024).is_none() {
+        return Err(invalid().into());
+    }
+    Ok(memory)
+}
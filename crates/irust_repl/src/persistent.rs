@@ -0,0 +1,1017 @@
+//! Persistent evaluation
+//!
+//! Instead of recompiling and re-running the whole repl body on each evaluation, the body is
+//! compiled as a `cdylib` that is loaded by a long-lived host process.
+//! The host keeps the values of the `let` bindings alive between evaluations, so each new input
+//! only runs the new code.
+//!
+//! Each generated library:
+//! - restores the previously saved bindings from the host store
+//! - re-runs the statements that can't be persisted (references, closures, ..)
+//! - runs the statements that were inserted since the last evaluation
+//! - forks, the child evaluates the expression (so it can consume the bindings freely)
+//! - saves the bindings back to the host store
+//!
+//! The bindings are shared between the host and the libraries as `Box<dyn Any>`, so every library
+//! must be built with the same toolchain, profile and compile mode: the `TypeId`s and layouts of
+//! the values are only stable for a given compiler and set of codegen options.
+//! That's why [`Repl::set_toolchain`] and [`Repl::set_profile`] refuse changes while the
+//! persistent mode is on, and a compile mode change restarts the host.
+use crate::{
+    cargo_cmds::{Cargo, RunOutput},
+    diagnostics::{Origin, SourceMap},
+    runtime,
+    utils::{is_allowed_in_lib, stdout_and_stderr},
+    CompileMode, Repl, ResourceUsage, Result, ToolChain,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+const EVAL_CMD: &str = "\u{1}irust_eval";
+const DONE_MARKER: &str = "\u{1}irust_done";
+const RESTORE_FN: &str = "__irust_restore";
+const SAVE_FN: &str = "__irust_save";
+
+const HOST_SRC: &str = r#"
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::os::raw::{c_char, c_int, c_void};
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+}
+const RTLD_NOW: c_int = 2;
+type Eval = extern "C" fn(*mut c_void, *mut c_int) -> c_int;
+
+fn dl_error() -> String {
+    unsafe { std::ffi::CStr::from_ptr(dlerror()) }.to_string_lossy().into_owned()
+}
+
+// Returns (state_lost, raw wait status)
+fn eval(lib: &str, store: &mut HashMap<String, Box<dyn Any>>) -> (c_int, c_int) {
+    let lib = std::ffi::CString::new(lib).expect("path has no nul bytes");
+    // Libraries are never unloaded, the stored values might point to their code (vtables, drop glue)
+    let handle = unsafe { dlopen(lib.as_ptr(), RTLD_NOW) };
+    if handle.is_null() {
+        eprintln!("{}", dl_error());
+        return (0, 1 << 8);
+    }
+    let eval = unsafe { dlsym(handle, b"irust_eval\0".as_ptr() as *const c_char) };
+    if eval.is_null() {
+        eprintln!("{}", dl_error());
+        return (0, 1 << 8);
+    }
+    let eval: Eval = unsafe { std::mem::transmute(eval) };
+    let mut status = 0;
+    let state_lost = eval(store as *mut _ as *mut c_void, &mut status);
+    (state_lost, status)
+}
+
+fn main() {
+    let mut store: HashMap<String, Box<dyn Any>> = HashMap::new();
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        // Ignore anything that is not a command (for example input that was meant for an evaluated program)
+        let Some((cwd, lib)) = line
+            .strip_prefix("EVAL_CMD ")
+            .and_then(|cmd| cmd.split_once('\t'))
+        else {
+            continue;
+        };
+        let _ = std::env::set_current_dir(cwd);
+        let (state_lost, status) = eval(lib, &mut store);
+        let _ = std::io::stdout().flush();
+        println!("DONE_MARKER {state_lost} {status}");
+        eprintln!("DONE_MARKER");
+    }
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChunkKind {
+    /// fn, struct, use, .. placed at the top level of the library
+    Item,
+    /// let statement that was run once, its bindings are saved in the host
+    Persisted,
+    /// statement without bindings that was run once
+    Executed,
+    /// statement that can't be persisted, it is re-run on each evaluation
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    code: String,
+    kind: ChunkKind,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    ty: String,
+    // index of the chunk that declared this binding
+    origin: usize,
+}
+
+/// The generated library, with the origin of each line
+struct Lines {
+    lines: Vec<String>,
+    source_map: SourceMap,
+}
+
+impl Lines {
+    /// Push `code`, its first line comes from the repl line `line`
+    fn push(&mut self, code: String, line: Option<usize>) {
+        let count = code.split('\n').count();
+        self.source_map
+            .lines
+            .extend((0..count).map(|idx| line.map(|line| (Origin::Repl, line + idx))));
+        self.lines.push(code);
+    }
+
+    /// Line number (1 based) of the next pushed line
+    fn next_line(&self) -> usize {
+        self.source_map.lines.len() + 1
+    }
+}
+
+enum Role {
+    Restore(String),
+    Save(String),
+}
+
+#[derive(Debug)]
+struct Host {
+    // stdin is kept in the process, so the interactive function can forward input to the evaluated code
+    process: Child,
+    stdout: mpsc::Receiver<Vec<u8>>,
+    stderr: mpsc::Receiver<Vec<u8>>,
+    toolchain: ToolChain,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PersistentState {
+    host: Option<Host>,
+    executed: Vec<Chunk>,
+    bindings: Vec<Binding>,
+    evals: usize,
+    // the libraries built with another compile mode can't share the saved bindings
+    compile_mode: Option<CompileMode>,
+}
+
+// A cloned repl gets its own host, its state will be recreated on the next evaluation
+impl Clone for PersistentState {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl Drop for PersistentState {
+    fn drop(&mut self) {
+        self.restart();
+    }
+}
+
+impl PersistentState {
+    /// Forget all persisted values, the next evaluation will run the whole body again
+    pub(crate) fn restart(&mut self) {
+        if let Some(mut host) = self.host.take() {
+            let _ = host.process.kill();
+            let _ = host.process.wait();
+        }
+        self.executed.clear();
+        self.bindings.clear();
+        self.compile_mode = None;
+    }
+
+    /// Evaluate `input` wrapped in the evaluator, the output is the same as a normal evaluation one, with the
+    /// source map of the generated library
+    pub(crate) fn eval(
+        &mut self,
+        repl: &Repl,
+        evaluator: &[String],
+        input: &str,
+        color: bool,
+        compile_mode: CompileMode,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<(RunOutput, SourceMap)> {
+        let cargo = &repl.cargo;
+        std::fs::create_dir_all(&cargo.paths.persistent_dir)?;
+        let (crate_attributes, chunks) = repl_chunks(repl);
+        let chunk_lines = repl_chunk_lines(repl);
+
+        let host_outdated = self
+            .host
+            .as_ref()
+            .is_some_and(|host| host.toolchain != repl.toolchain)
+            || self.compile_mode.is_some_and(|mode| mode != compile_mode);
+        let body_changed = self.executed.len() > chunks.len()
+            || self
+                .executed
+                .iter()
+                .zip(chunks.iter())
+                .any(|(executed, chunk)| &executed.code != chunk);
+        if host_outdated || body_changed {
+            self.restart();
+        }
+
+        let mut pending: Vec<(Chunk, Vec<String>)> = chunks[self.executed.len()..]
+            .iter()
+            .map(|code| classify(code))
+            .collect();
+
+        // Build the library, if it fails because of the persistence glue, adjust and retry
+        let mut moved: Vec<String> = vec![];
+        let mut pending_demoted = false;
+        let (build, source_map) = loop {
+            let (source, roles, source_map) = self.generate(
+                repl,
+                &crate_attributes,
+                &chunk_lines,
+                &pending,
+                &moved,
+                (evaluator, input),
+            );
+            std::fs::write(&cargo.paths.lib_file, source)?;
+            let build = cargo.cargo_build_cdylib(color, compile_mode, repl.toolchain.clone());
+            let _ = std::fs::remove_file(&cargo.paths.lib_file);
+            let (status, output) = build?;
+            if status.success() {
+                break (output, source_map);
+            }
+
+            let mut progressed = false;
+            for line in error_lines(&output) {
+                match roles.get(&line) {
+                    // The new code moved this value, it can't be saved anymore
+                    Some(Role::Save(name)) => {
+                        moved.push(name.clone());
+                        progressed = true;
+                    }
+                    // The recorded type can't be named, fallback to re-running its statement
+                    Some(Role::Restore(name)) => {
+                        if let Some(binding) = self.bindings.iter().find(|b| &b.name == name) {
+                            self.demote(binding.origin);
+                            progressed = true;
+                        }
+                    }
+                    None => (),
+                }
+            }
+            if progressed {
+                continue;
+            }
+            // Maybe a binding that borrows (not 'static), treat the new bindings as not persistable
+            if !pending_demoted
+                && pending
+                    .iter()
+                    .any(|(chunk, _)| chunk.kind == ChunkKind::Persisted)
+            {
+                for (chunk, names) in pending.iter_mut() {
+                    if chunk.kind == ChunkKind::Persisted {
+                        chunk.kind = ChunkKind::Replay;
+                        names.clear();
+                    }
+                }
+                pending_demoted = true;
+                continue;
+            }
+            // The error is in the user code
+            return Ok((RunOutput::Cargo(status, output), source_map));
+        };
+
+        self.evals += 1;
+        self.compile_mode = Some(compile_mode);
+        let lib = cargo.paths.persistent_dir.join(format!(
+            "eval_{}{}",
+            self.evals,
+            std::env::consts::DLL_SUFFIX
+        ));
+        std::fs::copy(
+            if compile_mode.is_release() {
+                &cargo.paths.release_cdylib_path
+            } else {
+                &cargo.paths.cdylib_path
+            },
+            &lib,
+        )?;
+
+        let start = Instant::now();
+        let result = self.run(cargo, repl.toolchain.clone(), &lib, interactive_function);
+        let usage = ResourceUsage {
+            wall_time: start.elapsed(),
+            ..Default::default()
+        };
+        let _ = std::fs::remove_file(&lib);
+        let (state_lost, output) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.restart();
+                return Err(e);
+            }
+        };
+        let output = (RunOutput::Exe(output, usage, build), source_map);
+        if state_lost {
+            self.restart();
+            return Ok(output);
+        }
+
+        // Record what was run
+        let first_pending = self.executed.len();
+        let mut origins: HashMap<String, usize> = self
+            .bindings
+            .iter()
+            .map(|binding| (binding.name.clone(), binding.origin))
+            .collect();
+        for (idx, (chunk, names)) in pending.into_iter().enumerate() {
+            for name in names {
+                origins.insert(name, first_pending + idx);
+            }
+            self.executed.push(chunk);
+        }
+        let types = std::fs::read_to_string(cargo.paths.persistent_dir.join("types"))?;
+        self.bindings.clear();
+        for (name, ty) in types.lines().filter_map(|line| line.split_once('\t')) {
+            let Some(&origin) = origins.get(name) else {
+                continue;
+            };
+            match nameable_type(ty, &cargo.name) {
+                Some(ty) => self.bindings.push(Binding {
+                    name: name.to_string(),
+                    ty,
+                    origin,
+                }),
+                None => self.demote(origin),
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Re-run the statement on each evaluation instead of restoring its bindings
+    fn demote(&mut self, origin: usize) {
+        if let Some(chunk) = self.executed.get_mut(origin) {
+            chunk.kind = ChunkKind::Replay;
+        }
+        self.bindings.retain(|binding| binding.origin != origin);
+    }
+
+    /// Returns the library source, the roles of the glue lines and where the lines come from
+    fn generate(
+        &self,
+        repl: &Repl,
+        crate_attributes: &[String],
+        chunk_lines: &[usize],
+        pending: &[(Chunk, Vec<String>)],
+        moved: &[String],
+        (evaluator, input): (&[String], &str),
+    ) -> (String, HashMap<usize, Role>, SourceMap) {
+        let mut lines = Lines {
+            lines: crate_attributes.to_vec(),
+            source_map: SourceMap {
+                lines: repl.source_map("", "").lines[..crate_attributes.len()].to_vec(),
+                lib: true,
+                ..Default::default()
+            },
+        };
+        let mut roles = HashMap::new();
+        // (chunk, is pending, repl line of the chunk start)
+        let all_chunks = || {
+            self.executed
+                .iter()
+                .map(|chunk| (chunk, false))
+                .chain(pending.iter().map(|(chunk, _)| (chunk, true)))
+                .zip(chunk_lines)
+                .map(|((chunk, is_pending), line)| (chunk, is_pending, *line))
+        };
+
+        lines.push("#![allow(unused)]".into(), None);
+        for (chunk, _, line) in all_chunks().filter(|(chunk, ..)| chunk.kind == ChunkKind::Item) {
+            lines.push(chunk.code.clone(), Some(line));
+        }
+        for glue in GLUE
+            .replace(
+                "TYPES_PATH",
+                &format!(
+                    "{:?}",
+                    repl.cargo.paths.persistent_dir.join("types").display()
+                ),
+            )
+            .replace("RESULT_TYPE", repl.main_result.ttype())
+            .replace("BACKTRACE_HOOK", runtime::BACKTRACE_HOOK)
+            .lines()
+        {
+            lines.push(glue.to_string(), None);
+        }
+
+        for binding in &self.bindings {
+            roles.insert(lines.next_line(), Role::Restore(binding.name.clone()));
+            lines.push(
+                format!(
+                    "    let mut {}: {} = {RESTORE_FN}(__irust_store, {:?});",
+                    binding.name, binding.ty, binding.name
+                ),
+                None,
+            );
+        }
+        for (chunk, is_pending, line) in all_chunks() {
+            match chunk.kind {
+                ChunkKind::Replay => lines.push(chunk.code.clone(), Some(line)),
+                ChunkKind::Persisted | ChunkKind::Executed if is_pending => {
+                    lines.push(chunk.code.clone(), Some(line))
+                }
+                _ => (),
+            }
+        }
+
+        lines.push("    __irust_flush();".into(), None);
+        lines.push("    if __irust_fork() {".into(), None);
+        let eval_statement = format!("{}{input}{}", evaluator[0], evaluator[1]);
+        let start = lines.next_line();
+        lines.push(eval_statement, None);
+        let end = lines.next_line();
+        // Point the statement lines to the input
+        lines.source_map.lines.truncate(start - 1);
+        lines.source_map.push_input(&evaluator[0], input);
+        lines.source_map.lines.resize(end - 1, None);
+        lines.push(
+            format!("        return {};", repl.main_result.instance()),
+            None,
+        );
+        lines.push("    }".into(), None);
+
+        let mut saved: Vec<&String> = self.bindings.iter().map(|b| &b.name).collect();
+        for name in pending.iter().flat_map(|(_, names)| names) {
+            if !saved.contains(&name) {
+                saved.push(name);
+            }
+        }
+        for name in saved.into_iter().filter(|name| !moved.contains(name)) {
+            roles.insert(lines.next_line(), Role::Save(name.clone()));
+            lines.push(
+                format!("    {SAVE_FN}(__irust_store, &mut __irust_types, {name:?}, {name});"),
+                None,
+            );
+        }
+        lines.push("    __irust_write_types(&__irust_types);".into(), None);
+        lines.push(format!("    {}", repl.main_result.instance()), None);
+        lines.push("}".into(), None);
+
+        (lines.lines.join("\n"), roles, lines.source_map)
+    }
+
+    fn run(
+        &mut self,
+        cargo: &Cargo,
+        toolchain: ToolChain,
+        lib: &Path,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<(bool, Output)> {
+        if self.host.is_none() {
+            self.host = Some(Host::start(cargo, toolchain)?);
+        }
+        let host = self.host.as_mut().expect("host is started");
+
+        let stdin = host.process.stdin.as_mut().expect("stdin is piped");
+        writeln!(
+            stdin,
+            "{EVAL_CMD} {}\t{}",
+            std::env::current_dir()?.display(),
+            lib.display()
+        )?;
+        stdin.flush()?;
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let done = |out: &[u8]| String::from_utf8_lossy(out).find(DONE_MARKER);
+        loop {
+            if let Some(ref function) = interactive_function {
+                function(&mut host.process)?;
+            }
+            let mut disconnected = false;
+            for (channel, out) in [(&host.stdout, &mut stdout), (&host.stderr, &mut stderr)] {
+                match channel.recv_timeout(Duration::from_millis(1)) {
+                    Ok(bytes) => out.extend(bytes),
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => disconnected = true,
+                }
+            }
+            if done(&stdout).is_some() && done(&stderr).is_some() {
+                break;
+            }
+            // The host died, (the evaluated code called `exit`, aborted or crashed)
+            if disconnected {
+                let status = host.process.wait()?;
+                return Ok((
+                    true,
+                    Output {
+                        status,
+                        stdout,
+                        stderr,
+                    },
+                ));
+            }
+        }
+
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+        let (stdout, report) = stdout.split_at(stdout.find(DONE_MARKER).expect("checked"));
+        let stderr = &stderr[..stderr.find(DONE_MARKER).expect("checked")];
+
+        let mut report = report[DONE_MARKER.len()..].split_whitespace();
+        let state_lost = report.next() != Some("0");
+        let status: i32 = report.next().ok_or("malformed host report")?.parse()?;
+
+        Ok((
+            state_lost,
+            Output {
+                status: exit_status(status),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: stderr.as_bytes().to_vec(),
+            },
+        ))
+    }
+}
+
+const GLUE: &str = r#"
+type __IrustStore = ::std::collections::HashMap<String, Box<dyn ::std::any::Any>>;
+static __IRUST_IN_CHILD: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(false);
+static __IRUST_STATUS: ::std::sync::atomic::AtomicI32 = ::std::sync::atomic::AtomicI32::new(0);
+
+extern "C" {
+    fn fork() -> i32;
+    fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
+    fn _exit(status: i32) -> !;
+}
+
+trait __IrustReport {
+    fn __irust_code(self) -> i32;
+}
+impl __IrustReport for () {
+    fn __irust_code(self) -> i32 {
+        0
+    }
+}
+impl<E: ::std::fmt::Debug> __IrustReport for Result<(), E> {
+    fn __irust_code(self) -> i32 {
+        match self {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                1
+            }
+        }
+    }
+}
+
+fn __irust_flush() {
+    let _ = ::std::io::Write::flush(&mut ::std::io::stdout());
+}
+
+// Returns true in the child process
+fn __irust_fork() -> bool {
+    let pid = unsafe { fork() };
+    if pid < 0 {
+        panic!("IRust: failed to fork");
+    }
+    if pid == 0 {
+        __IRUST_IN_CHILD.store(true, ::std::sync::atomic::Ordering::SeqCst);
+        return true;
+    }
+    let mut status = 0;
+    unsafe { waitpid(pid, &mut status, 0) };
+    __IRUST_STATUS.store(status, ::std::sync::atomic::Ordering::SeqCst);
+    false
+}
+
+fn __irust_restore<T: 'static>(store: &mut __IrustStore, name: &str) -> T {
+    *store
+        .remove(name)
+        .expect("IRust: missing binding")
+        .downcast()
+        .expect("IRust: binding changed type")
+}
+
+fn __irust_save<T: 'static>(store: &mut __IrustStore, types: &mut String, name: &str, value: T) {
+    types.push_str(&format!("{name}\t{}\n", ::std::any::type_name::<T>()));
+    store.insert(name.to_string(), Box::new(value));
+}
+
+fn __irust_write_types(types: &str) {
+    ::std::fs::write(TYPES_PATH, types).expect("IRust: failed to write bindings types");
+}
+
+#[no_mangle]
+pub extern "C" fn irust_eval(store: *mut ::std::ffi::c_void, status: *mut i32) -> i32 {
+    let store = unsafe { &mut *(store as *mut __IrustStore) };
+    // Each library has its own std, so its own panic hook
+    BACKTRACE_HOOK
+    let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+        __IrustReport::__irust_code(__irust_eval(store))
+    }));
+    __irust_flush();
+    let code = result.unwrap_or(101);
+    if __IRUST_IN_CHILD.load(::std::sync::atomic::Ordering::SeqCst) {
+        unsafe { _exit(code) }
+    }
+    if code != 0 {
+        // The bindings were consumed by the failing statements
+        unsafe { *status = code << 8 };
+        return 1;
+    }
+    unsafe { *status = __IRUST_STATUS.load(::std::sync::atomic::Ordering::SeqCst) };
+    0
+}
+
+fn __irust_eval(__irust_store: &mut __IrustStore) -> RESULT_TYPE {
+    let mut __irust_types = String::new();"#;
+
+impl Host {
+    fn start(cargo: &Cargo, toolchain: ToolChain) -> Result<Self> {
+        let dir = &cargo.paths.persistent_dir;
+        let src = dir.join("host.rs");
+        let bin = dir.join(format!("host_{toolchain}{}", std::env::consts::EXE_SUFFIX));
+        if !bin.exists() {
+            std::fs::write(
+                &src,
+                HOST_SRC
+                    .replace("EVAL_CMD", EVAL_CMD)
+                    .replace("DONE_MARKER", DONE_MARKER),
+            )?;
+            let mut rustc = Command::new("rustc");
+            if !matches!(toolchain, ToolChain::Default) {
+                rustc.arg(toolchain.as_arg());
+            }
+            let output = rustc
+                .args(["--edition", "2021", "-O", "-o"])
+                .arg(&bin)
+                .arg(&src)
+                .output()?;
+            if !output.status.success() {
+                return Err(format!(
+                    "Failed to build the persistent evaluation host:\n{}",
+                    stdout_and_stderr(output)
+                )
+                .into());
+            }
+        }
+
+        let mut process = Command::new(&bin)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = forward(process.stdout.take().expect("stdout is piped"));
+        let stderr = forward(process.stderr.take().expect("stderr is piped"));
+
+        Ok(Self {
+            process,
+            stdout,
+            stderr,
+            toolchain,
+        })
+    }
+}
+
+fn forward(mut reader: impl Read + Send + 'static) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(buffer[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(unix)]
+fn exit_status(raw: i32) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(raw)
+}
+#[cfg(not(unix))]
+fn exit_status(raw: i32) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(raw as u32)
+}
+
+/// Returns the crate attributes and the top level chunks of the user code
+pub(crate) fn repl_chunks(repl: &Repl) -> (Vec<String>, Vec<String>) {
+    let (main_idx, footer_idx) = main_bounds(repl);
+    (
+        repl.body[..main_idx].to_vec(),
+        split_chunks(&repl.body[main_idx + 1..footer_idx])
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect(),
+    )
+}
+
+/// The repl line where each chunk of [`repl_chunks`] starts
+fn repl_chunk_lines(repl: &Repl) -> Vec<usize> {
+    let (main_idx, footer_idx) = main_bounds(repl);
+    split_chunks(&repl.body[main_idx + 1..footer_idx])
+        .into_iter()
+        .map(|(line, _)| main_idx + 1 + line)
+        .collect()
+}
+
+/// The repl lines of the main function header and footer
+fn main_bounds(repl: &Repl) -> (usize, usize) {
+    let header = Repl::generate_body_delimiters(repl.executor, repl.main_result).0;
+    let main_idx = repl
+        .body
+        .iter()
+        .position(|line| line == &header)
+        .unwrap_or(0);
+    (main_idx, repl.body.len() - 2)
+}
+
+fn classify(code: &str) -> (Chunk, Vec<String>) {
+    let first_line = code
+        .lines()
+        .find(|line| !line.trim_start().starts_with("//"))
+        .unwrap_or_default();
+    let (kind, names) = if is_allowed_in_lib(first_line) {
+        (ChunkKind::Item, vec![])
+    } else if code.trim_start().starts_with("let") {
+        match let_bindings(code) {
+            Some(names) => (ChunkKind::Persisted, names),
+            None => (ChunkKind::Replay, vec![]),
+        }
+    } else {
+        (ChunkKind::Executed, vec![])
+    };
+    (
+        Chunk {
+            code: code.to_string(),
+            kind,
+        },
+        names,
+    )
+}
+
+/// Split code into top level statements and items, with the index of the line where they start
+/// A chunk ends after a top level `;` or a line that ends with a top level `}`
+fn split_chunks(lines: &[String]) -> Vec<(usize, String)> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut start = None;
+    let mut depth = 0_isize;
+    let mut block_comment = false;
+    let mut string: Option<usize> = None; // raw string hashes count (0 for normal strings)
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut idx = 0;
+        while idx < chars.len() {
+            let c = chars[idx];
+            let next = chars.get(idx + 1).copied();
+            current.push(c);
+            idx += 1;
+            if !c.is_whitespace() {
+                start.get_or_insert(line_idx);
+            }
+
+            if block_comment {
+                if c == '*' && next == Some('/') {
+                    current.push('/');
+                    idx += 1;
+                    block_comment = false;
+                }
+                continue;
+            }
+            if let Some(hashes) = string {
+                if c == '\\' && hashes == 0 {
+                    if let Some(next) = next {
+                        current.push(next);
+                        idx += 1;
+                    }
+                } else if c == '"'
+                    && chars[idx..].iter().take_while(|c| **c == '#').count() >= hashes
+                {
+                    current.extend(std::iter::repeat_n('#', hashes));
+                    idx += hashes;
+                    string = None;
+                }
+                continue;
+            }
+
+            match c {
+                '/' if next == Some('/') => {
+                    current.extend(&chars[idx..]);
+                    break;
+                }
+                '/' if next == Some('*') => block_comment = true,
+                '"' => string = Some(0),
+                'r' if matches!(next, Some('"') | Some('#'))
+                    && !current[..current.len() - 1]
+                        .ends_with(|c: char| c.is_alphanumeric() || c == '_') =>
+                {
+                    let hashes = chars[idx..].iter().take_while(|c| **c == '#').count();
+                    if chars.get(idx + hashes) == Some(&'"') {
+                        current.extend(&chars[idx..=idx + hashes]);
+                        idx += hashes + 1;
+                        string = Some(hashes);
+                    }
+                }
+                // char literal (as opposed to a lifetime)
+                '\'' if next == Some('\\') || chars.get(idx + 1) == Some(&'\'') => {
+                    let end = chars[idx + 1..]
+                        .iter()
+                        .position(|c| *c == '\'')
+                        .map(|pos| idx + pos + 2)
+                        .unwrap_or(chars.len());
+                    current.extend(&chars[idx..end]);
+                    idx = end;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ';' if depth == 0 => {
+                    chunks.push((
+                        start.take().unwrap_or(line_idx),
+                        std::mem::take(&mut current).trim().to_string(),
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        if depth == 0 && string.is_none() && !block_comment && line.trim_end().ends_with('}') {
+            chunks.push((
+                start.take().unwrap_or(line_idx),
+                std::mem::take(&mut current).trim().to_string(),
+            ));
+        } else {
+            current.push('\n');
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push((start.unwrap_or_default(), current.trim().to_string()));
+    }
+    chunks.retain(|(_, chunk)| !chunk.is_empty());
+    chunks
+}
+
+/// Names bound by a `let` statement, only identifiers and tuples of identifiers are supported
+fn let_bindings(code: &str) -> Option<Vec<String>> {
+    let rest = code.trim_start().strip_prefix("let")?;
+    if !rest.starts_with(|c: char| c.is_whitespace() || c == '(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut end = None;
+    let chars: Vec<char> = rest.chars().collect();
+    for (idx, c) in chars.iter().enumerate() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ':' if depth == 0 && chars.get(idx + 1) != Some(&':') => {
+                end = Some(idx);
+                break;
+            }
+            '=' if depth == 0 => {
+                end = Some(idx);
+                break;
+            }
+            // `let a;` and other patterns
+            ';' | '[' | '{' | '&' | '|' | '@' => return None,
+            _ => (),
+        }
+    }
+    let pattern: String = chars[..end?].iter().collect();
+
+    let mut names = vec![];
+    let mut tokens = pattern
+        .split(|c: char| c == '(' || c == ')' || c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .peekable();
+    // Only tuples are allowed to use parenthesis (no tuple structs)
+    if pattern.contains('(') && !pattern.trim_start().starts_with('(') {
+        return None;
+    }
+    while let Some(token) = tokens.next() {
+        match token {
+            "mut" => {
+                tokens.peek()?;
+            }
+            "_" => (),
+            "ref" => return None,
+            ident
+                if ident.starts_with(|c: char| c.is_lowercase() || c == '_')
+                    && ident.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                names.push(ident.to_string())
+            }
+            _ => return None,
+        }
+    }
+    Some(names)
+}
+
+/// Turn the result of `std::any::type_name` into a type that can be written in the generated code
+fn nameable_type(ty: &str, crate_name: &str) -> Option<String> {
+    // Closures, async blocks, items declared inside functions, ..
+    if ty.contains('{') || ty.contains("impl ") {
+        return None;
+    }
+    // Private modules that are re-exported
+    const PUBLIC_PATHS: &[(&str, &str)] = &[
+        (
+            "std::collections::hash::map::",
+            "std::collections::hash_map::",
+        ),
+        (
+            "std::collections::hash::set::",
+            "std::collections::hash_set::",
+        ),
+        (
+            "std::collections::btree::map::",
+            "std::collections::btree_map::",
+        ),
+        (
+            "std::collections::btree::set::",
+            "std::collections::btree_set::",
+        ),
+        ("std::sync::poison::mutex::", "std::sync::"),
+        ("std::sync::poison::rwlock::", "std::sync::"),
+        ("std::sync::poison::condvar::", "std::sync::"),
+        ("std::sync::poison::", "std::sync::"),
+        ("std::sync::mutex::", "std::sync::"),
+        ("std::sync::rwlock::", "std::sync::"),
+        ("std::sync::once_lock::", "std::sync::"),
+        ("std::sync::lazy_lock::", "std::sync::"),
+        ("std::sync::barrier::", "std::sync::"),
+        ("std::sync::condvar::", "std::sync::"),
+        ("std::ffi::os_str::", "std::ffi::"),
+        ("std::ffi::c_str::", "std::ffi::"),
+        ("std::ops::range::", "std::ops::"),
+        ("std::ops::function::", "std::ops::"),
+        ("std::num::nonzero::", "std::num::"),
+        ("std::net::ip_addr::", "std::net::"),
+        ("std::net::socket_addr::", "std::net::"),
+    ];
+
+    let mut ty = replace_path_root(ty, crate_name, "crate");
+    for root in ["core", "alloc"] {
+        ty = replace_path_root(&ty, root, "std");
+    }
+    for (private, public) in PUBLIC_PATHS {
+        ty = ty.replace(private, public);
+    }
+    Some(ty)
+}
+
+fn replace_path_root(ty: &str, root: &str, replacement: &str) -> String {
+    let pattern = format!("{root}::");
+    let mut result = String::new();
+    let mut rest = ty;
+    while let Some(idx) = rest.find(&pattern) {
+        let is_root = !rest[..idx].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':');
+        result.push_str(&rest[..idx]);
+        result.push_str(if is_root { replacement } else { root });
+        result.push_str("::");
+        rest = &rest[idx + pattern.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Lines of the generated library that have errors, from cargo json output
+fn error_lines(output: &str) -> Vec<usize> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-message")
+        .filter(|message| {
+            message["message"]["level"]
+                .as_str()
+                .is_some_and(|level| level.starts_with("error"))
+        })
+        .flat_map(|message| {
+            message["message"]["spans"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|span| span["is_primary"] == true && span["file_name"] == "src/lib.rs")
+                .filter_map(|span| span["line_start"].as_u64())
+                .map(|line| line as usize)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
@@ -0,0 +1,149 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Build settings of the repl crate, unset values use cargo defaults
+///
+/// The profile settings are applied to both the debug and the release profiles of the repl `Cargo.toml`,
+/// `target_cpu` and `rustflags` are passed to rustc via the repl cargo config
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub opt_level: Option<String>,
+    pub debug_assertions: Option<bool>,
+    pub overflow_checks: Option<bool>,
+    pub lto: Option<String>,
+    pub codegen_units: Option<u32>,
+    pub panic: Option<String>,
+    pub target_cpu: Option<String>,
+    pub rustflags: Option<String>,
+}
+
+impl Profile {
+    pub const KEYS: [&'static str; 8] = [
+        "opt-level",
+        "debug-assertions",
+        "overflow-checks",
+        "lto",
+        "codegen-units",
+        "panic",
+        "target-cpu",
+        "rustflags",
+    ];
+
+    /// Set a setting with its cargo name, `default` unsets it
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let value = (value != "default").then_some(value);
+        let one_of = |accepted: &[&str]| -> Result<Option<String>, Box<dyn std::error::Error>> {
+            match value {
+                Some(value) if !accepted.contains(&value) => Err(format!(
+                    "Invalid `{key}` value, accepted values are {}",
+                    accepted.join(" ")
+                )
+                .into()),
+                value => Ok(value.map(ToOwned::to_owned)),
+            }
+        };
+        let bool = || -> Result<Option<bool>, Box<dyn std::error::Error>> {
+            value
+                .map(|value| {
+                    value.parse().map_err(|_| {
+                        format!("Invalid `{key}` value, expected true or false").into()
+                    })
+                })
+                .transpose()
+        };
+
+        match key {
+            "opt-level" => self.opt_level = one_of(&["0", "1", "2", "3", "s", "z"])?,
+            "debug-assertions" => self.debug_assertions = bool()?,
+            "overflow-checks" => self.overflow_checks = bool()?,
+            "lto" => self.lto = one_of(&["false", "true", "thin", "fat", "off"])?,
+            "codegen-units" => {
+                self.codegen_units = value
+                    .map(|value| value.parse())
+                    .transpose()
+                    .map_err(|_| "Invalid `codegen-units` value, expected a number")?
+            }
+            "panic" => self.panic = one_of(&["unwind", "abort"])?,
+            "target-cpu" => self.target_cpu = value.map(ToOwned::to_owned),
+            "rustflags" => self.rustflags = value.map(ToOwned::to_owned),
+            _ => {
+                return Err(format!(
+                    "Unknown profile setting `{key}`, available settings are {}",
+                    Self::KEYS.join(" ")
+                )
+                .into())
+            }
+        }
+        Ok(())
+    }
+
+    /// The `[profile.*]` settings, as written in `Cargo.toml`
+    pub(crate) fn cargo_settings(&self) -> Vec<(&'static str, toml_edit::Value)> {
+        let mut settings = vec![];
+        if let Some(opt_level) = &self.opt_level {
+            // Numeric levels are integers in Cargo.toml
+            match opt_level.parse::<i64>() {
+                Ok(level) => settings.push(("opt-level", level.into())),
+                Err(_) => settings.push(("opt-level", opt_level.into())),
+            }
+        }
+        if let Some(debug_assertions) = self.debug_assertions {
+            settings.push(("debug-assertions", debug_assertions.into()));
+        }
+        if let Some(overflow_checks) = self.overflow_checks {
+            settings.push(("overflow-checks", overflow_checks.into()));
+        }
+        if let Some(lto) = &self.lto {
+            match lto.parse::<bool>() {
+                Ok(lto) => settings.push(("lto", lto.into())),
+                Err(_) => settings.push(("lto", lto.into())),
+            }
+        }
+        if let Some(codegen_units) = self.codegen_units {
+            settings.push(("codegen-units", i64::from(codegen_units).into()));
+        }
+        if let Some(panic) = &self.panic {
+            settings.push(("panic", panic.into()));
+        }
+        settings
+    }
+
+    /// Flags passed to rustc
+    pub(crate) fn rustc_flags(&self) -> Vec<String> {
+        let mut flags = vec![];
+        if let Some(target_cpu) = &self.target_cpu {
+            flags.push(format!("-Ctarget-cpu={target_cpu}"));
+        }
+        if let Some(rustflags) = &self.rustflags {
+            flags.extend(rustflags.split_whitespace().map(ToOwned::to_owned));
+        }
+        flags
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = [
+            self.opt_level.clone(),
+            self.debug_assertions.map(|value| value.to_string()),
+            self.overflow_checks.map(|value| value.to_string()),
+            self.lto.clone(),
+            self.codegen_units.map(|value| value.to_string()),
+            self.panic.clone(),
+            self.target_cpu.clone(),
+            self.rustflags.clone(),
+        ];
+        let mut first = true;
+        for (key, value) in Self::KEYS.iter().zip(values) {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            write!(f, "{key} = {}", value.as_deref().unwrap_or("default"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Streaming mode: the evaluated program is attached to a pseudo terminal, its output is shown while it runs and
+//! the keystrokes are forwarded to it until it exits
+//!
+//! stdin and stdout are the pty, so programs can read lines or draw a TUI, stderr stays a pipe so panics can be
+//! reported (see [`crate::runtime`]), it's shown after the program exits
+use crate::limits::ResourceUsage;
+use crate::Result;
+use std::process::{Command, Output};
+use std::time::Duration;
+
+/// The terminal of the user, see [`crate::EvalConfig::terminal`]
+pub trait Terminal {
+    /// Show the output of the program as it arrives, it contains terminal escape sequences and `\r\n` line endings
+    fn write(&mut self, output: &[u8]) -> Result<()>;
+    /// Poll the user input, it's called in a loop until the program exits so it shouldn't block for long, returning
+    /// an error kills the program
+    fn read(&mut self) -> Result<Option<TerminalInput>>;
+    /// (columns, rows)
+    fn size(&self) -> (u16, u16);
+}
+
+pub enum TerminalInput {
+    /// Keystrokes encoded like a terminal does, exp: `\r` for enter, `\x03` for ctrl-c (the program gets `SIGINT`)
+    Bytes(Vec<u8>),
+    /// (columns, rows)
+    Resize(u16, u16),
+}
+
+/// Run `cmd` attached to a pty, the returned stdout is what the program wrote to the terminal with `\n` line endings
+#[cfg(unix)]
+pub(crate) fn run(
+    mut cmd: Command,
+    terminal: &mut dyn Terminal,
+    timeout: Option<Duration>,
+) -> Result<(Output, ResourceUsage)> {
+    use crate::utils::{kill_process_group, try_wait_measured};
+    use std::io::{Read, Write};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    let (mut master, slave) = unix::open(terminal.size())?;
+    unix::make_controlling_terminal(&mut cmd);
+    let mut child = cmd
+        .stdin(slave.try_clone()?)
+        .stdout(slave)
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // `cmd` holds the last copy of the slave, reading the master fails once the program and its children close it
+    drop(cmd);
+    let start = Instant::now();
+
+    let (tx, rx) = mpsc::channel();
+    let mut reader = master.try_clone()?;
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(n @ 1..) = reader.read(&mut buffer) {
+            if tx.send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr = std::thread::spawn(move || {
+        let mut err = Vec::new();
+        let _ = stderr.read_to_end(&mut err);
+        err
+    });
+
+    let mut stdout = Vec::new();
+    let mut usage = ResourceUsage::default();
+    let show = |terminal: &mut dyn Terminal, output: Vec<u8>, stdout: &mut Vec<u8>| {
+        stdout.extend_from_slice(&output);
+        terminal.write(&output)
+    };
+    let status = loop {
+        if let Some(status) = try_wait_measured(&mut child, &mut usage)? {
+            break status;
+        }
+        if !usage.timed_out && timeout.is_some_and(|timeout| start.elapsed() > timeout) {
+            usage.timed_out = true;
+            // The program is the leader of the session created by `make_controlling_terminal`
+            kill_process_group(&mut child)?;
+        }
+        if let Ok(output) = rx.recv_timeout(Duration::from_millis(1)) {
+            let output = rx.try_iter().fold(output, |mut output, more| {
+                output.extend(more);
+                output
+            });
+            show(terminal, output, &mut stdout)?;
+        }
+        let input = match terminal.read() {
+            Ok(input) => input,
+            Err(e) => {
+                child.kill()?;
+                child.wait()?;
+                return Err(e);
+            }
+        };
+        match input {
+            // Ignore write errors (the program might have ended)
+            Some(TerminalInput::Bytes(bytes)) => {
+                let _ = master.write_all(&bytes);
+            }
+            Some(TerminalInput::Resize(columns, rows)) => unix::resize(&master, (columns, rows))?,
+            None => {}
+        }
+    };
+    usage.wall_time = start.elapsed();
+    // The rest of the output, children of the program can keep the pty open so don't wait for the end
+    while let Ok(output) = rx.recv_timeout(Duration::from_millis(20)) {
+        show(terminal, output, &mut stdout)?;
+    }
+
+    let stdout = String::from_utf8_lossy(&stdout)
+        .replace("\r\n", "\n")
+        .into_bytes();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok((
+        Output {
+            status,
+            stdout,
+            stderr,
+        },
+        usage,
+    ))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn run(
+    _cmd: Command,
+    _terminal: &mut dyn Terminal,
+    _timeout: Option<Duration>,
+) -> Result<(Output, ResourceUsage)> {
+    Err("The streaming mode is only supported on unix".into())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    /// Open a pty of `size` (columns, rows), returns the master and the slave
+    pub(super) fn open(size: (u16, u16)) -> io::Result<(File, File)> {
+        // SAFETY: the returned file descriptors are checked and owned by the files
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            if master == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(master);
+            if libc::grantpt(master.as_raw_fd()) != 0 || libc::unlockpt(master.as_raw_fd()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // ptsname_r is not available everywhere, the name is copied right away
+            let name = libc::ptsname(master.as_raw_fd());
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let slave = libc::open(name, libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            if slave == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let slave = File::from_raw_fd(slave);
+            resize(&master, size)?;
+            Ok((master, slave))
+        }
+    }
+
+    pub(super) fn resize(master: &File, (columns, rows): (u16, u16)) -> io::Result<()> {
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: columns,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCSWINSZ reads a winsize
+        if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// The pty (stdin) becomes the controlling terminal of the program, so ctrl-c sends it `SIGINT`
+    pub(super) fn make_controlling_terminal(cmd: &mut Command) {
+        // SAFETY: setsid and ioctl are async-signal-safe
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Failures of the evaluated program at runtime: panics, non zero exit codes and signals
+//!
+//! Unless `RUST_BACKTRACE` is set, the program prints the backtrace of its panics with [`BACKTRACE_HOOK`], the panic
+//! location and the backtrace frames pointing to the generated `main.rs` are mapped back to the input with the
+//! [`SourceMap`]
+use crate::diagnostics::{Origin, SourceMap};
+use std::process::ExitStatus;
+
+/// `thread 'main' panicked at ` (newer versions add the thread id: `thread 'main' (123) panicked at `)
+const PANIC_TAG: &str = " panicked at ";
+/// The exit code of a process whose main thread panicked
+const PANIC_EXIT_CODE: i32 = 101;
+/// Frames of the standard library and of the async executors
+const RUNTIME_FRAMES: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "__rustc::",
+    "rust_begin_unwind",
+    "__rust",
+    "_start",
+    "__libc_start",
+    "tokio::",
+    "async_std::",
+    "async_global_executor::",
+    "async_executor::",
+    "async_io::",
+    "async_task::",
+    "futures_lite::",
+];
+
+/// Installed by the program before running the repl code, it prints the backtrace after the default panic message
+///
+/// Unlike running the program with `RUST_BACKTRACE=1`, its environment is unchanged: `std::backtrace::Backtrace::capture`
+/// (and the error crates that use it) keep their default behavior
+/// It's a single line so the line numbers of the program are unchanged
+pub(crate) const BACKTRACE_HOOK: &str = "if ::std::env::var_os(\"RUST_BACKTRACE\").is_none() { let __irust_hook = ::std::panic::take_hook(); ::std::panic::set_hook(Box::new(move |info| { __irust_hook(info); eprintln!(\"stack backtrace:\\n{}\", ::std::backtrace::Backtrace::force_capture()); })); }";
+
+/// Describe the failure from the program stderr and its exit status
+pub fn report(
+    stderr: &str,
+    status: ExitStatus,
+    source_map: &SourceMap,
+    crate_name: &str,
+) -> String {
+    let mut report = vec![];
+    let mut panicked = false;
+    let mut lines = stderr.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some((thread, location)) = line
+            .split_once(PANIC_TAG)
+            .filter(|(thread, _)| thread.starts_with("thread '"))
+        {
+            panicked = true;
+            report.push(format!(
+                "{thread}{PANIC_TAG}{}",
+                map_location(location, source_map)
+            ));
+        } else if line == "stack backtrace:" {
+            report.push(line.to_string());
+            let mut frames = vec![];
+            while let Some(frame) = lines.next_if(|line| is_frame(line)) {
+                let function = frame.split_once(": ").map_or("", |(_, function)| function);
+                let location = lines
+                    .next_if(|line| line.trim_start().starts_with("at "))
+                    .map(|line| line.trim_start()["at ".len()..].to_string());
+                frames.push((function.to_string(), location));
+            }
+            report.extend(format_frames(frames, source_map, crate_name));
+        } else if line.starts_with("note: run with `RUST_BACKTRACE=1`")
+            || line.starts_with("note: Some details are omitted")
+        {
+            continue;
+        } else {
+            report.push(line.to_string());
+        }
+    }
+
+    // The panic exit code is implied by the panic message
+    if !(panicked && status.code() == Some(PANIC_EXIT_CODE)) {
+        report.push(describe_status(status));
+    }
+    report.join("\n")
+}
+
+/// `  12: path::to::function`
+fn is_frame(line: &str) -> bool {
+    line.trim_start()
+        .split_once(": ")
+        .is_some_and(|(index, _)| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Keep the frames of the repl code (and its dependencies), renumbered
+fn format_frames(
+    frames: Vec<(String, Option<String>)>,
+    source_map: &SourceMap,
+    crate_name: &str,
+) -> Vec<String> {
+    // The repl code is in the main function, or in `__irust_eval` in persistent mode
+    let main_path = if source_map.lib {
+        format!("{crate_name}::__irust_eval::")
+    } else {
+        format!("{crate_name}::main::")
+    };
+    let crate_path = format!("{crate_name}::");
+    // The persistent mode host process and the library entry point
+    let is_glue = |path: &str| {
+        let path = path.strip_prefix(&crate_path).unwrap_or(path);
+        source_map.lib && (path.starts_with("irust_eval") || path.starts_with("host::"))
+    };
+    frames
+        .into_iter()
+        .filter(|(function, location)| {
+            let path = function.trim_start_matches('<');
+            !RUNTIME_FRAMES.iter().any(|frame| path.starts_with(frame))
+                && !is_glue(path)
+                && !location
+                    .as_ref()
+                    .is_some_and(|location| location.starts_with("/rustc/"))
+        })
+        .enumerate()
+        .flat_map(|(idx, (function, location))| {
+            let mut frame = vec![format!(
+                "{idx:>4}: {}",
+                function.replace(&main_path, "").replace(&crate_path, "")
+            )];
+            if let Some(location) = location {
+                frame.push(format!(
+                    "             at {}",
+                    map_location(&location, source_map)
+                ));
+            }
+            frame
+        })
+        .collect()
+}
+
+/// `./src/main.rs:5:9` => `input, line 2, column 5`, the locations outside `main.rs` are kept
+fn map_location(location: &str, source_map: &SourceMap) -> String {
+    let Some((path, position)) = location.split_once(&format!("{}:", source_map.file())) else {
+        return location.to_string();
+    };
+    let Some(prefix) = path.strip_suffix("src/") else {
+        return location.to_string();
+    };
+    // The backtrace paths are absolute or relative (`./src/main.rs`), before rust 1.73 the panic message comes
+    // before the location: `'message', src/main.rs:5:9`
+    let prefix = if prefix.ends_with('/') { "" } else { prefix };
+    let mut numbers = position.splitn(3, ':');
+    let line = numbers.next().and_then(|line| line.parse().ok());
+    let column = numbers.next().and_then(|column| column.parse().ok());
+    // The panic location is followed by `:`
+    let rest = numbers
+        .next()
+        .map(|rest| format!(":{rest}"))
+        .unwrap_or_default();
+    let Some((origin, line, column)) = line
+        .zip(column)
+        .and_then(|(line, column)| source_map.locate(line, column))
+    else {
+        return location.to_string();
+    };
+    match origin {
+        Origin::Input => format!("{prefix}input, line {line}, column {column}{rest}"),
+        Origin::Repl => format!("{prefix}repl line {line}, column {column}{rest}"),
+    }
+}
+
+/// `process exited with code 1` or `process was killed by signal 11 (SIGSEGV)`
+fn describe_status(status: ExitStatus) -> String {
+    format!("process {}", exit_reason(status))
+}
+
+/// `exited with code 1` or `was killed by signal 11 (SIGSEGV)`
+pub fn exit_reason(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("exited with code {code}");
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            let name = match signal {
+                libc::SIGHUP => "SIGHUP",
+                libc::SIGINT => "SIGINT",
+                libc::SIGQUIT => "SIGQUIT",
+                libc::SIGILL => "SIGILL",
+                libc::SIGTRAP => "SIGTRAP",
+                libc::SIGABRT => "SIGABRT",
+                libc::SIGBUS => "SIGBUS",
+                libc::SIGFPE => "SIGFPE",
+                libc::SIGKILL => "SIGKILL",
+                libc::SIGSEGV => "SIGSEGV",
+                libc::SIGPIPE => "SIGPIPE",
+                libc::SIGTERM => "SIGTERM",
+                libc::SIGXCPU => "SIGXCPU",
+                libc::SIGSYS => "SIGSYS",
+                _ => return format!("was killed by signal {signal}"),
+            };
+            let core_dumped = if status.core_dumped() {
+                ", core dumped"
+            } else {
+                ""
+            };
+            return format!("was killed by signal {signal} ({name}{core_dumped})");
+        }
+    }
+    "exited with an unknown status".to_string()
+}
@@ -0,0 +1,506 @@
+//! Isolation of the evaluated program, Linux only
+//!
+//! The program runs in new user, mount, PID, network and IPC namespaces:
+//! - the filesystem is read-only except the scratch directory (an empty tmpfs for each run, also set as `TMPDIR`)
+//! - the directories of the unix sockets (`/run`, `/var/run`, `/tmp` and `$XDG_RUNTIME_DIR`) are replaced by empty
+//!   read-only directories, so the program can't reach the docker daemon, the ssh agent or D-Bus
+//! - the program is the init process of its PID namespace, it can't see or signal the other processes (the signals
+//!   it doesn't handle are ignored, except `SIGKILL` sent from outside)
+//! - the network only has a loopback interface that is down
+//! - the environment only keeps the variables in [`ENV_ALLOWLIST`]
+//! - a seccomp filter kills the program if it uses the system calls that could undo the isolation (mounts, new
+//!   namespaces with `unshare`, `setns` or `clone`, ptrace, kernel modules...), `clone3` fails with `ENOSYS` since
+//!   its flags can't be filtered, so the C library falls back to `clone`
+//!
+//! The build itself is not sandboxed: build scripts and proc macros of the dependencies run unrestricted
+use crate::Result;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+
+/// Errors printed by the program when it can't reach the network
+const NETWORK_ERRORS: &[&str] = &[
+    "Network is unreachable",
+    "Cannot assign requested address",
+    "failed to lookup address information",
+];
+
+/// Variables of the environment passed to the program, the others can hold secrets or the addresses of services
+const ENV_ALLOWLIST: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LANGUAGE",
+    "TZ",
+    "TERM",
+    "COLORTERM",
+    "NO_COLOR",
+    "RUST_BACKTRACE",
+    "RUST_LIB_BACKTRACE",
+    "RUST_LOG",
+    "RUST_MIN_STACK",
+];
+
+/// Directories of the unix sockets of the user and of the system
+const HIDDEN_DIRS: &[&str] = &["/run", "/var/run", "/tmp"];
+
+/// Prepare `cmd` to run in the sandbox, `scratch_dir` is the only writable directory
+pub(crate) fn apply(cmd: &mut Command, scratch_dir: &Path) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::apply(cmd, scratch_dir)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (cmd, scratch_dir);
+        Err("The sandbox is only supported on Linux".into())
+    }
+}
+
+/// Clear the environment of `cmd` except the allowed variables, the ones set on `cmd` are kept
+fn filter_env(cmd: &mut Command) {
+    let explicit: Vec<(OsString, Option<OsString>)> = cmd
+        .get_envs()
+        .map(|(key, value)| (key.to_owned(), value.map(ToOwned::to_owned)))
+        .collect();
+    cmd.env_clear();
+    for (key, value) in std::env::vars_os() {
+        let allowed = key
+            .to_str()
+            .is_some_and(|key| ENV_ALLOWLIST.contains(&key) || key.starts_with("LC_"));
+        if allowed {
+            cmd.env(key, value);
+        }
+    }
+    for (key, value) in explicit {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+}
+
+/// The directories to hide: the existing [`HIDDEN_DIRS`] and `$XDG_RUNTIME_DIR`, without the ones inside another one
+fn hidden_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = HIDDEN_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .chain(std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from))
+        .filter_map(|dir| dir.canonicalize().ok())
+        .filter(|dir| dir.is_dir() && dir != Path::new("/"))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    let parents = dirs.clone();
+    dirs.retain(|dir| {
+        !parents
+            .iter()
+            .any(|parent| parent != dir && dir.starts_with(parent))
+    });
+    dirs
+}
+
+/// Check that the sandbox can be created, unprivileged user namespaces can be disabled by the system
+pub(crate) fn check_available(scratch_dir: &Path) -> Result<()> {
+    let mut cmd = Command::new("true");
+    apply(&mut cmd, scratch_dir)?;
+    let status = cmd
+        .status()
+        .map_err(|e| format!("The sandbox can't be created: {e}"))?;
+    if !status.success() {
+        return Err(format!("The sandbox can't be created: {status}").into());
+    }
+    Ok(())
+}
+
+/// Which rule of the sandbox the program broke, if it did
+pub(crate) fn violation(stderr: &str, status: ExitStatus, scratch_dir: &Path) -> Option<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if status.signal() == Some(libc::SIGSYS) {
+            return Some("sandbox violation: the program used a forbidden system call".to_string());
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = status;
+    if stderr.contains("Read-only file system") {
+        return Some(format!(
+            "sandbox violation: the filesystem is read-only, only the scratch directory `{}` is writable",
+            scratch_dir.display()
+        ));
+    }
+    NETWORK_ERRORS
+        .iter()
+        .any(|error| stderr.contains(error))
+        .then(|| "sandbox violation: the network is disabled".to_string())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use crate::Result;
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::unix::{ffi::OsStrExt, process::CommandExt};
+    use std::path::Path;
+    use std::process::Command;
+    use std::ptr::null;
+
+    // `linux/mount.h`, `linux/fcntl.h`, `linux/audit.h`, `linux/filter.h` and `linux/seccomp.h`
+    const MOUNT_ATTR_RDONLY: u64 = 0x1;
+    const AT_RECURSIVE: libc::c_int = 0x8000;
+    const OPEN_TREE_CLONE: libc::c_uint = 0x1;
+    const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x4;
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JEQ_K: u16 = 0x15;
+    const BPF_JGE_K: u16 = 0x35;
+    const BPF_JSET_K: u16 = 0x45;
+    const BPF_RET_K: u16 = 0x06;
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    /// The `clone` flags that create namespaces
+    const CLONE_NEW_FLAGS: libc::c_int = libc::CLONE_NEWNS
+        | libc::CLONE_NEWCGROUP
+        | libc::CLONE_NEWUTS
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUSER
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWNET;
+
+    /// System calls that could undo the isolation or attack the kernel
+    const FORBIDDEN_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_mount_setattr,
+        libc::SYS_move_mount,
+        libc::SYS_open_tree,
+        libc::SYS_fsopen,
+        libc::SYS_fsmount,
+        libc::SYS_fspick,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_kexec_load,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+    ];
+
+    /// `struct mount_attr` of `mount_setattr`
+    #[repr(C)]
+    struct MountAttr {
+        attr_set: u64,
+        attr_clr: u64,
+        propagation: u64,
+        userns_fd: u64,
+    }
+
+    /// What the child process needs to enter the sandbox, allocated before the fork
+    struct Plan {
+        uid_map: String,
+        gid_map: String,
+        /// Replaced by read-only empty tmpfs
+        hidden: Vec<CString>,
+        /// The directory of the executable if it's in a hidden directory, it's mounted back at the same place
+        kept: Option<CString>,
+        /// Mount points to create in the hidden directories, parents first
+        mount_points: Vec<CString>,
+        scratch_dir: CString,
+        filter: Vec<libc::sock_filter>,
+    }
+
+    pub(super) fn apply(cmd: &mut Command, scratch_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(scratch_dir)?;
+        let scratch_dir = scratch_dir.canonicalize()?;
+        // SAFETY: getuid and getgid always succeed
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        // Root in the namespace would keep its capabilities after exec, so it's mapped to nobody
+        let inner = |id| if id == 0 { 65534 } else { id };
+
+        let hidden = super::hidden_dirs();
+        let in_hidden = |path: &Path| hidden.iter().any(|dir| path.starts_with(dir));
+        let exe_dir = Path::new(cmd.get_program())
+            .parent()
+            .filter(|dir| dir.is_absolute())
+            .and_then(|dir| dir.canonicalize().ok())
+            .filter(|dir| in_hidden(dir));
+        let mut mount_points = vec![];
+        for path in exe_dir.iter().chain([&scratch_dir]) {
+            let mut ancestors: Vec<&Path> = path
+                .ancestors()
+                .take_while(|ancestor| {
+                    in_hidden(ancestor) && !hidden.iter().any(|dir| dir == ancestor)
+                })
+                .collect();
+            ancestors.reverse();
+            mount_points.extend(ancestors.into_iter().map(Path::to_path_buf));
+        }
+        let c_path = |path: &Path| CString::new(path.as_os_str().as_bytes());
+        let plan = Plan {
+            uid_map: format!("{} {uid} 1", inner(uid)),
+            gid_map: format!("{} {gid} 1", inner(gid)),
+            hidden: hidden
+                .iter()
+                .map(|dir| c_path(dir))
+                .collect::<std::result::Result<_, _>>()?,
+            kept: exe_dir.as_deref().map(c_path).transpose()?,
+            mount_points: mount_points
+                .iter()
+                .map(|dir| c_path(dir))
+                .collect::<std::result::Result<_, _>>()?,
+            scratch_dir: c_path(&scratch_dir)?,
+            filter: seccomp_filter(),
+        };
+
+        super::filter_env(cmd);
+        cmd.env("TMPDIR", &scratch_dir);
+        // SAFETY: everything is allocated before the fork, the closure only does system calls
+        unsafe {
+            cmd.pre_exec(move || enter(&plan));
+        }
+        Ok(())
+    }
+
+    /// Runs in the child process, between fork and exec
+    fn enter(plan: &Plan) -> io::Result<()> {
+        let root = c"/".as_ptr();
+        let tmpfs = c"tmpfs".as_ptr();
+        let read_only = MountAttr {
+            attr_set: MOUNT_ATTR_RDONLY,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        let set_read_only = |path: *const libc::c_char, flags: libc::c_int| {
+            // SAFETY: path is a nul terminated string and the attributes outlive the call
+            check(unsafe {
+                libc::syscall(
+                    libc::SYS_mount_setattr,
+                    libc::AT_FDCWD,
+                    path,
+                    flags,
+                    &read_only as *const MountAttr,
+                    std::mem::size_of::<MountAttr>(),
+                )
+            } as libc::c_int)
+        };
+        // SAFETY: the pointers are valid nul terminated strings and structs that outlive the calls
+        unsafe {
+            check(libc::unshare(
+                libc::CLONE_NEWUSER
+                    | libc::CLONE_NEWNS
+                    | libc::CLONE_NEWPID
+                    | libc::CLONE_NEWNET
+                    | libc::CLONE_NEWIPC,
+            ))?;
+            write_file(c"/proc/self/setgroups", "deny")?;
+            write_file(c"/proc/self/uid_map", &plan.uid_map)?;
+            write_file(c"/proc/self/gid_map", &plan.gid_map)?;
+
+            // The mount changes must not propagate to the parent namespace
+            check(libc::mount(
+                null(),
+                root,
+                null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                null(),
+            ))?;
+            set_read_only(root, AT_RECURSIVE)?;
+
+            // The directory of the executable is cloned before it's hidden
+            let kept = match &plan.kept {
+                Some(dir) => {
+                    let fd = libc::syscall(
+                        libc::SYS_open_tree,
+                        libc::AT_FDCWD,
+                        dir.as_ptr(),
+                        OPEN_TREE_CLONE
+                            | libc::O_CLOEXEC as libc::c_uint
+                            | AT_RECURSIVE as libc::c_uint,
+                    ) as libc::c_int;
+                    check(fd)?;
+                    Some((fd, dir))
+                }
+                None => None,
+            };
+            for dir in &plan.hidden {
+                check(libc::mount(
+                    tmpfs,
+                    dir.as_ptr(),
+                    tmpfs,
+                    libc::MS_NOSUID | libc::MS_NODEV,
+                    null(),
+                ))?;
+            }
+            for dir in &plan.mount_points {
+                if libc::mkdir(dir.as_ptr(), 0o755) == -1
+                    && io::Error::last_os_error().raw_os_error() != Some(libc::EEXIST)
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some((fd, dir)) = kept {
+                check(libc::syscall(
+                    libc::SYS_move_mount,
+                    fd,
+                    c"".as_ptr(),
+                    libc::AT_FDCWD,
+                    dir.as_ptr(),
+                    MOVE_MOUNT_F_EMPTY_PATH,
+                ) as libc::c_int)?;
+                libc::close(fd);
+            }
+            check(libc::mount(
+                tmpfs,
+                plan.scratch_dir.as_ptr(),
+                tmpfs,
+                libc::MS_NOSUID | libc::MS_NODEV,
+                null(),
+            ))?;
+            for dir in &plan.hidden {
+                set_read_only(dir.as_ptr(), 0)?;
+            }
+
+            // Required to install a seccomp filter without privileges, it also disables setuid binaries
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+
+            // Only the children enter the PID namespace, the program is started by a new child
+            let pid = libc::fork();
+            check(pid)?;
+            if pid > 0 {
+                wait_and_exit_like(pid);
+            }
+            // The program dies with the parent, it can be killed with the parent process group
+            check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0))?;
+
+            if !plan.filter.is_empty() {
+                let program = libc::sock_fprog {
+                    len: plan.filter.len() as u16,
+                    filter: plan.filter.as_ptr() as *mut libc::sock_filter,
+                };
+                check(libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs in the parent of the program (outside of its PID namespace), it exits with the status of the program
+    fn wait_and_exit_like(pid: libc::pid_t) -> ! {
+        // SAFETY: only system calls on the parent process and its child
+        unsafe {
+            // The file descriptors are only needed by the program, `Command::spawn` waits for the close of the one
+            // it uses to report the exec errors
+            libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0);
+            let mut status = 0;
+            while libc::waitpid(pid, &mut status, 0) == -1
+                && io::Error::last_os_error().raw_os_error() == Some(libc::EINTR)
+            {}
+            if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                // Die by the same signal, without a core dump
+                libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::kill(libc::getpid(), signal);
+            }
+            libc::_exit(if libc::WIFEXITED(status) {
+                libc::WEXITSTATUS(status)
+            } else {
+                1
+            })
+        }
+    }
+
+    /// Kill the process on the forbidden system calls, empty on the architectures it doesn't support
+    fn seccomp_filter() -> Vec<libc::sock_filter> {
+        let statement = |code, k| libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |code, k, jt, jf| libc::sock_filter { code, jt, jf, k };
+        let kill = statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS);
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        {
+            // The fields of `struct seccomp_data`, the flags are the low half of the first argument (little endian)
+            let (nr_offset, arch_offset, flags_offset) = (0, 4, 16);
+            let mut filter = vec![
+                statement(BPF_LD_W_ABS, arch_offset),
+                jump(BPF_JEQ_K, AUDIT_ARCH, 1, 0),
+                kill,
+                statement(BPF_LD_W_ABS, nr_offset),
+            ];
+            // x32 system calls have the same arch with this bit set
+            if cfg!(target_arch = "x86_64") {
+                filter.extend([jump(BPF_JGE_K, 0x4000_0000, 0, 1), kill]);
+            }
+            for &syscall in FORBIDDEN_SYSCALLS {
+                filter.extend([jump(BPF_JEQ_K, syscall as u32, 0, 1), kill]);
+            }
+            filter.extend([
+                jump(BPF_JEQ_K, libc::SYS_clone3 as u32, 0, 1),
+                statement(BPF_RET_K, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+                jump(BPF_JEQ_K, libc::SYS_clone as u32, 0, 3),
+                statement(BPF_LD_W_ABS, flags_offset),
+                jump(BPF_JSET_K, CLONE_NEW_FLAGS as u32, 0, 1),
+                kill,
+            ]);
+            filter.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+            filter
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        {
+            let _ = (jump, kill);
+            vec![]
+        }
+    }
+
+    fn write_file(path: &CStr, content: &str) -> io::Result<()> {
+        // SAFETY: path is nul terminated and content is valid for its length
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            check(fd)?;
+            let written = libc::write(fd, content.as_ptr().cast(), content.len());
+            libc::close(fd);
+            if written != content.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+//! Unit tests defined in the repl
+//!
+//! The `#[test]` functions are kept apart from the repl body, they are written in a test module of `main.rs`
+//! only when the tests run, the module contains a copy of the repl items (fn, struct, use..) so the tests can use them
+use crate::diagnostics::{Level, SourceMap};
+use crate::persistent::repl_chunks;
+use crate::utils::is_allowed_in_lib;
+use crate::{EvalResult, Repl, Result};
+use std::process::Child;
+
+const TEST_MODULE: &str = "irust_tests";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: TestOutcome,
+    /// Captured stdout and panic message
+    pub output: String,
+}
+
+#[derive(Debug)]
+pub struct TestReport {
+    /// The tests didn't run because the build failed
+    pub build_failure: Option<EvalResult>,
+    pub tests: Vec<TestResult>,
+}
+
+/// Whether the code is a function with a `#[test]` attribute
+pub fn is_test(code: &str) -> bool {
+    code.lines()
+        .map(str::trim)
+        .take_while(|line| line.starts_with("#["))
+        .any(|line| line.contains("#[test]"))
+}
+
+/// Name of the first function of the code
+fn test_name(code: &str) -> Option<&str> {
+    let mut words = code
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty());
+    words.find(|word| *word == "fn")?;
+    words.next()
+}
+
+impl Repl {
+    /// Add a `#[test]` function, it replaces the test with the same name if there is one
+    pub fn add_test(&mut self, code: impl ToString) -> Result<()> {
+        let code = code.to_string();
+        if !is_test(&code) {
+            return Err("Only functions with a `#[test]` attribute can be added as tests".into());
+        }
+        let name =
+            test_name(&code).ok_or("The `#[test]` attribute must be entered with its function")?;
+        match self
+            .tests
+            .iter()
+            .position(|test| test_name(test) == Some(name))
+        {
+            Some(idx) => self.tests[idx] = code,
+            None => self.tests.push(code),
+        }
+        Ok(())
+    }
+
+    pub fn tests(&self) -> &[String] {
+        &self.tests
+    }
+
+    /// Run the tests whose name contains `filter` (all of them without a filter) with `cargo test`, in the sandbox if
+    /// it's enabled
+    pub fn test(
+        &self,
+        filter: Option<&str>,
+        color: bool,
+        interactive_function: Option<fn(&mut Child) -> Result<()>>,
+    ) -> Result<TestReport> {
+        if self.tests.is_empty() {
+            return Err(
+                "There are no tests, a function with a `#[test]` attribute defines one".into(),
+            );
+        }
+        std::fs::write(
+            &self.cargo.paths.main_file,
+            format!("{}\n{}", self.body(), self.test_module()),
+        )?;
+        let result = self.cargo.cargo_test(
+            filter,
+            color,
+            self.toolchain.clone(),
+            self.sandbox,
+            interactive_function,
+        );
+        self.write()?;
+        let (status, output) = result?;
+
+        // The test module lines are not mapped, the diagnostics are shown without their source
+        let build = EvalResult::build_failure(status, output, &SourceMap::default(), color);
+        if build
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.level == Level::Error)
+        {
+            return Ok(TestReport {
+                build_failure: Some(build),
+                tests: vec![],
+            });
+        }
+        let tests = parse_test_output(&build.output);
+        if tests.is_empty() && !status.success() {
+            return Err(build.output.into());
+        }
+        Ok(TestReport {
+            build_failure: None,
+            tests,
+        })
+    }
+
+    /// The tests with a copy of the repl items
+    pub(crate) fn test_module(&self) -> String {
+        let (_, chunks) = repl_chunks(self);
+        let items = chunks.into_iter().filter(|chunk| {
+            chunk
+                .lines()
+                .find(|line| !line.trim_start().starts_with("//"))
+                .is_some_and(is_allowed_in_lib)
+        });
+        let mut module = vec![
+            "#[cfg(test)]".to_string(),
+            format!("mod {TEST_MODULE} {{"),
+            "#![allow(unused)]".to_string(),
+        ];
+        module.extend(items);
+        module.extend(self.tests.iter().cloned());
+        module.push("}".to_string());
+        module.join("\n")
+    }
+}
+
+/// Parse libtest output (`--show-output --color never`)
+fn parse_test_output(output: &str) -> Vec<TestResult> {
+    let strip_module = |name: &str| {
+        name.strip_prefix(TEST_MODULE)
+            .and_then(|name| name.strip_prefix("::"))
+            .unwrap_or(name)
+            .to_string()
+    };
+    let mut tests: Vec<TestResult> = output
+        .lines()
+        .filter_map(|line| {
+            let (name, outcome) = line.strip_prefix("test ")?.rsplit_once(" ... ")?;
+            let outcome = match outcome {
+                "ok" => TestOutcome::Passed,
+                "FAILED" => TestOutcome::Failed,
+                outcome if outcome.starts_with("ignored") => TestOutcome::Ignored,
+                _ => return None,
+            };
+            Some(TestResult {
+                name: strip_module(name),
+                outcome,
+                output: String::new(),
+            })
+        })
+        .collect();
+
+    // ---- irust_tests::name stdout ----
+    // captured output
+    //
+    // failures:
+    let mut lines = output.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|line| line.strip_suffix(" stdout ----"))
+        else {
+            continue;
+        };
+        let mut captured = vec![];
+        while let Some(line) = lines.next_if(|line| {
+            !line.starts_with("---- ") && *line != "failures:" && *line != "successes:"
+        }) {
+            captured.push(line);
+        }
+        let name = strip_module(name);
+        if let Some(test) = tests.iter_mut().find(|test| test.name == name) {
+            test.output = captured.join("\n").trim_end().to_string();
+        }
+    }
+    tests
+}
@@ -1,9 +1,11 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::{fmt::Display, str::FromStr};
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum ToolChain {
     Stable,
     Beta,
@@ -11,6 +13,9 @@ pub enum ToolChain {
     // cargo with no +argument, it can be different from the above
     #[default]
     Default,
+    /// Any other rustup toolchain, exp: `1.75.0`, `nightly-2024-01-01` or a linked toolchain name
+    #[cfg_attr(feature = "serde", serde(untagged))]
+    Custom(String),
 }
 
 impl FromStr for ToolChain {
@@ -21,21 +26,76 @@ impl FromStr for ToolChain {
             "beta" => Ok(ToolChain::Beta),
             "nightly" => Ok(ToolChain::Nightly),
             "default" => Ok(ToolChain::Default),
-            _ => Err("Unknown toolchain".into()),
+            _ => {
+                let toolchain = s.trim_start_matches('+');
+                if toolchain.is_empty() || toolchain.contains(char::is_whitespace) {
+                    return Err("Unknown toolchain".into());
+                }
+                Ok(ToolChain::Custom(toolchain.to_string()))
+            }
         }
     }
 }
 
 impl ToolChain {
-    pub(crate) fn as_arg(&self) -> &str {
+    pub(crate) fn as_arg(&self) -> String {
         match self {
-            ToolChain::Stable => "+stable",
-            ToolChain::Beta => "+beta",
-            ToolChain::Nightly => "+nightly",
             // The caller should not call as_arg for the default toolchain
             ToolChain::Default => unreachable!(),
+            toolchain => format!("+{toolchain}"),
         }
     }
+
+    /// Check that the toolchain is installed with rustup
+    pub fn check_installed(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if *self == ToolChain::Default {
+            return Ok(());
+        }
+        let output = Command::new("rustup")
+            .args(["toolchain", "list"])
+            .output()
+            .map_err(|e| format!("Failed to run rustup: {e}"))?;
+        let toolchain = self.to_string();
+        // The installed toolchains names contain the host triple, exp: `1.75.0-x86_64-unknown-linux-gnu (default)`
+        let installed = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .any(|name| {
+                name == toolchain
+                    || name.strip_prefix(&toolchain).is_some_and(|triple| {
+                        // `nightly` should not match `nightly-2024-01-01-x86_64-unknown-linux-gnu`
+                        triple.starts_with('-') && triple[1..].starts_with(char::is_alphabetic)
+                    })
+            });
+        if installed {
+            Ok(())
+        } else {
+            Err(format!(
+                "Toolchain `{toolchain}` is not installed, you can install it with `rustup toolchain install {toolchain}`"
+            )
+            .into())
+        }
+    }
+
+    /// The toolchain of `rust-toolchain.toml` (or the legacy `rust-toolchain`) in `dir` or its parents, like rustup
+    /// does, with the path of that file
+    ///
+    /// Like rustup, the search stops at the first toolchain file even if it doesn't set a channel
+    pub fn from_toolchain_file(dir: &Path) -> Option<(ToolChain, PathBuf)> {
+        let path = dir.ancestors().find_map(|dir| {
+            ["rust-toolchain.toml", "rust-toolchain"]
+                .into_iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        })?;
+        let contents = std::fs::read_to_string(&path).ok()?;
+        let channel = match contents.parse::<toml_edit::Document>() {
+            Ok(toml) => toml.get("toolchain")?.get("channel")?.as_str()?.to_string(),
+            // The legacy file can contain only the toolchain name
+            Err(_) => contents.trim().to_string(),
+        };
+        Some((channel.parse().ok()?, path))
+    }
 }
 
 impl Display for ToolChain {
@@ -45,6 +105,7 @@ impl Display for ToolChain {
             ToolChain::Beta => write!(f, "beta"),
             ToolChain::Nightly => write!(f, "nightly"),
             ToolChain::Default => write!(f, "default"),
+            ToolChain::Custom(toolchain) => write!(f, "{toolchain}"),
         }
     }
 }
@@ -0,0 +1,88 @@
+//! Types of the evaluated values, computed with `std::any::type_name` by the compiled code
+use crate::{CompileMode, EvalResult, Repl, Result};
+use once_cell::sync::Lazy;
+
+/// Evaluator that prints the value with its type: `value: Type`
+///
+/// `match` keeps the temporaries of the expression alive like the format arguments of the default evaluator
+pub static TYPED_EVALUATOR: Lazy<[String; 2]> = Lazy::new(|| {
+    [
+        "match {\n".into(),
+        "\n} { __irust_value => println!(\"{:?}: {}\", __irust_value, std::any::type_name_of_val(&__irust_value)) };"
+            .into(),
+    ]
+});
+
+/// Separates the output of the previous statements from the type in the output of `TYPE_OF_EVALUATOR`
+const TYPE_MARKER: &str = "\u{1}irust_type\n";
+
+/// The expression is the body of a closure that is never called, so only its type is computed
+static TYPE_OF_EVALUATOR: Lazy<[String; 2]> = Lazy::new(|| {
+    [
+        format!("{{ fn __irust_type_of<T>(_: impl FnOnce() -> T) -> &'static str {{ std::any::type_name::<T>() }}\nprintln!(\"{{}}{{}}\", {TYPE_MARKER:?}, __irust_type_of(|| {{\n"),
+        "\n})); };".into(),
+    ]
+});
+
+impl Repl {
+    /// Type of `expression`, it works for any type (no `Debug` requirement) and the expression itself is not run
+    ///
+    /// The program still runs up to the expression like a normal evaluation, so the previous statements are
+    /// re-run (except in persistent mode), their output is discarded
+    ///
+    /// On success the output is the shortened type name, otherwise the result contains the build errors
+    pub fn type_of(&mut self, expression: impl ToString) -> Result<EvalResult> {
+        let mut result = self.eval_inner(
+            expression,
+            None,
+            false,
+            &*TYPE_OF_EVALUATOR,
+            CompileMode::Debug,
+            None,
+        )?;
+        if result.status.success() {
+            let (_, type_name) = result
+                .output
+                .rsplit_once(TYPE_MARKER)
+                .ok_or("The program exited before the type was computed")?;
+            result.output = short_type_name(type_name.trim());
+        }
+        Ok(result)
+    }
+}
+
+/// Split the output of `TYPED_EVALUATOR` into the value and its shortened type
+pub fn split_typed_output(output: &str) -> Option<(&str, String)> {
+    // The type is printed last and type names don't contain `: `
+    let (value, type_name) = output.rsplit_once(": ")?;
+    if type_name.contains('\n') {
+        return None;
+    }
+    Some((value, short_type_name(type_name)))
+}
+
+/// Remove the module paths of a `type_name`, exp: `alloc::vec::Vec<core::option::Option<i32>>` => `Vec<Option<i32>>`
+pub fn short_type_name(type_name: &str) -> String {
+    let is_path_char = |c: char| c.is_alphanumeric() || c == '_' || c == ':';
+    let mut short = String::new();
+    let mut rest = type_name;
+    while !rest.is_empty() {
+        let path_len = rest.find(|c| !is_path_char(c)).unwrap_or(rest.len());
+        let (path, after) = rest.split_at(path_len);
+        match path.rsplit_once("::") {
+            // `repl::main::{{closure}}` => `{{closure}}`
+            Some(("", "")) => short.push_str("::"),
+            Some((_, "")) => {}
+            Some((_, segment)) => short.push_str(segment),
+            None => short.push_str(path),
+        }
+        let other_len = after.find(is_path_char).unwrap_or(after.len());
+        short.push_str(&after[..other_len]);
+        rest = &after[other_len..];
+    }
+    // Closures defined in closures
+    while short.contains("{{closure}}::{{closure}}") {
+        short = short.replace("{{closure}}::{{closure}}", "{{closure}}");
+    }
+    short
+}
@@ -1,8 +1,9 @@
-use crate::Result;
+use crate::{limits::ResourceUsage, Result};
 use std::{
     io::Read,
-    process::{Child, Output},
+    process::{Child, ExitStatus, Output},
     sync::mpsc,
+    time::{Duration, Instant},
 };
 
 pub fn stdout_and_stderr(out: Output) -> String {
@@ -17,13 +18,27 @@ pub fn stdout_and_stderr(out: Output) -> String {
 
 pub trait ProcessUtils {
     fn interactive_output(self, function: Option<fn(&mut Child) -> Result<()>>) -> Result<Output>;
+    /// Like `interactive_output`, the process is killed after `timeout` and its resource usage is collected
+    ///
+    /// With a timeout, the process must be the leader of its process group (see [`kill_process_group`])
+    fn measured_output(
+        self,
+        function: Option<fn(&mut Child) -> Result<()>>,
+        timeout: Option<Duration>,
+    ) -> Result<(Output, ResourceUsage)>;
 }
 
 impl ProcessUtils for Child {
-    fn interactive_output(
+    fn interactive_output(self, function: Option<fn(&mut Child) -> Result<()>>) -> Result<Output> {
+        Ok(self.measured_output(function, None)?.0)
+    }
+
+    fn measured_output(
         mut self,
         function: Option<fn(&mut Child) -> Result<()>>,
-    ) -> Result<Output> {
+        timeout: Option<Duration>,
+    ) -> Result<(Output, ResourceUsage)> {
+        let start = Instant::now();
         let mut stdout = self.stdout.take().expect("stdout is piped");
         let mut stderr = self.stderr.take().expect("stderr is piped");
 
@@ -46,11 +61,22 @@ impl ProcessUtils for Child {
             let _ = tx_err.send(OutType::Stderr(err));
         });
 
-        while self.try_wait()?.is_none() {
+        let mut usage = ResourceUsage::default();
+        let status = loop {
+            if let Some(status) = try_wait_measured(&mut self, &mut usage)? {
+                break status;
+            }
+            if !usage.timed_out && timeout.is_some_and(|timeout| start.elapsed() > timeout) {
+                usage.timed_out = true;
+                kill_process_group(&mut self)?;
+            }
             if let Some(ref function) = function {
                 function(&mut self)?;
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
             }
-        }
+        };
+        usage.wall_time = start.elapsed();
         let mut stdout = None;
         let mut stderr = None;
         for _ in 0..2 {
@@ -60,15 +86,71 @@ impl ProcessUtils for Child {
             }
         }
 
-        Ok(Output {
-            status: self.wait()?,
-            stdout: stdout.unwrap(),
-            stderr: stderr.unwrap(),
-        })
+        Ok((
+            Output {
+                status,
+                stdout: stdout.unwrap(),
+                stderr: stderr.unwrap(),
+            },
+            usage,
+        ))
+    }
+}
+
+/// `try_wait` that also collects the CPU time and peak memory of the process on unix
+#[cfg(unix)]
+pub(crate) fn try_wait_measured(
+    child: &mut Child,
+    usage: &mut ResourceUsage,
+) -> Result<Option<ExitStatus>> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct that wait4 fills
+    let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+    let pid = unsafe { libc::wait4(child.id() as _, &mut status, libc::WNOHANG, &mut rusage) };
+    match pid {
+        0 => Ok(None),
+        -1 => Err(std::io::Error::last_os_error().into()),
+        _ => {
+            let time = |time: libc::timeval| {
+                Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+            };
+            usage.user_time = Some(time(rusage.ru_utime));
+            usage.system_time = Some(time(rusage.ru_stime));
+            // In bytes on macOS and KiB elsewhere
+            let max_rss = rusage.ru_maxrss as u64;
+            usage.peak_rss = Some(if cfg!(target_os = "macos") {
+                max_rss
+            } else {
+                max_rss * 1024
+            });
+            Ok(Some(ExitStatus::from_raw(status)))
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn try_wait_measured(
+    child: &mut Child,
+    _usage: &mut ResourceUsage,
+) -> Result<Option<ExitStatus>> {
+    Ok(child.try_wait()?)
+}
+
+/// Kill the process with the processes it started, on unix they are only killed if the process is the leader of its
+/// process group
+pub(crate) fn kill_process_group(child: &mut Child) -> Result<()> {
+    // SAFETY: kill has no memory safety requirements
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
     }
+    child.kill()?;
+    Ok(())
 }
 
-pub fn _is_allowed_in_lib(s: &str) -> bool {
+pub fn is_allowed_in_lib(s: &str) -> bool {
     match s.split_whitespace().collect::<Vec<_>>().as_slice() {
         // async fn|const fn|unsafe fn
         [_, "fn", ..]
@@ -81,7 +163,11 @@ pub fn _is_allowed_in_lib(s: &str) -> bool {
         | ["impl", ..]
         | ["pub", ..]
         | ["extern", ..]
-        | ["macro", ..] => true,
+        | ["macro", ..]
+        | ["const", ..]
+        | ["static", ..]
+        | ["type", ..]
+        | ["mod", ..] => true,
         ["macro_rules!", ..] => true,
         // attribute exp:
         // #[derive(Debug)]
@@ -29,3 +29,840 @@ fn two_repls_at_the_same_time() {
 
     assert_eq!(a1_thread.join().unwrap() + a2_thread.join().unwrap(), 9)
 }
+
+#[cfg(unix)]
+#[test]
+fn persistent() {
+    use irust_repl::diagnostics::Origin;
+
+    let mut repl = Repl::default();
+    repl.set_persistent(true).unwrap();
+    repl.insert(r#"let v = { println!("side effect"); vec![1, 2, 3] };"#);
+    assert_eq!(repl.eval("v.len()").unwrap().output, "side effect\n3");
+    // The statement is not re-run
+    assert_eq!(repl.eval("v.len()").unwrap().output, "3");
+
+    repl.insert("#[derive(Debug)] struct A(i32);");
+    repl.insert("let mut map = std::collections::HashMap::new();");
+    repl.insert("map.insert(1, A(v[0]));");
+    assert_eq!(repl.eval("map.len()").unwrap().output, "1");
+    // Consuming a value in the evaluated expression doesn't drop it
+    assert_eq!(
+        repl.eval("map.into_values().next()").unwrap().output,
+        "Some(A(1))"
+    );
+    assert_eq!(repl.eval("map.len()").unwrap().output, "1");
+
+    // Bindings that borrow are re-run
+    repl.insert("let first = &v[0];");
+    assert_eq!(repl.eval("*first + 1").unwrap().output, "2");
+
+    // Removing a statement restarts the state
+    repl.pop();
+    assert_eq!(repl.eval("v.len()").unwrap().output, "side effect\n3");
+
+    // Build errors and panics are mapped to the input like normal evaluations
+    let result = repl.eval("v.len() + missing").unwrap();
+    let span = &result.diagnostics[0].spans[0];
+    assert_eq!(
+        (span.origin, span.line_start, span.column_start),
+        (Origin::Input, 1, 11)
+    );
+    let result = repl
+        .eval("{ println!(\"out\"); eprintln!(\"err\"); panic!(\"boom\") }")
+        .unwrap();
+    assert_eq!(result.output, "out");
+    let failure = result.runtime_failure.unwrap();
+    assert!(failure.starts_with("err\n"));
+    assert!(failure.contains("panicked at input, line 1, column 38:\nboom"));
+
+    let mut limits = Limits::default();
+    limits.set("timeout", "1s").unwrap();
+    assert!(repl.set_limits(limits).is_err());
+
+    // The libraries must be built with the same toolchain and profile as the saved values
+    assert!(repl.set_toolchain(ToolChain::Nightly).is_err());
+    let mut profile = Profile::default();
+    profile.set("opt-level", "3").unwrap();
+    assert!(repl.set_profile(profile).is_err());
+    assert!(repl.set_profile(Profile::default()).is_ok());
+}
+
+#[test]
+fn export() {
+    let mut repl = Repl::default();
+    repl.set_main_result(MainResult::Result);
+    repl.insert("let a: u8 = \"4\".parse()?;");
+    repl.insert("println!(\"{a}\");");
+
+    let dir = std::env::temp_dir().join(format!("irust_export_{}", std::process::id()));
+    repl.export(&dir).unwrap();
+    let output = std::process::Command::new("cargo")
+        .arg("run")
+        .arg("-q")
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "4\n");
+}
+
+#[test]
+fn diagnostics() {
+    use irust_repl::diagnostics::{Level, Origin};
+
+    let mut repl = Repl::default();
+    repl.insert("let unused = 1;");
+    repl.insert("let a = 4;");
+    let result = repl.eval("let b: u8 = a;\na + c").unwrap();
+    assert!(!result.status.success());
+
+    let error = &result.diagnostics[0];
+    assert_eq!(error.level, Level::Error);
+    assert_eq!(error.code.as_deref(), Some("E0425"));
+    // The span points to the input, not to the generated main.rs
+    let span = &error.spans[0];
+    assert_eq!(
+        (span.origin, span.line_start, span.column_start),
+        (Origin::Input, 2, 5)
+    );
+    assert_eq!(span.text, ["a + c"]);
+    assert!(result.output.contains("--> input:2:5"));
+    assert!(result.output.contains("2 | a + c"));
+
+    let result = repl.eval_check("let d: u8 = \"\";".into()).unwrap();
+    let span = &result.diagnostics[0].spans[0];
+    assert_eq!(
+        (span.origin, span.line_start, span.column_start),
+        (Origin::Input, 1, 13)
+    );
+
+    // Warnings in previous lines point to the repl line
+    let result = repl.eval_check("let e = a;".into()).unwrap();
+    assert!(result.status.success());
+    let warning = result
+        .diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message == "unused variable: `unused`")
+        .unwrap();
+    assert_eq!(warning.level, Level::Warning);
+    assert_eq!(warning.spans[0].origin, Origin::Repl);
+    assert_eq!(
+        repl.lines().nth(warning.spans[0].line_start).unwrap(),
+        "let unused = 1;"
+    );
+
+    // A span that starts in the repl lines and ends in the input is cut at the end of its first line
+    repl.insert("let f: u8 = String::from(");
+    let result = repl.eval_check("\"f\");".into()).unwrap();
+    let span = &result.diagnostics[0].spans[0];
+    assert_eq!(span.origin, Origin::Repl);
+    assert_eq!(span.line_end, span.line_start);
+    assert_eq!(span.text, ["let f: u8 = String::from("]);
+}
+
+#[test]
+fn apply_suggestions() {
+    use irust_repl::diagnostics::apply_suggestions;
+
+    let mut repl = Repl::default();
+    repl.insert("let v = vec![1];");
+    let input = "let r: &Vec<i32> = v;";
+    let result = repl.eval_check(input.into()).unwrap();
+    assert!(!result.status.success());
+    assert_eq!(
+        apply_suggestions(input, &result.diagnostics).as_deref(),
+        Some("let r: &Vec<i32> = &v;")
+    );
+
+    let input = "let s = String::new();\ns.push('a');";
+    let result = repl.eval_check(input.into()).unwrap();
+    assert_eq!(
+        apply_suggestions(input, &result.diagnostics).as_deref(),
+        Some("let mut s = String::new();\ns.push('a');")
+    );
+
+    let input = "HashMap::<u8, u8>::new()";
+    let result = repl.eval(input).unwrap();
+    assert_eq!(
+        apply_suggestions(input, &result.diagnostics).as_deref(),
+        Some("use std::collections::HashMap;\nHashMap::<u8, u8>::new()")
+    );
+}
+
+#[test]
+fn dependencies() {
+    let dir = std::env::temp_dir().join(format!("irust_dep_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"dep\"\nversion = \"0.1.0\"\n\n[features]\ndefault = [\"a\"]\na = []\nb = []",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("src/lib.rs"),
+        "pub fn b() -> bool { cfg!(feature = \"b\") }",
+    )
+    .unwrap();
+
+    let mut repl = Repl::default();
+    assert_eq!(
+        repl.cargo
+            .add_path_dependency(&dir, ToolChain::Default)
+            .unwrap(),
+        "dep"
+    );
+    let deps = repl.cargo.dependencies(ToolChain::Default).unwrap();
+    assert_eq!(deps[0].name, "dep");
+    assert_eq!(deps[0].version.as_deref(), Some("0.1.0"));
+    assert_eq!(deps[0].features, ["a", "default"]);
+    assert_eq!(deps[0].available_features, ["a", "b", "default"]);
+
+    repl.cargo
+        .set_dependency_features(
+            "dep",
+            &[("b", true), ("default", false)],
+            ToolChain::Default,
+        )
+        .unwrap();
+    assert_eq!(
+        repl.cargo.dependencies(ToolChain::Default).unwrap()[0].features,
+        ["b"]
+    );
+    assert_eq!(repl.eval("dep::b()").unwrap().output, "true");
+
+    // The manifest is restored if the dependency can't be resolved
+    assert!(repl
+        .cargo
+        .set_dependency_version("dep", "2", ToolChain::Default)
+        .is_err());
+    assert_eq!(
+        repl.cargo.dependencies(ToolChain::Default).unwrap()[0].features,
+        ["b"]
+    );
+    // Nothing is removed if one of the names is not a dependency
+    assert!(repl
+        .cargo
+        .remove_dependencies(&["dep", "unknown"], ToolChain::Default)
+        .is_err());
+    assert_eq!(
+        repl.cargo.dependencies(ToolChain::Default).unwrap().len(),
+        1
+    );
+
+    repl.cargo
+        .remove_dependencies(&["dep"], ToolChain::Default)
+        .unwrap();
+    assert!(repl
+        .cargo
+        .dependencies(ToolChain::Default)
+        .unwrap()
+        .is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn toolchain_file() {
+    use irust_repl::ToolChain;
+
+    let dir = std::env::temp_dir().join(format!("irust_toolchain_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(
+        dir.join("rust-toolchain.toml"),
+        "[toolchain]\nchannel = \"1.75.0\"\ncomponents = [\"clippy\"]",
+    )
+    .unwrap();
+
+    let (toolchain, path) = ToolChain::from_toolchain_file(&dir.join("src")).unwrap();
+    assert_eq!(toolchain, ToolChain::Custom("1.75.0".into()));
+    assert_eq!(path, dir.join("rust-toolchain.toml"));
+    assert_eq!("nightly".parse::<ToolChain>().unwrap(), ToolChain::Nightly);
+    assert_eq!(
+        "+nightly-2024-01-01".parse::<ToolChain>().unwrap(),
+        ToolChain::Custom("nightly-2024-01-01".into())
+    );
+
+    // The closest toolchain file is used even without a channel
+    std::fs::write(
+        dir.join("src").join("rust-toolchain.toml"),
+        "[toolchain]\ncomponents = [\"clippy\"]",
+    )
+    .unwrap();
+    assert_eq!(ToolChain::from_toolchain_file(&dir.join("src")), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn edition() {
+    use irust_repl::{diagnostics::Origin, Edition};
+
+    let mut repl = Repl::default();
+    repl.insert("let gen = 1;");
+    let errors = repl.set_edition(Edition::E2024).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].spans[0].origin, Origin::Repl);
+    assert_eq!(errors[0].spans[0].text, ["let gen = 1;"]);
+
+    assert!(repl.set_edition(Edition::E2021).unwrap().is_empty());
+    assert_eq!(repl.eval("gen").unwrap().output, "1");
+}
+
+#[test]
+fn profile() {
+    use irust_repl::Profile;
+
+    let mut repl = Repl::default();
+    let mut profile = Profile::default();
+    profile.set("overflow-checks", "false").unwrap();
+    profile.set("rustflags", "--cfg irust_test").unwrap();
+    assert!(profile.set("panic", "maybe").is_err());
+    repl.set_profile(profile).unwrap();
+
+    assert_eq!(
+        repl.eval("std::hint::black_box(255u8) + 1").unwrap().output,
+        "0"
+    );
+    assert_eq!(repl.eval("cfg!(irust_test)").unwrap().output, "true");
+}
+
+#[test]
+fn compile_mode() {
+    use irust_repl::{CompileMode, ToolChain};
+
+    let cranelift = "cranelift".parse::<CompileMode>().unwrap();
+    assert_eq!(cranelift.to_string(), "Cranelift");
+    assert_eq!("miri".parse::<CompileMode>().unwrap().to_string(), "Miri");
+    assert!(CompileMode::Release
+        .check_available(&ToolChain::Stable)
+        .is_ok());
+    // The cranelift backend is only distributed with nightly toolchains
+    let err = cranelift
+        .check_available(&ToolChain::Stable)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("rustc-codegen-cranelift-preview"));
+}
+
+#[test]
+fn map_rendered() {
+    use irust_repl::{diagnostics, DEFAULT_EVALUATOR};
+
+    let mut repl = Repl::default();
+    repl.insert("let a = 4;");
+    let input = "let p = &a as *const i32;\nunsafe { *p.add(1) }";
+    let source_map = repl.source_map(&DEFAULT_EVALUATOR[0], input);
+    let main_line = repl
+        .eval_in_tmp_repl(
+            format!("{}{input}{}", DEFAULT_EVALUATOR[0], DEFAULT_EVALUATOR[1]),
+            |repl| {
+                let main = std::fs::read_to_string(&repl.cargo.paths.main_file)?;
+                Ok(main
+                    .lines()
+                    .position(|line| line.contains("*p.add(1)"))
+                    .unwrap()
+                    + 1)
+            },
+        )
+        .unwrap();
+
+    // A Miri report, the gutter line is colored like rustc does
+    let report = format!(
+        "error: Undefined Behavior: out-of-bounds pointer use
+ --> src/main.rs:{main_line}:10
+  |
+\x1b[1m\x1b[38;5;12m{main_line}\x1b[0m \x1b[1m\x1b[38;5;12m|\x1b[0m unsafe {{ *p.add(1) }}
+  = note: inside `main` at src/main.rs:{main_line}:10: {main_line}:19
+  = note: inside `std::rt::lang_start` at src/main.rs:1:1"
+    );
+    let mapped = diagnostics::map_rendered(&report, &source_map);
+    let lines: Vec<&str> = mapped.lines().collect();
+    assert_eq!(lines[1], " --> input:2:10");
+    assert_eq!(
+        lines[3],
+        format!(
+            "\x1b[1m\x1b[38;5;12m{:<width$}\x1b[0m \x1b[1m\x1b[38;5;12m|\x1b[0m unsafe {{ *p.add(1) }}",
+            2,
+            width = main_line.to_string().len()
+        )
+    );
+    assert_eq!(lines[4], "  = note: inside `main` at input:2:10: 2:19");
+    // Lines added by IRust are kept as is
+    assert_eq!(
+        lines[5],
+        "  = note: inside `std::rt::lang_start` at src/main.rs:1:1"
+    );
+}
+
+#[test]
+fn tests() {
+    use irust_repl::test_runner::{is_test, TestOutcome};
+
+    let mut repl = Repl::default();
+    repl.insert("fn add(a: i32, b: i32) -> i32 { a + b }");
+    repl.insert("let unused = 1;");
+    assert!(is_test("#[test]\nfn t() {}"));
+    assert!(!is_test("fn t() {}"));
+    repl.add_test("#[test]\nfn adds() { println!(\"adding\"); assert_eq!(add(1, 2), 3); }")
+        .unwrap();
+    repl.add_test("#[test]\nfn fails() { assert_eq!(add(1, 1), 3); }")
+        .unwrap();
+
+    let report = repl.test(None, false, None).unwrap();
+    assert!(report.build_failure.is_none());
+    let outcome = |name: &str| {
+        report
+            .tests
+            .iter()
+            .find(|test| test.name == name)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(outcome("adds").outcome, TestOutcome::Passed);
+    assert_eq!(outcome("adds").output, "adding");
+    assert_eq!(outcome("fails").outcome, TestOutcome::Failed);
+    assert!(outcome("fails").output.contains("left: 2"));
+
+    // A test with the same name replaces the previous one
+    repl.add_test("#[test]\nfn fails() { assert_eq!(add(1, 1), 2); }")
+        .unwrap();
+    let report = repl.test(Some("fail"), false, None).unwrap();
+    assert_eq!(report.tests.len(), 1);
+    assert_eq!(report.tests[0].outcome, TestOutcome::Passed);
+    assert_eq!(repl.tests().len(), 2);
+}
+
+#[test]
+fn emit() {
+    use irust_repl::emit::{demangle, EmitKind};
+
+    assert_eq!(
+        demangle("_ZN46_$LT$repl..Foo$u20$as$u20$core..fmt..Debug$GT$3fmt17h0123456789abcdefE")
+            .unwrap(),
+        "<repl::Foo as core::fmt::Debug>::fmt"
+    );
+    assert_eq!(
+        demangle("_RNvNtCsgEmfK2I1SDS_4core9panicking5panic").unwrap(),
+        "core::panicking::panic"
+    );
+
+    let mut repl = Repl::default();
+    repl.insert("fn add(a: i32, b: i32) -> i32 { a + b }");
+    repl.insert("struct Foo;");
+    repl.insert("impl Foo { fn double(&self, a: i32) -> i32 { a * 2 } }");
+    repl.insert("let unused = 1;");
+
+    let mir = repl
+        .emit(EmitKind::Mir, "add", false, ToolChain::Default)
+        .unwrap();
+    assert!(mir.starts_with("fn add(_1: i32, _2: i32) -> i32 {"));
+    assert!(mir.trim_end().ends_with('}'));
+
+    let asm = repl
+        .emit(EmitKind::Asm, "Foo::double", true, ToolChain::Default)
+        .unwrap();
+    assert!(asm.starts_with("Foo::double:"));
+    let ir = repl
+        .emit(EmitKind::LlvmIr, "add", true, ToolChain::Default)
+        .unwrap();
+    assert!(ir.starts_with("define ") && ir.contains("@add("));
+    assert!(!ir.contains("!dbg"));
+
+    assert!(repl
+        .emit(EmitKind::Asm, "missing", false, ToolChain::Default)
+        .is_err());
+}
+
+#[test]
+fn type_name() {
+    use irust_repl::type_name::{short_type_name, split_typed_output, TYPED_EVALUATOR};
+
+    assert_eq!(
+        short_type_name("alloc::vec::Vec<core::option::Option<&dyn core::any::Any>>"),
+        "Vec<Option<&dyn Any>>"
+    );
+    assert_eq!(short_type_name("repl::main::{{closure}}"), "{{closure}}");
+
+    let mut repl = Repl::default();
+    repl.insert("struct NotDebug;");
+    repl.insert("let v = vec![1u8];");
+    // The expression is not run
+    let result = repl
+        .type_of("(NotDebug, v, std::fs::remove_dir(\"/\"))")
+        .unwrap();
+    assert!(result.status.success());
+    assert_eq!(result.output, "(NotDebug, Vec<u8>, Result<(), Error>)");
+    // The output of the previous statements is not part of the type
+    repl.insert("println!(\"hi\");");
+    assert_eq!(repl.type_of("v[0]").unwrap().output, "u8");
+    repl.pop();
+    let result = repl.type_of("undefined").unwrap();
+    assert!(!result.status.success());
+
+    let result = repl
+        .eval_with_configuration(EvalConfig {
+            input: "println!(\"hello\"); v.iter().map(|x| x * 2).collect::<Vec<_>>()",
+            interactive_function: None,
+            color: false,
+            evaluator: &*TYPED_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+            terminal: None,
+        })
+        .unwrap();
+    let (value, type_name) = split_typed_output(&result.output).unwrap();
+    assert_eq!(value, "hello\n[2]");
+    assert_eq!(type_name, "Vec<u8>");
+}
+
+#[test]
+fn runtime_failure() {
+    let mut repl = Repl::default();
+    repl.insert("fn boom(v: &[i32]) -> i32 { v[3] }");
+
+    let result = repl.eval("println!(\"before\"); boom(&[1])").unwrap();
+    assert!(!result.status.success());
+    assert_eq!(result.output, "before");
+    let failure = result.runtime_failure.unwrap();
+    assert!(failure.contains("panicked at repl line 1, column 29:"));
+    assert!(failure.contains("index out of bounds"));
+    assert!(failure.contains("   0: boom\n             at repl line 1, column 29"));
+    assert!(failure.contains("   1: main\n             at input, line 1, column 21"));
+    assert!(!failure.contains("irust_host_repl"));
+    assert!(!failure.contains("core::"));
+    // The backtrace is printed by a panic hook, the program environment is unchanged
+    if std::env::var_os("RUST_BACKTRACE").is_none() {
+        assert_eq!(
+            repl.eval("std::env::var_os(\"RUST_BACKTRACE\")")
+                .unwrap()
+                .output,
+            "None"
+        );
+    }
+
+    let result = repl.eval("std::process::exit(3)").unwrap();
+    assert_eq!(
+        result.runtime_failure.unwrap(),
+        "process exited with code 3"
+    );
+
+    #[cfg(unix)]
+    {
+        let result = repl
+            .eval("unsafe { std::ptr::null_mut::<i32>().write_volatile(1) }")
+            .unwrap();
+        assert!(result
+            .runtime_failure
+            .unwrap()
+            .contains("killed by signal 11 (SIGSEGV)"));
+    }
+}
+
+#[test]
+fn limits() {
+    let mut repl = Repl::default();
+
+    let result = repl.eval("vec![1u8; 4 << 20].len()").unwrap();
+    assert_eq!(result.output, "4194304");
+    let usage = result.usage.unwrap();
+    assert!(!usage.timed_out);
+    #[cfg(unix)]
+    assert!(usage.peak_rss.unwrap() >= 4 << 20);
+
+    let mut limits = Limits::default();
+    limits.set("timeout", "500ms").unwrap();
+    assert!(limits.set("timeout", "soon").is_err());
+    assert!(limits.set("timeout", "0").is_err());
+    assert!(limits.set("memory", "18446744073709551615G").is_err());
+    assert!(limits.set("memory", "17592186044416").is_err());
+    assert!(limits.set("stack", "1M").is_err());
+    repl.set_limits(limits).unwrap();
+    let result = repl.eval("loop {}").unwrap();
+    assert!(result.usage.unwrap().timed_out);
+    assert!(result
+        .runtime_failure
+        .unwrap()
+        .ends_with("timeout exceeded: the program ran for more than 500ms"));
+
+    // The processes started by the program are killed too, they would keep its output open
+    #[cfg(unix)]
+    {
+        let start = std::time::Instant::now();
+        let result = repl
+            .eval(r#"{ std::process::Command::new("sleep").arg("30").spawn().unwrap(); loop {} }"#)
+            .unwrap();
+        assert!(result.usage.unwrap().timed_out);
+        assert!(start.elapsed() < std::time::Duration::from_secs(20));
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut limits = Limits::default();
+        limits.set("memory", "256M").unwrap();
+        limits.set("cpu", "1s").unwrap();
+        repl.set_limits(limits).unwrap();
+        let result = repl.eval("vec![1u8; 1 << 30].len()").unwrap();
+        assert!(result
+            .runtime_failure
+            .unwrap()
+            .ends_with("memory limit exceeded: 256 MiB"));
+        let result = repl.eval("loop {}").unwrap();
+        assert!(result
+            .runtime_failure
+            .unwrap()
+            .contains("CPU time limit exceeded: 1s"));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn sandbox() {
+    let mut repl = Repl::default();
+    if let Err(e) = repl.set_sandbox(true) {
+        // Unprivileged user namespaces can be disabled
+        eprintln!("skipping the sandbox test: {e}");
+        return;
+    }
+    assert!(repl.set_persistent(true).is_err());
+
+    // The scratch directory is writable, reading works
+    repl.insert("let scratch = std::env::temp_dir().join(\"file\");");
+    repl.insert("std::fs::write(&scratch, \"hello\").unwrap();");
+    assert_eq!(
+        repl.eval("std::fs::read_to_string(&scratch).unwrap()")
+            .unwrap()
+            .output,
+        "\"hello\""
+    );
+    repl.reset().unwrap();
+    assert!(repl.is_sandboxed());
+
+    let outside = repl.cargo.paths.irust_dir.join("outside");
+    let result = repl
+        .eval(format!("std::fs::write({outside:?}, \"x\").unwrap()"))
+        .unwrap();
+    assert!(result
+        .runtime_failure
+        .unwrap()
+        .contains("sandbox violation: the filesystem is read-only"));
+    assert!(!outside.exists());
+
+    let result = repl
+        .eval("std::net::TcpStream::connect(\"127.0.0.1:80\").unwrap()")
+        .unwrap();
+    assert!(result
+        .runtime_failure
+        .unwrap()
+        .ends_with("sandbox violation: the network is disabled"));
+
+    let result = repl
+        .eval("unsafe { libc_unshare(0) }; extern \"C\" { #[link_name = \"unshare\"] fn libc_unshare(flags: i32) -> i32; }")
+        .unwrap();
+    assert!(result
+        .runtime_failure
+        .unwrap()
+        .ends_with("sandbox violation: the program used a forbidden system call"));
+
+    // Processes can be started, but not in new namespaces
+    assert_eq!(
+        repl.eval("std::process::Command::new(\"true\").status().unwrap().success()")
+            .unwrap()
+            .output,
+        "true"
+    );
+    #[cfg(target_arch = "x86_64")]
+    {
+        // clone(CLONE_NEWUSER | SIGCHLD)
+        let result = repl
+            .eval("unsafe { libc_syscall(56, 0x1000_0000 | 17, 0, 0, 0, 0) }; extern \"C\" { #[link_name = \"syscall\"] fn libc_syscall(n: i64, ...) -> i64; }")
+            .unwrap();
+        assert!(result
+            .runtime_failure
+            .unwrap()
+            .ends_with("sandbox violation: the program used a forbidden system call"));
+    }
+
+    // The other processes can't be seen or signaled
+    let result = repl
+        .eval(format!("{{ extern \"C\" {{ #[link_name = \"kill\"] fn libc_kill(pid: i32, signal: i32) -> i32; }} unsafe {{ (libc_kill({}, 0), libc_kill(-1, 0)) }} }}", std::process::id()))
+        .unwrap();
+    assert_eq!(result.output, "(-1, -1)");
+
+    // The unix sockets of the host can't be reached
+    let socket =
+        std::path::Path::new("/tmp").join(format!("irust_sandbox_{}.sock", std::process::id()));
+    let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+    let result = repl
+        .eval(format!(
+            "std::os::unix::net::UnixStream::connect({socket:?}).is_err()"
+        ))
+        .unwrap();
+    assert_eq!(result.output, "true");
+    std::fs::remove_file(&socket).unwrap();
+
+    // The program and its parent outside of the PID namespace are killed by the timeout
+    let mut limits = Limits::default();
+    limits.set("timeout", "500ms").unwrap();
+    repl.set_limits(limits).unwrap();
+    let start = std::time::Instant::now();
+    assert!(repl.eval("loop {}").unwrap().usage.unwrap().timed_out);
+    assert!(start.elapsed() < std::time::Duration::from_secs(20));
+    repl.set_limits(Limits::default()).unwrap();
+
+    // Only the allowed variables are passed to the program
+    if std::env::var_os("CARGO_MANIFEST_DIR").is_some() {
+        assert_eq!(
+            repl.eval("std::env::var_os(\"CARGO_MANIFEST_DIR\")")
+                .unwrap()
+                .output,
+            "None"
+        );
+    }
+
+    // The tests run in the sandbox too
+    repl.add_test(format!(
+        "#[test]\nfn read_only() {{ assert!(std::fs::write({outside:?}, \"x\").is_err()); }}"
+    ))
+    .unwrap();
+    let report = repl.test(None, false, None).unwrap();
+    assert_eq!(
+        report.tests[0].outcome,
+        irust_repl::test_runner::TestOutcome::Passed
+    );
+    assert!(!outside.exists());
+
+    repl.set_sandbox(false).unwrap();
+    repl.eval(format!("std::fs::write({outside:?}, \"x\").unwrap()"))
+        .unwrap();
+    assert!(outside.exists());
+}
+
+#[test]
+#[cfg(unix)]
+fn streaming() {
+    /// Answers the prompts of the program and records what it shows
+    struct MockTerminal {
+        shown: Vec<u8>,
+        answers: Vec<&'static str>,
+    }
+    impl Terminal for MockTerminal {
+        fn write(&mut self, output: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+            self.shown.extend_from_slice(output);
+            Ok(())
+        }
+        fn read(&mut self) -> Result<Option<TerminalInput>, Box<dyn std::error::Error>> {
+            if !self.shown.ends_with(b"? ") || self.answers.is_empty() {
+                return Ok(None);
+            }
+            let answer = self.answers.remove(0);
+            Ok(Some(TerminalInput::Bytes(answer.as_bytes().to_vec())))
+        }
+        fn size(&self) -> (u16, u16) {
+            (80, 24)
+        }
+    }
+
+    let mut repl = Repl::default();
+    let mut terminal = MockTerminal {
+        shown: vec![],
+        answers: vec!["bob\r"],
+    };
+    let result = repl
+        .eval_with_configuration(EvalConfig {
+            input: "{ use std::io::Write; print!(\"name? \"); std::io::stdout().flush().unwrap(); let mut name = String::new(); std::io::stdin().read_line(&mut name).unwrap(); (std::io::IsTerminal::is_terminal(&std::io::stdin()), name.trim().len()) }",
+            interactive_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+            terminal: Some(&mut terminal),
+        })
+        .unwrap();
+    // The input is echoed by the pty
+    assert_eq!(
+        String::from_utf8_lossy(&terminal.shown),
+        "name? bob\r\n(true, 3)\r\n"
+    );
+    assert_eq!(result.output, "name? bob\n(true, 3)");
+
+    // ctrl-c interrupts the program
+    let mut terminal = MockTerminal {
+        shown: vec![],
+        answers: vec!["\x03"],
+    };
+    let result = repl
+        .eval_with_configuration(EvalConfig {
+            input: "{ use std::io::Write; print!(\"stop? \"); std::io::stdout().flush().unwrap(); loop {} }",
+            interactive_function: None,
+            color: false,
+            evaluator: &*DEFAULT_EVALUATOR,
+            compile_mode: CompileMode::Debug,
+            terminal: Some(&mut terminal),
+        })
+        .unwrap();
+    assert!(result
+        .runtime_failure
+        .unwrap()
+        .contains("killed by signal 2 (SIGINT)"));
+}
+
+#[test]
+fn jobs() {
+    use irust_repl::jobs::JobStatus;
+
+    let mut repl = Repl::default();
+    repl.insert("let a = 4;");
+    let mut job = repl
+        .spawn_job(
+            "{ std::thread::sleep(std::time::Duration::from_millis(500)); a * 2 }",
+            false,
+            &*DEFAULT_EVALUATOR,
+            CompileMode::Debug,
+        )
+        .unwrap();
+    assert_eq!(job.status(), JobStatus::Running);
+
+    // The repl keeps working, the job uses the repl as it was when it was launched
+    repl.insert("let a = 5;");
+    assert_eq!(repl.eval("a").unwrap().output, "5");
+    while !job.poll() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!job.poll());
+    assert_eq!(job.status(), JobStatus::Done);
+    assert!(job.elapsed() >= std::time::Duration::from_millis(500));
+    assert_eq!(job.wait().unwrap().output, "8");
+
+    let mut job = repl
+        .spawn_job("a.foo()", false, &*DEFAULT_EVALUATOR, CompileMode::Debug)
+        .unwrap();
+    while !job.poll() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(job.status(), JobStatus::Failed);
+    assert!(!job.wait().unwrap().diagnostics.is_empty());
+
+    let mut job = repl
+        .spawn_job("loop {}", false, &*DEFAULT_EVALUATOR, CompileMode::Debug)
+        .unwrap();
+    job.kill();
+    assert_eq!(job.status(), JobStatus::Killed);
+    while !job.poll() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(job.wait().is_err());
+
+    // The build is stopped too
+    let job = repl
+        .spawn_job(
+            "{ #[allow(long_running_const_eval)] const N: u64 = { let mut i = 0; while i < u64::MAX { i += 1; } i }; N }",
+            false,
+            &*DEFAULT_EVALUATOR,
+            CompileMode::Debug,
+        )
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let start = std::time::Instant::now();
+    job.kill();
+    assert!(job.wait().is_err());
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
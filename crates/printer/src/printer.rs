@@ -1,6 +1,7 @@
 use crossterm::{style::Color, terminal::ClearType};
 use std::ops::Range;
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::{buffer::Buffer, Result};
 
@@ -63,6 +64,82 @@ impl PrintQueue {
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
+
+    /// Split the queue at its new lines (including the ones inside strings)
+    pub fn lines(self) -> Vec<PrintQueue> {
+        let mut lines = vec![PrintQueue::default()];
+        let push_str = |lines: &mut Vec<PrintQueue>, string: &str, color: Color| {
+            for (idx, part) in string.split('\n').enumerate() {
+                if idx > 0 {
+                    lines.push(PrintQueue::default());
+                }
+                if !part.is_empty() {
+                    let line = lines.last_mut().expect("lines is never empty");
+                    line.push(PrinterItem::String(part.to_string(), color));
+                }
+            }
+        };
+        for item in self {
+            match item {
+                PrinterItem::NewLine | PrinterItem::Char('\n', _) => {
+                    lines.push(PrintQueue::default())
+                }
+                PrinterItem::String(string, color) => push_str(&mut lines, &string, color),
+                PrinterItem::Str(string, color) => push_str(&mut lines, string, color),
+                PrinterItem::RcString(string, range, color) => {
+                    push_str(&mut lines, &string[range], color)
+                }
+                item => lines.last_mut().expect("lines is never empty").push(item),
+            }
+        }
+        lines
+    }
+
+    /// Keep the first items of this line that fit in `width` columns
+    fn truncate(self, width: usize) -> PrintQueue {
+        let mut truncated = PrintQueue::default();
+        let mut line_width = 0;
+        for c in self
+            .items
+            .into_iter()
+            .flat_map(|item| -> Vec<(char, Color)> {
+                match item {
+                    PrinterItem::Char(c, color) => vec![(c, color)],
+                    PrinterItem::String(string, color) => {
+                        string.chars().map(|c| (c, color)).collect()
+                    }
+                    PrinterItem::Str(string, color) => string.chars().map(|c| (c, color)).collect(),
+                    PrinterItem::RcString(string, range, color) => {
+                        string[range].chars().map(|c| (c, color)).collect()
+                    }
+                    PrinterItem::NewLine => vec![],
+                }
+            })
+        {
+            line_width += c.0.width().unwrap_or(0);
+            if line_width > width {
+                break;
+            }
+            truncated.push(PrinterItem::Char(c.0, c.1));
+        }
+        truncated
+    }
+
+    /// Number of terminal rows needed to print this line
+    fn rows(&self, width: usize) -> usize {
+        let line_width: usize = self
+            .items
+            .iter()
+            .map(|item| match item {
+                PrinterItem::Char(c, _) => c.width().unwrap_or(0),
+                PrinterItem::String(string, _) => string.width(),
+                PrinterItem::Str(string, _) => string.width(),
+                PrinterItem::RcString(string, range, _) => string[range.clone()].width(),
+                PrinterItem::NewLine => 0,
+            })
+            .sum();
+        line_width.div_ceil(width.max(1)).max(1)
+    }
 }
 
 impl Iterator for PrintQueue {
@@ -193,6 +270,13 @@ impl<W: std::io::Write> Printer<W> {
     }
 
     pub fn print_output(&mut self, printer: PrintQueue) -> Result<()> {
+        self.print_items(printer)?;
+        self.readjust_cursor_pos()?;
+
+        Ok(())
+    }
+
+    fn print_items(&mut self, printer: PrintQueue) -> Result<()> {
         for item in printer {
             match item {
                 PrinterItem::Char(c, color) => {
@@ -213,6 +297,77 @@ impl<W: std::io::Write> Printer<W> {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Print `queue` under the input without moving the cursor
+    ///
+    /// Lines are truncated to the terminal width, the text is cleared the next time the input is printed
+    pub fn print_below_input(&mut self, queue: PrintQueue, buffer: &Buffer) -> Result<()> {
+        let input_rows = self.cursor.input_last_pos(buffer).1 + 1 - self.cursor.starting_pos().1;
+        let max_lines = self.cursor.height().saturating_sub(input_rows + 1);
+        let lines: Vec<PrintQueue> = queue.lines().into_iter().take(max_lines).collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let height_overflow = self
+            .cursor
+            .screen_height_overflow_by_new_lines(buffer, lines.len());
+        if height_overflow != 0 {
+            self.scroll_up(height_overflow);
+        }
+
+        self.cursor.hide();
+        self.cursor.save_position();
+        let input_last_row = self.cursor.input_last_pos(buffer).1;
+        self.cursor.goto(0, input_last_row + 1);
+        self.writer.raw.clear(ClearType::FromCursorDown)?;
+        let width = self.cursor.width().saturating_sub(1);
+        for (idx, line) in lines.into_iter().enumerate() {
+            self.cursor.goto(0, input_last_row + 1 + idx);
+            self.print_items(line.truncate(width))?;
+        }
+        self.writer.raw.reset_color()?;
+        self.cursor.restore_position();
+        self.cursor.show();
+
+        Ok(())
+    }
+
+    /// Like `print_output` but waits for a key press each time the terminal is filled
+    ///
+    /// `space` shows the next page, `enter` the next line and `q`, `esc` or `ctrl-c` stop the output
+    pub fn print_output_paged(&mut self, printer: PrintQueue) -> Result<()> {
+        const MORE: &str = "-- More -- (space: next page, enter: next line, q: quit)";
+        let page_rows = self.cursor.height().saturating_sub(1).max(1);
+        let width = self.cursor.width();
+
+        let mut rows = 0;
+        let mut available_rows = page_rows;
+        let mut lines = printer.lines().into_iter().peekable();
+        while let Some(line) = lines.next() {
+            let line_rows = line.rows(width);
+            rows += line_rows;
+            if rows > available_rows {
+                self.writer.raw.set_fg(Color::Yellow)?;
+                self.writer.raw.write(MORE)?;
+                let page = wait_for_page_key()?;
+                self.writer.raw.write("\r")?;
+                self.writer.raw.clear(ClearType::CurrentLine)?;
+                match page {
+                    Page::Next => available_rows = page_rows,
+                    Page::Line => available_rows = line_rows,
+                    Page::Quit => break,
+                }
+                rows = line_rows;
+            }
+
+            self.print_items(line)?;
+            if lines.peek().is_some() {
+                self.writer.raw.write("\r\n")?;
+            }
+        }
         self.readjust_cursor_pos()?;
 
         Ok(())
@@ -385,6 +540,35 @@ impl<W: std::io::Write> Printer<W> {
     }
 }
 
+enum Page {
+    Next,
+    Line,
+    Quit,
+}
+
+fn wait_for_page_key() -> Result<Page> {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+    loop {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press | KeyEventKind::Repeat,
+            ..
+        }) = crossterm::event::read()?
+        {
+            match code {
+                KeyCode::Char(' ') | KeyCode::PageDown => return Ok(Page::Next),
+                KeyCode::Enter | KeyCode::Down => return Ok(Page::Line),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(Page::Quit)
+                }
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Page::Quit),
+                _ => (),
+            }
+        }
+    }
+}
+
 pub fn default_process_fn(buffer: &Buffer) -> PrintQueue {
     let mut queue = PrintQueue::default();
     for c in buffer.iter() {
@@ -152,3 +152,28 @@ fn move_to_and_modify_start(printer: &mut Printer<impl Write>, x: usize, y: usiz
     printer.cursor.pos.starting_pos.1 = y;
     printer.cursor.goto_start();
 }
+
+#[test]
+fn print_queue_lines() {
+    use super::{PrintQueue, PrinterItem};
+
+    let mut queue = PrintQueue::default();
+    queue.push(PrinterItem::Str("a\nb", Color::Red));
+    queue.push(PrinterItem::Char('c', Color::Red));
+    queue.add_new_line(1);
+    queue.push(PrinterItem::String("d".into(), Color::Red));
+
+    let lines: Vec<String> = queue
+        .lines()
+        .into_iter()
+        .map(|line| {
+            line.map(|item| match item {
+                PrinterItem::String(s, _) => s,
+                PrinterItem::Char(c, _) => c.to_string(),
+                _ => unreachable!(),
+            })
+            .collect()
+        })
+        .collect();
+    assert_eq!(lines, ["a", "bc", "d"]);
+}